@@ -0,0 +1,81 @@
+use crate::{
+    algos::{opt::OptPass, pass_manager::Analysis},
+    ir::{Algo, Function, Instruction, Module, Operation, ValueId},
+};
+
+/// `lower_par_moves` as a pass.
+pub struct LowerParMoves;
+
+impl OptPass for LowerParMoves {
+    fn name(&self) -> &'static str {
+        "lower-par-moves"
+    }
+
+    fn requires(&self) -> &'static [Algo] {
+        &[Algo::PhiLowering]
+    }
+
+    fn preserves_analysis(&self, analysis: Analysis) -> bool {
+        analysis != Analysis::Liveness
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        lower_par_moves(module);
+    }
+}
+
+/// Replaces the par-moves of every block with `Operation::Copy`s at the end
+/// of its instructions, ordered so that no copy overwrites a value a later
+/// one still reads. Cycles like `a <- b, b <- a` are broken by first saving
+/// one of the values in a new temporary.
+///
+/// A value may then be defined by the copies of several blocks, so the
+/// module is no longer in SSA form.
+pub fn lower_par_moves(module: &mut Module) {
+    module.algos_run.push(Algo::LowerParMoves);
+    for func in module.functions.iter_mut() {
+        for block in 0..func.blocks.len() {
+            let moves = std::mem::take(&mut func.blocks[block].par_moves);
+            let copies = sequentialize(func, moves);
+            func.blocks[block]
+                .instructions
+                .extend(copies.into_iter().map(|(dst, src)| Instruction {
+                    yielded: Some(dst),
+                    operation: Operation::Copy(src),
+                }));
+        }
+        func.rebuild_children();
+    }
+}
+
+/// Orders the parallel `moves` into sequential `(dst, src)` copies, adding
+/// temporaries to `func` where they form cycles.
+pub fn sequentialize(
+    func: &mut Function,
+    moves: Vec<(ValueId, ValueId)>,
+) -> Vec<(ValueId, ValueId)> {
+    let mut pending: Vec<(ValueId, ValueId)> =
+        moves.into_iter().filter(|(dst, src)| dst != src).collect();
+    let mut copies = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        // a move whose target no other pending move reads can go first
+        let ready = (0..pending.len()).find(|i| {
+            let dst = pending[*i].0;
+            pending.iter().all(|(_, src)| *src != dst)
+        });
+        if let Some(i) = ready {
+            copies.push(pending.remove(i));
+            continue;
+        }
+        // only cycles are left: save a target before it is overwritten
+        let dst = pending[0].0;
+        let tmp = func.push_value(func.values[dst.0].ty.clone());
+        copies.push((tmp, dst));
+        for (_, src) in pending.iter_mut() {
+            if *src == dst {
+                *src = tmp;
+            }
+        }
+    }
+    copies
+}
@@ -1,65 +1,273 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::ir::{
-    Algo, BasicBlock, BlockId, Function, Instruction, Module, Operation, ValueId, VariableId,
+use crate::{
+    algos::{delete_instructions::delete, opt::OptPass, remove_critical_edges::split_entry},
+    analysis::dominators::{DominanceFrontiers, DominatorTree},
+    ir::{Algo, BlockId, Function, Instruction, Module, Operation, Type, ValueId, VariableId},
 };
 
-use super::delete_instructions::delete;
-
 /// Gets rid of all `load` and `store` instructions and replaces them with values and Φ functions.
 ///
-/// notes on impl:
-/// if no def in the current blk:
-///  - recurse into preds and find a def for each one
-///  - all bbs have a last definition
+/// notes on impl (Cytron et al.):
+///  - Φs for a variable are placed in the iterated dominance frontier of the
+///    blocks assigning to it (`Variable::bbs_assign_to`), pruned to the
+///    blocks where the variable is live-in
+///  - loads are then renamed to the reaching definition by walking the
+///    dominator tree, and every Φ gets exactly one operand per pred, in
+///    `preds` order
+///  - reading a variable before any store reads zero
+///
+/// Functions already in SSA form (e.g. built with
+/// `ModuleBuilder::read_variable`) have no loads to rename and keep their Φs.
 pub fn lower(module: &mut Module) {
-    module.algos_run.push(Algo::PhiLowering);
-    // Function: V: BB: I -> should delete instruction
-    let mut func_dels: Vec<Vec<Vec<bool>>> = Vec::new();
-    for (func_id, func) in (module.functions).iter_mut().enumerate() {
-        func_dels.push(Vec::new());
-        for (block_id, block) in func.blocks.clone().iter().enumerate() {
-            func_dels[func_id].push(Vec::new());
-            let mut last_var_defs = HashMap::new();
-            for (instr_id, instr) in block.instructions.iter().enumerate() {
-                func_dels[func_id][block_id].push(false);
-                match instr.operation {
-                    Operation::LoadVar(var) => {
-                        if let Some(val) = last_var_defs.get(&var) {
-                            // mark for deletion
-                            func_dels[func_id][block_id][instr_id] = true;
-                            // replace uses of the load with the val
-                            func.replace_children_with(instr.yielded.unwrap(), *val);
-                        } else {
-                            // backtrack through preds for a def
-                            let defs = find_defs_in_preds(
-                                func,
-                                BlockId(block_id),
-                                var,
-                                BlockId(block_id),
-                                instr.yielded.unwrap(),
-                            );
-                            func.replace_instruction(
-                                BlockId(block_id),
-                                instr_id,
-                                Instruction {
-                                    yielded: instr.yielded,
-                                    operation: Operation::Phi(defs),
-                                },
-                            )
-                        }
-                    }
-                    Operation::StoreVar(to, val) => {
-                        last_var_defs.insert(to, val);
-                        func_dels[func_id][block_id][instr_id] = true;
+    if !module.algos_run.contains(&Algo::SsaConstruction) {
+        module.algos_run.push(Algo::SsaConstruction);
+    }
+    for func in module.functions.iter_mut() {
+        if func.blocks.is_empty() {
+            continue;
+        }
+        split_entry(func);
+        lower_function(func);
+        func.rebuild_children();
+    }
+}
+
+/// `lower` as a pass.
+pub struct LowerToSsa;
+
+impl OptPass for LowerToSsa {
+    fn name(&self) -> &'static str {
+        "ssa"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        lower(module);
+    }
+}
+
+fn lower_function(func: &mut Function) {
+    let doms = DominatorTree::compute(func);
+    let frontiers = DominanceFrontiers::compute(func, &doms);
+    let live_in = variable_live_in(func);
+
+    // placement: (block, variable) -> Φ value, in variable order per block
+    let mut phis: Vec<Vec<(VariableId, ValueId)>> = vec![Vec::new(); func.blocks.len()];
+    for var in 0..func.variables.len() {
+        let mut defs: Vec<BlockId> = func.variables[var].bbs_assign_to.iter().copied().collect();
+        defs.sort_by_key(|b| b.0);
+        for block in frontiers.iterated(defs) {
+            if live_in[block.0].contains(&VariableId(var)) {
+                let val = func.push_value(func.variables[var].ty.clone());
+                func.values[val.0].owner = block;
+                phis[block.0].push((VariableId(var), val));
+            }
+        }
+    }
+    let mut phi_operands: Vec<Vec<Vec<Option<ValueId>>>> = phis
+        .iter()
+        .enumerate()
+        .map(|(block, phis)| vec![vec![None; func.blocks[block].preds.len()]; phis.len()])
+        .collect();
+
+    let mut renamer = Renamer {
+        stacks: vec![Vec::new(); func.variables.len()],
+        subst: HashMap::new(),
+        zeros: HashMap::new(),
+    };
+
+    // walk the dominator tree, then rename unreachable blocks on their own
+    let mut roots = vec![BlockId(0)];
+    roots.extend(
+        (0..func.blocks.len())
+            .map(BlockId)
+            .filter(|b| !doms.is_reachable(*b)),
+    );
+    for root in roots {
+        let mut walk = vec![(root, false)];
+        let mut pushed: Vec<Vec<VariableId>> = vec![Vec::new(); func.blocks.len()];
+        while let Some((block, done)) = walk.pop() {
+            if done {
+                for var in pushed[block.0].drain(..) {
+                    renamer.stacks[var.0].pop();
+                }
+                continue;
+            }
+            pushed[block.0] = renamer.rename_block(func, block, &phis, &mut phi_operands);
+            walk.push((block, true));
+            if doms.is_reachable(block) {
+                for child in doms.children(block).iter().rev() {
+                    walk.push((*child, false));
+                }
+            }
+        }
+    }
+
+    // materialise the Φs and the zeros for undefined reads
+    for (block, block_phis) in phis.into_iter().enumerate() {
+        let mut instrs: Vec<Instruction> = block_phis
+            .into_iter()
+            .zip(phi_operands[block].iter())
+            .map(|((var, val), operands)| Instruction {
+                yielded: Some(val),
+                operation: Operation::Phi(
+                    operands
+                        .iter()
+                        .map(|op| op.unwrap_or_else(|| renamer.zero(func, var)))
+                        .collect(),
+                ),
+            })
+            .collect();
+        instrs.append(&mut func.blocks[block].instructions);
+        func.blocks[block].instructions = instrs;
+    }
+    let mut zeros: Vec<(Type, ValueId)> = renamer.zeros.into_iter().collect();
+    zeros.sort_by_key(|(_, val)| val.0);
+    let mut instrs: Vec<Instruction> = zeros
+        .into_iter()
+        .map(|(_, val)| Instruction {
+            yielded: Some(val),
+            operation: Operation::Integer(0),
+        })
+        .collect();
+    instrs.append(&mut func.blocks[0].instructions);
+    func.blocks[0].instructions = instrs;
+
+    // point every use of a load at its reaching definition
+    let subst = renamer.subst;
+    let resolve = |mut val: ValueId| {
+        while let Some(next) = subst.get(&val) {
+            val = *next;
+        }
+        val
+    };
+    for block in func.blocks.iter_mut() {
+        for instr in block.instructions.iter_mut() {
+            for operand in instr.operation.operands_mut() {
+                *operand = resolve(*operand);
+            }
+        }
+        if let Some(operand) = block.terminator.operand_mut() {
+            *operand = resolve(*operand);
+        }
+    }
+}
+
+struct Renamer {
+    /// Reaching definition of every variable along the current dominator path
+    stacks: Vec<Vec<ValueId>>,
+    /// Loads and the value they are replaced by
+    subst: HashMap<ValueId, ValueId>,
+    zeros: HashMap<Type, ValueId>,
+}
+
+impl Renamer {
+    fn current(&mut self, func: &mut Function, var: VariableId) -> ValueId {
+        match self.stacks[var.0].last() {
+            Some(val) => *val,
+            None => self.zero(func, var),
+        }
+    }
+
+    fn zero(&mut self, func: &mut Function, var: VariableId) -> ValueId {
+        let ty = func.variables[var.0].ty.clone();
+        *self
+            .zeros
+            .entry(ty.clone())
+            .or_insert_with(|| func.push_value(ty))
+    }
+
+    /// Renames the loads and stores of one block, fills in the Φ operands of
+    /// its successors and returns the variables it pushed a definition for.
+    fn rename_block(
+        &mut self,
+        func: &mut Function,
+        block: BlockId,
+        phis: &[Vec<(VariableId, ValueId)>],
+        phi_operands: &mut [Vec<Vec<Option<ValueId>>>],
+    ) -> Vec<VariableId> {
+        let mut pushed = Vec::new();
+        for (var, val) in phis[block.0].iter() {
+            self.stacks[var.0].push(*val);
+            pushed.push(*var);
+        }
+
+        let instrs = std::mem::take(&mut func.blocks[block.0].instructions);
+        let mut kept = Vec::with_capacity(instrs.len());
+        for instr in instrs {
+            match instr.operation {
+                Operation::LoadVar(var) => {
+                    let def = self.current(func, var);
+                    if let Some(val) = instr.yielded {
+                        self.subst.insert(val, def);
                     }
-                    _ => {}
                 }
+                Operation::StoreVar(var, val) => {
+                    self.stacks[var.0].push(val);
+                    pushed.push(var);
+                }
+                _ => kept.push(instr),
+            }
+        }
+        func.blocks[block.0].instructions = kept;
+
+        for succ in func.blocks[block.0].terminator.successors() {
+            let positions: Vec<usize> = func.blocks[succ.0]
+                .preds
+                .iter()
+                .enumerate()
+                .filter(|(_, pred)| **pred == block)
+                .map(|(i, _)| i)
+                .collect();
+            for (phi, (var, _)) in phis[succ.0].iter().enumerate() {
+                let def = self.current(func, *var);
+                for pos in positions.iter() {
+                    phi_operands[succ.0][phi][*pos] = Some(def);
+                }
+            }
+        }
+        pushed
+    }
+}
+
+/// Computes which variables are live on entry to each block, i.e. may be
+/// loaded before being stored to on some path from the block's start.
+fn variable_live_in(func: &Function) -> Vec<HashSet<VariableId>> {
+    let count = func.blocks.len();
+    let mut upward_exposed = vec![HashSet::new(); count];
+    let mut killed = vec![HashSet::new(); count];
+    for (id, block) in func.blocks.iter().enumerate() {
+        for instr in block.instructions.iter() {
+            match instr.operation {
+                Operation::LoadVar(var) if !killed[id].contains(&var) => {
+                    upward_exposed[id].insert(var);
+                }
+                Operation::StoreVar(var, _) => {
+                    killed[id].insert(var);
+                }
+                _ => (),
             }
         }
     }
-    delete(module, func_dels);
-    remove_singleelem_phis(module);
+
+    let mut live_in = upward_exposed.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for id in (0..count).rev() {
+            for succ in func.blocks[id].terminator.successors() {
+                let flowing: Vec<VariableId> = live_in[succ.0]
+                    .iter()
+                    .filter(|var| !killed[id].contains(*var))
+                    .copied()
+                    .collect();
+                for var in flowing {
+                    changed |= live_in[id].insert(var);
+                }
+            }
+        }
+    }
+    live_in
 }
 
 pub fn remove_singleelem_phis(module: &mut Module) {
@@ -82,37 +290,3 @@ pub fn remove_singleelem_phis(module: &mut Module) {
     }
     delete(module, dels);
 }
-
-fn find_last_def(bb: &BasicBlock, var: VariableId) -> Option<ValueId> {
-    let rev_instrs = bb.instructions.iter().rev();
-    for instr in rev_instrs {
-        if let Operation::StoreVar(to, val) = instr.operation {
-            if to == var {
-                return Some(val);
-            }
-        }
-    }
-    None
-}
-
-fn find_defs_in_preds(
-    func: &Function,
-    block_id: BlockId,
-    var: VariableId,
-    stop_at: BlockId,
-    to: ValueId,
-) -> Vec<ValueId> {
-    let mut defs = Vec::new();
-    for pred in func.blocks[block_id.0].preds.clone() {
-        if pred == stop_at {
-            defs.push(to);
-            return defs;
-        }
-        if let Some(val) = find_last_def(&func.blocks[pred.0], var) {
-            defs.push(val);
-        } else {
-            defs.append(&mut find_defs_in_preds(func, pred, var, stop_at, to));
-        }
-    }
-    defs
-}
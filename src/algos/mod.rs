@@ -1,5 +1,9 @@
 pub mod delete_instructions;
+pub mod lower_par_moves;
 pub mod lower_to_ssa;
 pub mod opt;
+pub mod out_of_ssa;
+pub mod pass_manager;
 pub mod phi_lowering;
 pub mod remove_critical_edges;
+pub mod verify;
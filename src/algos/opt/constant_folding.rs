@@ -1,7 +1,87 @@
+use std::collections::HashMap;
+
 use super::OptPass;
+use crate::{
+    analysis::dominators::reverse_postorder,
+    ir::{Algo, Function, Module, Operation, Terminator, ValueId},
+};
 
+/// Folds `BinOp`s, `UnOp`s and `Cast`s over constant operands, and `Select`s
+/// picking a constant, into `Integer`s, following the width and signedness
+/// of the operands' type as `BinOp::eval` does, and turns branches on
+/// constant conditions into jumps.
+///
+/// Division and remainder by a constant zero are left alone, so the fault
+/// still happens at runtime. Blocks which become unreachable are kept, with
+/// the untaken edge removed from their preds and Φs.
+#[derive(Default)]
 pub struct ConstantFolding {}
 
 impl OptPass for ConstantFolding {
-    fn run(&mut self, module: &mut crate::ir::Module) {}
+    fn name(&self) -> &'static str {
+        "constfold"
+    }
+
+    fn forbids(&self) -> &'static [Algo] {
+        &[Algo::PhiRemoval]
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        for func in module.functions.iter_mut() {
+            fold_function(func);
+        }
+    }
+}
+
+fn fold_function(func: &mut Function) {
+    let mut consts: HashMap<ValueId, i64> = HashMap::new();
+    let mut changed = false;
+
+    // in reverse postorder every operand but a Φ's is seen before its use
+    for block in reverse_postorder(func) {
+        for pos in 0..func.blocks[block.0].instructions.len() {
+            let instr = &func.blocks[block.0].instructions[pos];
+            let Some(dst) = instr.yielded else { continue };
+            let folded = match &instr.operation {
+                Operation::Integer(val) => {
+                    consts.insert(dst, func.values[dst.0].ty.wrap(*val));
+                    continue;
+                }
+                Operation::BinOp(op, lhs, rhs) => match (consts.get(lhs), consts.get(rhs)) {
+                    (Some(l), Some(r)) => op.eval(*l, *r, &func.values[lhs.0].ty),
+                    _ => None,
+                },
+                Operation::UnOp(op, val) => {
+                    consts.get(val).map(|c| op.eval(*c, &func.values[val.0].ty))
+                }
+                Operation::Cast(op, val, ty) => consts
+                    .get(val)
+                    .map(|c| op.eval(*c, &func.values[val.0].ty, ty)),
+                Operation::Select(cond, a, b) => consts
+                    .get(cond)
+                    .and_then(|c| consts.get(if *c != 0 { a } else { b }))
+                    .copied(),
+                _ => None,
+            };
+            if let Some(val) = folded {
+                let val = func.values[dst.0].ty.wrap(val);
+                func.blocks[block.0].instructions[pos].operation = Operation::Integer(val);
+                consts.insert(dst, val);
+                changed = true;
+            }
+        }
+
+        if let Terminator::Branch(cond, t, f) = func.blocks[block.0].terminator {
+            if let Some(cond) = consts.get(&cond) {
+                let (taken, untaken) = if *cond != 0 { (t, f) } else { (f, t) };
+                func.blocks[block.0].terminator = Terminator::Jump(taken);
+                func.remove_edge(block, untaken);
+                changed = true;
+            }
+        }
+    }
+
+    if changed {
+        func.rebuild_children();
+    }
 }
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use super::FunctionPass;
+use crate::{
+    algos::pass_manager::FunctionAnalyses,
+    ir::{BlockId, Function, Operation, ValueId},
+};
+
+/// Aggressive dead code elimination.
+///
+/// Everything is assumed dead until it is reached from a root: a `Call`,
+/// `StoreVar` or `Store`, which may have side effects, or the operand of a
+/// terminator. Values reached from there stay alive along with the operands
+/// of their definitions, so unused `Φ` cycles are removed as well as plain
+/// unused values. Par-moves into dead values are dropped too.
+///
+/// Blocks unreachable from the entry are deleted first, and the remaining
+/// ones are renumbered.
+#[derive(Default)]
+pub struct DeadCodeElimination {}
+
+impl FunctionPass for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run_on_function(&mut self, func: &mut Function, analyses: &mut FunctionAnalyses) -> bool {
+        let doms = analyses.dominators(func);
+        let keep: Vec<bool> = (0..func.blocks.len())
+            .map(|b| doms.is_reachable(BlockId(b)))
+            .collect();
+        let removed_blocks = keep.contains(&false);
+        if removed_blocks {
+            func.retain_blocks(&keep);
+        }
+        let removed_values = sweep(func);
+        if removed_values {
+            func.rebuild_children();
+        }
+        removed_blocks || removed_values
+    }
+}
+
+/// Removes the instructions and par-moves not reachable from a root and
+/// returns whether there were any.
+fn sweep(func: &mut Function) -> bool {
+    // the operands of every definition of each value, as out of SSA a value
+    // may be the target of several instructions and par-moves
+    let mut defs: HashMap<ValueId, Vec<ValueId>> = HashMap::new();
+    let mut live = vec![false; func.values.len()];
+    let mut worklist = Vec::new();
+
+    for block in func.blocks.iter() {
+        for instr in block.instructions.iter() {
+            if let Some(val) = instr.yielded {
+                defs.entry(val)
+                    .or_default()
+                    .extend(instr.operation.operands());
+            }
+            if has_side_effects(&instr.operation) {
+                worklist.extend(instr.operation.operands());
+                if let Some(val) = instr.yielded {
+                    worklist.push(val);
+                }
+            }
+        }
+        for (dst, src) in block.par_moves.iter() {
+            defs.entry(*dst).or_default().push(*src);
+        }
+        worklist.extend(block.terminator.operand());
+    }
+
+    while let Some(val) = worklist.pop() {
+        if live[val.0] {
+            continue;
+        }
+        live[val.0] = true;
+        if let Some(operands) = defs.get(&val) {
+            worklist.extend(operands.iter().copied());
+        }
+    }
+
+    let mut changed = false;
+    for block in func.blocks.iter_mut() {
+        let before = block.instructions.len() + block.par_moves.len();
+        block.instructions.retain(|instr| {
+            has_side_effects(&instr.operation) || instr.yielded.is_some_and(|val| live[val.0])
+        });
+        block.par_moves.retain(|(dst, _)| live[dst.0]);
+        changed |= before != block.instructions.len() + block.par_moves.len();
+    }
+    changed
+}
+
+fn has_side_effects(op: &Operation) -> bool {
+    matches!(
+        op,
+        Operation::Call(..) | Operation::StoreVar(..) | Operation::Store(..)
+    )
+}
@@ -0,0 +1,53 @@
+use super::OptPass;
+use crate::{
+    analysis::call_graph::CallGraph,
+    ir::{FunctionId, Linkage, Module, Operation},
+};
+
+/// Deletes the `Linkage::Private` functions which can't be called from any
+/// public or external one, and renumbers the rest in order, fixing up the
+/// `FunctionId`s of every call.
+#[derive(Default)]
+pub struct DeadFunctionElimination {}
+
+impl OptPass for DeadFunctionElimination {
+    fn name(&self) -> &'static str {
+        "dfe"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        let graph = CallGraph::compute(module);
+        let roots: Vec<FunctionId> = module
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|(_, func)| func.linkage != Linkage::Private)
+            .map(|(id, _)| FunctionId(id))
+            .collect();
+        let keep = graph.reachable_from(&roots);
+        if !keep.contains(&false) {
+            return;
+        }
+
+        let mut remap = vec![None; keep.len()];
+        let functions = std::mem::take(&mut module.functions);
+        for (id, mut func) in functions.into_iter().enumerate() {
+            if keep[id] {
+                remap[id] = Some(FunctionId(module.functions.len()));
+                func.id = module.functions.len();
+                module.functions.push(func);
+            }
+        }
+        for func in module.functions.iter_mut() {
+            for instr in func
+                .blocks
+                .iter_mut()
+                .flat_map(|bb| bb.instructions.iter_mut())
+            {
+                if let Operation::Call(callee, _) = &mut instr.operation {
+                    *callee = remap[callee.0].expect("call to a deleted function");
+                }
+            }
+        }
+    }
+}
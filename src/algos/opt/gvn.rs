@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use super::FunctionPass;
+use crate::{
+    algos::pass_manager::{Analysis, FunctionAnalyses},
+    ir::{Algo, BlockId, Function, Operation, Type, ValueId},
+};
+
+/// Dominator-based global value numbering.
+///
+/// Walks the dominator tree with a scoped table of the pure operations seen
+/// so far (`Integer`s and `BinOp`s, keyed by their result type too), and
+/// replaces any recomputation with the dominating value through
+/// `Function::replace_children_with`. Operands of commutative `BinOp`s are
+/// put in a canonical order first, so `add %1 %2` and `add %2 %1` match.
+/// Φs are only merged with identical Φs of the same block.
+#[derive(Default)]
+pub struct GlobalValueNumbering {}
+
+/// An operation, its result type, and for Φs the block they are in.
+type Key = (Operation, Type, Option<BlockId>);
+
+impl FunctionPass for GlobalValueNumbering {
+    fn name(&self) -> &'static str {
+        "gvn"
+    }
+
+    fn forbids(&self) -> &'static [Algo] {
+        &[Algo::PhiRemoval]
+    }
+
+    fn preserves_analysis(&self, analysis: Analysis) -> bool {
+        analysis != Analysis::Liveness
+    }
+
+    fn run_on_function(&mut self, func: &mut Function, analyses: &mut FunctionAnalyses) -> bool {
+        let doms = analyses.dominators(func);
+        let mut table: HashMap<Key, ValueId> = HashMap::new();
+        // keys added by every block on the current dominator path
+        let mut scopes: Vec<Vec<Key>> = vec![Vec::new(); func.blocks.len()];
+        let mut changed = false;
+
+        let mut walk = vec![(BlockId(0), false)];
+        while let Some((block, done)) = walk.pop() {
+            if done {
+                for key in scopes[block.0].drain(..) {
+                    table.remove(&key);
+                }
+                continue;
+            }
+            walk.push((block, true));
+            for child in doms.children(block).into_iter().rev() {
+                walk.push((child, false));
+            }
+
+            let mut pos = 0;
+            while pos < func.blocks[block.0].instructions.len() {
+                let instr = &mut func.blocks[block.0].instructions[pos];
+                let Some(val) = instr.yielded else {
+                    pos += 1;
+                    continue;
+                };
+                if let Operation::BinOp(op, lhs, rhs) = &mut instr.operation {
+                    if op.is_commutative() && rhs.0 < lhs.0 {
+                        std::mem::swap(lhs, rhs);
+                    }
+                }
+                let scope = match instr.operation {
+                    Operation::Integer(_)
+                    | Operation::BinOp(..)
+                    | Operation::Arg(_)
+                    | Operation::GlobalAddr(_)
+                    | Operation::UnOp(..)
+                    | Operation::Cast(..)
+                    | Operation::Select(..) => None,
+                    Operation::Phi(_) => Some(block),
+                    _ => {
+                        pos += 1;
+                        continue;
+                    }
+                };
+                let key = (
+                    instr.operation.clone(),
+                    func.values[val.0].ty.clone(),
+                    scope,
+                );
+                match table.get(&key) {
+                    Some(leader) => {
+                        let leader = *leader;
+                        func.blocks[block.0].instructions.remove(pos);
+                        func.replace_children_with(val, leader);
+                        changed = true;
+                    }
+                    None => {
+                        table.insert(key.clone(), val);
+                        scopes[block.0].push(key);
+                        pos += 1;
+                    }
+                }
+            }
+        }
+        if changed {
+            func.rebuild_children();
+        }
+        changed
+    }
+}
@@ -0,0 +1,259 @@
+use super::OptPass;
+use crate::ir::{
+    Algo, BasicBlock, BlockId, Function, FunctionId, Instruction, Linkage, Module, Operation,
+    Terminator, ValueId, VariableId,
+};
+
+/// The largest callee `Inliner::default()` inlines, as counted by
+/// `inline_cost`.
+pub const DEFAULT_INLINE_THRESHOLD: usize = 40;
+
+/// Inlines calls to functions whose body costs at most `threshold`.
+///
+/// The block of the call is split in two: the part before the call jumps to
+/// a copy of the callee's blocks, whose returns jump to the part after it.
+/// The returned value is merged there with a Φ, or with par-moves in the
+/// returning blocks if Φs were lowered already; a callee with a single
+/// return just has its returned value used in place of the call's.
+///
+/// `Linkage::External` functions are never inlined, as their definition may
+/// be replaced at link time. Neither are recursive calls, whether into the
+/// caller itself or into a function it was inlined from.
+pub struct Inliner {
+    threshold: usize,
+}
+
+impl Inliner {
+    pub fn new(threshold: usize) -> Self {
+        Inliner { threshold }
+    }
+}
+
+impl Default for Inliner {
+    fn default() -> Self {
+        Inliner::new(DEFAULT_INLINE_THRESHOLD)
+    }
+}
+
+impl OptPass for Inliner {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    fn requires(&self) -> &'static [Algo] {
+        &[Algo::SsaConstruction]
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        // callees are always inlined as they were before the pass
+        let callees = module.functions.clone();
+        let phis_lowered = module.algos_run.contains(&Algo::PhiLowering);
+        for caller in module.functions.iter_mut() {
+            inline_calls(caller, &callees, self.threshold, phis_lowered);
+        }
+    }
+}
+
+/// The size of `func`: one per instruction, par-move and terminator.
+pub fn inline_cost(func: &Function) -> usize {
+    func.blocks
+        .iter()
+        .map(|bb| bb.instructions.len() + bb.par_moves.len() + 1)
+        .sum()
+}
+
+fn inline_calls(caller: &mut Function, callees: &[Function], threshold: usize, phis_lowered: bool) {
+    let caller_id = FunctionId(caller.id);
+    // the functions every block was inlined from, to stop at recursion
+    let mut inlined_from: Vec<Vec<FunctionId>> = vec![vec![]; caller.blocks.len()];
+    let mut changed = false;
+
+    let mut block = 0;
+    while block < caller.blocks.len() {
+        let call =
+            caller.blocks[block]
+                .instructions
+                .iter()
+                .position(|instr| match &instr.operation {
+                    Operation::Call(callee, _) => {
+                        let func = &callees[callee.0];
+                        *callee != caller_id
+                            && !inlined_from[block].contains(callee)
+                            && func.linkage != Linkage::External
+                            && !func.blocks.is_empty()
+                            && inline_cost(func) <= threshold
+                    }
+                    _ => false,
+                });
+        let Some(pos) = call else {
+            block += 1;
+            continue;
+        };
+        let Operation::Call(callee, _) = caller.blocks[block].instructions[pos].operation else {
+            unreachable!()
+        };
+
+        let cont = inline_call(
+            caller,
+            BlockId(block),
+            pos,
+            &callees[callee.0],
+            phis_lowered,
+        );
+        let mut history = inlined_from[block].clone();
+        inlined_from.resize(caller.blocks.len(), vec![]);
+        inlined_from[cont.0] = history.clone();
+        history.push(callee);
+        let body = cont.0 - callees[callee.0].blocks.len()..cont.0;
+        inlined_from[body].fill(history);
+        changed = true;
+        block += 1;
+    }
+    if changed {
+        caller.rebuild_children();
+    }
+}
+
+/// Replaces the call at `pos` of `block` with a copy of `callee`'s body and
+/// returns the block the code after the call was moved to.
+fn inline_call(
+    caller: &mut Function,
+    block: BlockId,
+    pos: usize,
+    callee: &Function,
+    phis_lowered: bool,
+) -> BlockId {
+    let value_offset = caller.values.len();
+    let block_offset = caller.blocks.len();
+    let var_offset = caller.variables.len();
+    let cont = BlockId(block_offset + callee.blocks.len());
+    let map_val = |val: ValueId| ValueId(val.0 + value_offset);
+    let map_block = |b: BlockId| BlockId(b.0 + block_offset);
+
+    for val in callee.values.iter() {
+        caller.push_value(val.ty.clone());
+    }
+    for var in callee.variables.iter() {
+        let mut var = var.clone();
+        var.bbs_assign_to = var.bbs_assign_to.iter().map(|b| map_block(*b)).collect();
+        caller.variables.push(var);
+    }
+
+    let Operation::Call(_, args) = &caller.blocks[block.0].instructions[pos].operation else {
+        unreachable!()
+    };
+    let args = args.clone();
+    // the callee's args are replaced by the call's operands
+    let mut arg_values = Vec::new();
+
+    let mut returns = Vec::new();
+    for (id, bb) in callee.blocks.iter().enumerate() {
+        let instructions = bb
+            .instructions
+            .iter()
+            .filter(|instr| match (instr.yielded, &instr.operation) {
+                (Some(val), Operation::Arg(idx)) => {
+                    arg_values.push((map_val(val), args[*idx]));
+                    false
+                }
+                _ => true,
+            })
+            .map(|instr| {
+                let mut operation = instr.operation.clone();
+                for operand in operation.operands_mut() {
+                    *operand = map_val(*operand);
+                }
+                if let Operation::LoadVar(var) | Operation::StoreVar(var, _) = &mut operation {
+                    *var = VariableId(var.0 + var_offset);
+                }
+                Instruction {
+                    yielded: instr.yielded.map(map_val),
+                    operation,
+                }
+            })
+            .collect();
+        let terminator = match bb.terminator {
+            Terminator::Return(val) => {
+                returns.push((map_block(BlockId(id)), map_val(val)));
+                Terminator::Jump(cont)
+            }
+            Terminator::Jump(target) => Terminator::Jump(map_block(target)),
+            Terminator::Branch(cond, t, f) => {
+                Terminator::Branch(map_val(cond), map_block(t), map_block(f))
+            }
+            Terminator::NoTerm => Terminator::NoTerm,
+        };
+        caller.push_block(BasicBlock {
+            instructions,
+            terminator,
+            preds: bb.preds.iter().map(|b| map_block(*b)).collect(),
+            id: block_offset + id,
+            par_moves: bb
+                .par_moves
+                .iter()
+                .map(|(dst, src)| (map_val(*dst), map_val(*src)))
+                .collect(),
+        });
+    }
+    let entry = map_block(BlockId(0));
+    caller.blocks[entry.0].preds.push(block);
+
+    // everything after the call moves to the continuation
+    let bb = &mut caller.blocks[block.0];
+    let mut rest = bb.instructions.split_off(pos);
+    let call = rest.remove(0);
+    let terminator = std::mem::replace(&mut bb.terminator, Terminator::Jump(entry));
+    let par_moves = std::mem::take(&mut bb.par_moves);
+    for succ in terminator.successors() {
+        for pred in caller.blocks[succ.0].preds.iter_mut() {
+            if *pred == block {
+                *pred = cont;
+            }
+        }
+    }
+    for var in caller.variables.iter_mut() {
+        if var.bbs_assign_to.contains(&block) {
+            var.bbs_assign_to.insert(cont);
+        }
+    }
+
+    let mut replace = None;
+    match (call.yielded, &returns[..]) {
+        (Some(dst), [(_, val)]) => replace = Some((dst, *val)),
+        // the callee never returns, so the continuation is unreachable
+        (Some(dst), []) => rest.insert(
+            0,
+            Instruction {
+                yielded: Some(dst),
+                operation: Operation::Integer(0),
+            },
+        ),
+        (Some(dst), _) if phis_lowered => {
+            for (ret, val) in returns.iter() {
+                caller.blocks[ret.0].par_moves.push((dst, *val));
+            }
+        }
+        (Some(dst), _) => rest.insert(
+            0,
+            Instruction {
+                yielded: Some(dst),
+                operation: Operation::Phi(returns.iter().map(|(_, val)| *val).collect()),
+            },
+        ),
+        (None, _) => (),
+    }
+    caller.push_block(BasicBlock {
+        instructions: rest,
+        terminator,
+        preds: returns.iter().map(|(ret, _)| *ret).collect(),
+        id: cont.0,
+        par_moves,
+    });
+    if let Some((dst, val)) = replace {
+        caller.replace_children_with(dst, val);
+    }
+    for (val, arg) in arg_values {
+        caller.replace_children_with(val, arg);
+    }
+    cont
+}
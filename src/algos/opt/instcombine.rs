@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+
+use super::FunctionPass;
+use crate::{
+    algos::pass_manager::{Analysis, FunctionAnalyses},
+    analysis::dominators::reverse_postorder,
+    ir::{Algo, BinOp, BlockId, Function, Instruction, Operation, Type, ValueId},
+};
+
+/// Algebraic simplification and strength reduction of `BinOp`s.
+///
+/// Applies the identities below, with the constant operand of commutative
+/// operations moved to the right first:
+///  - `x + 0`, `x - 0`, `x * 1`, `x / 1`, `x | 0`, `x ^ 0`, `x << 0`,
+///    `x >> 0`, `x & x` and `x | x` are `x`,
+///  - `x * 0`, `x & 0`, `x % 1`, `x - x` and `x ^ x` are `0`,
+///  - `x == x`, `x <= x` and `x >= x` are `1`, `x != x`, `x < x` and
+///    `x > x` are `0`,
+///  - `x * 2^n` becomes `x << n`, and for unsigned `x`, `x / 2^n` becomes
+///    `x >> n` and `x % 2^n` becomes `x & (2^n - 1)`.
+///
+/// An instruction is only replaced by one of its operands if they have the
+/// same type. Whenever an instruction is simplified, its users (found through
+/// `Value::children`) are revisited, until nothing changes.
+#[derive(Default)]
+pub struct InstCombine {}
+
+impl FunctionPass for InstCombine {
+    fn name(&self) -> &'static str {
+        "instcombine"
+    }
+
+    fn forbids(&self) -> &'static [Algo] {
+        &[Algo::PhiRemoval]
+    }
+
+    fn preserves_analysis(&self, analysis: Analysis) -> bool {
+        analysis != Analysis::Liveness
+    }
+
+    fn run_on_function(&mut self, func: &mut Function, _: &mut FunctionAnalyses) -> bool {
+        func.rebuild_children();
+        let mut defs: HashMap<ValueId, (BlockId, usize)> = HashMap::new();
+        let mut consts: HashMap<ValueId, i64> = HashMap::new();
+        let mut worklist = Vec::new();
+        for block in reverse_postorder(func) {
+            index_block(func, block, &mut defs);
+            for instr in func.blocks[block.0].instructions.iter() {
+                match (instr.yielded, &instr.operation) {
+                    (Some(val), Operation::Integer(c)) => {
+                        consts.insert(val, func.values[val.0].ty.wrap(*c));
+                    }
+                    (Some(val), Operation::BinOp(..)) => worklist.push(val),
+                    _ => (),
+                }
+            }
+        }
+        worklist.reverse();
+        let mut queued: HashSet<ValueId> = worklist.iter().copied().collect();
+        let mut removed: HashSet<ValueId> = HashSet::new();
+        let mut changed = false;
+
+        while let Some(dst) = worklist.pop() {
+            queued.remove(&dst);
+            if removed.contains(&dst) {
+                continue;
+            }
+            let Some((block, pos)) = defs.get(&dst).copied() else {
+                continue;
+            };
+            let instr = &mut func.blocks[block.0].instructions[pos];
+            let Operation::BinOp(op, lhs, rhs) = &mut instr.operation else {
+                continue;
+            };
+            if op.is_commutative() && consts.contains_key(lhs) && !consts.contains_key(rhs) {
+                std::mem::swap(lhs, rhs);
+            }
+            let (op, lhs, rhs) = (*op, *lhs, *rhs);
+            let Some(simplified) = simplify(func, &consts, dst, op, lhs, rhs) else {
+                continue;
+            };
+
+            changed = true;
+            let users = func.values[dst.0].children.clone();
+            match simplified {
+                Simplified::Value(val) => {
+                    func.replace_children_with(dst, val);
+                    removed.insert(dst);
+                }
+                Simplified::Const(c) => {
+                    let c = func.values[dst.0].ty.wrap(c);
+                    func.blocks[block.0].instructions[pos].operation = Operation::Integer(c);
+                    consts.insert(dst, c);
+                }
+                Simplified::Op(op, c) => {
+                    let val = func.push_value(func.values[lhs.0].ty.clone());
+                    func.values[val.0].owner = block;
+                    func.values[val.0].children.push(dst);
+                    func.blocks[block.0].instructions.insert(
+                        pos,
+                        Instruction {
+                            yielded: Some(val),
+                            operation: Operation::Integer(c),
+                        },
+                    );
+                    func.blocks[block.0].instructions[pos + 1].operation =
+                        Operation::BinOp(op, lhs, val);
+                    consts.insert(val, c);
+                    index_block(func, block, &mut defs);
+                }
+            }
+            for user in users {
+                if queued.insert(user) {
+                    worklist.push(user);
+                }
+            }
+        }
+
+        for bb in func.blocks.iter_mut() {
+            bb.instructions
+                .retain(|i| i.yielded.is_none_or(|val| !removed.contains(&val)));
+        }
+        if changed {
+            func.rebuild_children();
+        }
+        changed
+    }
+}
+
+/// What a `BinOp` simplifies to.
+enum Simplified {
+    /// One of its operands
+    Value(ValueId),
+    Const(i64),
+    /// The same left operand with another operation and a constant right one
+    Op(BinOp, i64),
+}
+
+fn simplify(
+    func: &Function,
+    consts: &HashMap<ValueId, i64>,
+    dst: ValueId,
+    op: BinOp,
+    lhs: ValueId,
+    rhs: ValueId,
+) -> Option<Simplified> {
+    let ty = &func.values[lhs.0].ty;
+    // comparisons may yield another type than their operands
+    let forward = (*ty == func.values[dst.0].ty).then_some(Simplified::Value(lhs));
+
+    if lhs == rhs {
+        return match op {
+            BinOp::And | BinOp::Or => forward,
+            BinOp::Sub | BinOp::Xor | BinOp::Ne | BinOp::Lt | BinOp::Gt => {
+                Some(Simplified::Const(0))
+            }
+            BinOp::Eq | BinOp::Le | BinOp::Ge => Some(Simplified::Const(1)),
+            _ => None,
+        };
+    }
+
+    let c = *consts.get(&rhs)?;
+    let c = ty.wrap(c);
+    match (op, c) {
+        (BinOp::Add | BinOp::Sub | BinOp::Or | BinOp::Xor | BinOp::Shl | BinOp::Shr, 0) => forward,
+        (BinOp::Mul | BinOp::Div, 1) => forward,
+        (BinOp::Mul | BinOp::And, 0) | (BinOp::Mod, 1) => Some(Simplified::Const(0)),
+        (BinOp::Mul, _) => log2(ty, c).map(|n| Simplified::Op(BinOp::Shl, n)),
+        (BinOp::Div, _) if !ty.is_signed() => log2(ty, c).map(|n| Simplified::Op(BinOp::Shr, n)),
+        (BinOp::Mod, _) if !ty.is_signed() => {
+            log2(ty, c).map(|_| Simplified::Op(BinOp::And, ty.wrap(c - 1)))
+        }
+        _ => None,
+    }
+}
+
+/// `n` if `c` is `2^n` when read as a value of type `ty`.
+fn log2(ty: &Type, c: i64) -> Option<i64> {
+    let bits = if ty.is_signed() {
+        ty.bits() - 1
+    } else {
+        ty.bits()
+    };
+    let mask = if bits >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << bits) - 1
+    };
+    let c = c as u64;
+    (c & mask == c && c.is_power_of_two()).then(|| c.trailing_zeros() as i64)
+}
+
+/// Records where the values defined in `block` are.
+fn index_block(func: &Function, block: BlockId, defs: &mut HashMap<ValueId, (BlockId, usize)>) {
+    for (pos, instr) in func.blocks[block.0].instructions.iter().enumerate() {
+        if let Some(val) = instr.yielded {
+            defs.insert(val, (block, pos));
+        }
+    }
+}
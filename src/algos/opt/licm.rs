@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+
+use super::FunctionPass;
+use crate::{
+    algos::{pass_manager::FunctionAnalyses, remove_critical_edges::split_entry},
+    analysis::loops::{Loop, LoopInfo},
+    ir::{Algo, BasicBlock, BinOp, BlockId, Function, Instruction, Operation, Terminator, ValueId},
+};
+
+/// Loop-invariant code motion.
+///
+/// Gives every loop a preheader, then moves the `Integer`s and `BinOp`s of a
+/// loop whose operands are all defined outside of it to the end of its
+/// preheader, innermost loops first so invariants can keep moving out.
+///
+/// Hoisted instructions run even if the loop body wouldn't have, so a `div`
+/// or `mod` is only hoisted when dividing by a non-zero constant.
+#[derive(Default)]
+pub struct LoopInvariantCodeMotion {}
+
+impl FunctionPass for LoopInvariantCodeMotion {
+    fn name(&self) -> &'static str {
+        "licm"
+    }
+
+    fn forbids(&self) -> &'static [Algo] {
+        &[Algo::PhiRemoval]
+    }
+
+    fn run_on_function(&mut self, func: &mut Function, analyses: &mut FunctionAnalyses) -> bool {
+        let mut changed = false;
+        // a loop headed by the entry can't get a preheader otherwise
+        if !func.blocks[0].preds.is_empty() {
+            split_entry(func);
+            analyses.invalidate(|_| false);
+            changed = true;
+        }
+        loop {
+            let info = LoopInfo::compute(func, analyses.dominators(func));
+            let Some(lp) = info.loops().iter().find(|lp| lp.preheader(func).is_none()) else {
+                break;
+            };
+            insert_preheader(func, lp);
+            analyses.invalidate(|_| false);
+            changed = true;
+        }
+
+        let doms = analyses.dominators(func);
+        let info = LoopInfo::compute(func, doms);
+        let rpo = doms.reverse_postorder().to_vec();
+        let mut loops = info.loops().to_vec();
+        loops.sort_by_key(|lp| std::cmp::Reverse(lp.depth));
+        for lp in loops.iter() {
+            changed |= hoist(func, lp, &rpo);
+        }
+        if changed {
+            func.rebuild_children();
+        }
+        changed
+    }
+}
+
+/// Adds a block which all the edges entering `lp` from outside go through
+/// instead, merging the operands of the header's Φs for those edges into Φs
+/// of the new block. Returns the new block.
+pub fn insert_preheader(func: &mut Function, lp: &Loop) -> BlockId {
+    let header = lp.header;
+    let preheader = BlockId(func.blocks.len());
+    let old_preds = func.blocks[header.0].preds.clone();
+    let outside: Vec<usize> = (0..old_preds.len())
+        .filter(|i| !lp.contains(old_preds[*i]))
+        .collect();
+
+    let mut instructions = Vec::new();
+    for pos in 0..func.blocks[header.0].instructions.len() {
+        let instr = &func.blocks[header.0].instructions[pos];
+        let (Some(phi), Operation::Phi(vals)) = (instr.yielded, &instr.operation) else {
+            break;
+        };
+        let incoming: Vec<ValueId> = outside.iter().map(|i| vals[*i]).collect();
+        let mut kept: Vec<ValueId> = (0..vals.len())
+            .filter(|i| !outside.contains(i))
+            .map(|i| vals[i])
+            .collect();
+        let merged = if incoming.iter().all(|val| *val == incoming[0]) {
+            incoming[0]
+        } else {
+            let merged = func.push_value(func.values[phi.0].ty.clone());
+            instructions.push(Instruction {
+                yielded: Some(merged),
+                operation: Operation::Phi(incoming),
+            });
+            merged
+        };
+        kept.push(merged);
+        func.blocks[header.0].instructions[pos].operation = Operation::Phi(kept);
+    }
+
+    let entries: Vec<BlockId> = outside.iter().map(|i| old_preds[*i]).collect();
+    let mut preds: Vec<BlockId> = old_preds
+        .iter()
+        .filter(|pred| lp.contains(**pred))
+        .copied()
+        .collect();
+    preds.push(preheader);
+    func.blocks[header.0].preds = preds;
+
+    let mut retargeted = HashSet::new();
+    for entry in entries.iter() {
+        if !retargeted.insert(*entry) {
+            continue;
+        }
+        for target in func.blocks[entry.0].terminator.successors_mut() {
+            if *target == header {
+                *target = preheader;
+            }
+        }
+    }
+    func.push_block(BasicBlock {
+        instructions,
+        terminator: Terminator::Jump(header),
+        preds: entries,
+        id: preheader.0,
+        par_moves: vec![],
+    });
+    preheader
+}
+
+/// Moves the invariant instructions of `lp` to its preheader and returns
+/// whether there were any.
+fn hoist(func: &mut Function, lp: &Loop, rpo: &[BlockId]) -> bool {
+    let Some(preheader) = lp.preheader(func) else {
+        return false;
+    };
+    let mut consts: HashMap<ValueId, i64> = HashMap::new();
+    let mut in_loop: HashSet<ValueId> = HashSet::new();
+    for (id, block) in func.blocks.iter().enumerate() {
+        for instr in block.instructions.iter() {
+            if let (Some(val), Operation::Integer(c)) = (instr.yielded, &instr.operation) {
+                consts.insert(val, func.values[val.0].ty.wrap(*c));
+            }
+        }
+        if lp.contains(BlockId(id)) {
+            in_loop.extend(block.instructions.iter().filter_map(|i| i.yielded));
+            in_loop.extend(block.par_moves.iter().map(|(dst, _)| *dst));
+        }
+    }
+
+    let mut hoisted = Vec::new();
+    for block in rpo.iter().filter(|b| lp.contains(**b)) {
+        let mut pos = 0;
+        while pos < func.blocks[block.0].instructions.len() {
+            let instr = &func.blocks[block.0].instructions[pos];
+            let invariant = match instr.operation {
+                Operation::Integer(_) | Operation::Arg(_) | Operation::GlobalAddr(_) => true,
+                Operation::UnOp(..) | Operation::Cast(..) | Operation::Select(..) => instr
+                    .operation
+                    .operands()
+                    .iter()
+                    .all(|val| !in_loop.contains(val)),
+                Operation::BinOp(op, lhs, rhs) => {
+                    !in_loop.contains(&lhs)
+                        && !in_loop.contains(&rhs)
+                        && (!matches!(op, BinOp::Div | BinOp::Mod)
+                            || consts.get(&rhs).is_some_and(|c| *c != 0))
+                }
+                _ => false,
+            };
+            match instr.yielded {
+                Some(val) if invariant => {
+                    in_loop.remove(&val);
+                    hoisted.push(func.blocks[block.0].instructions.remove(pos));
+                }
+                _ => pos += 1,
+            }
+        }
+    }
+    let changed = !hoisted.is_empty();
+    func.blocks[preheader.0].instructions.append(&mut hoisted);
+    changed
+}
@@ -1,7 +1,63 @@
-use crate::ir::Module;
+use crate::{
+    algos::pass_manager::{Analysis, FunctionAnalyses},
+    ir::{Algo, Function, Module},
+};
 
 pub mod constant_folding;
+pub mod dce;
+pub mod dead_functions;
+pub mod gvn;
+pub mod inline;
+pub mod instcombine;
+pub mod licm;
+pub mod sccp;
+pub mod simplify_cfg;
+pub mod tail_recursion;
 
+/// A transform over a whole module.
+///
+/// `requires` lists the `Algo`s which must have run before it and `forbids`
+/// the ones which mustn't (both checked by the `PassManager`), and
+/// `preserves` says which of the ones that have run still hold afterwards,
+/// and which cached analyses stay valid.
 pub trait OptPass {
+    /// The name of the pass in pipeline strings and timing reports.
+    fn name(&self) -> &'static str;
+    fn requires(&self) -> &'static [Algo] {
+        &[]
+    }
+    /// E.g. `Algo::PhiRemoval` for passes which need every value to have a
+    /// single definition.
+    fn forbids(&self) -> &'static [Algo] {
+        &[]
+    }
+    fn preserves(&self, _algo: Algo) -> bool {
+        true
+    }
+    fn preserves_analysis(&self, _analysis: Analysis) -> bool {
+        false
+    }
     fn run(&mut self, module: &mut Module);
 }
+
+/// A transform run on every function with a body in turn, with access to
+/// the analyses cached for it.
+pub trait FunctionPass {
+    /// The name of the pass in pipeline strings and timing reports.
+    fn name(&self) -> &'static str;
+    fn requires(&self) -> &'static [Algo] {
+        &[]
+    }
+    fn forbids(&self) -> &'static [Algo] {
+        &[]
+    }
+    fn preserves(&self, _algo: Algo) -> bool {
+        true
+    }
+    fn preserves_analysis(&self, _analysis: Analysis) -> bool {
+        false
+    }
+    /// Returns whether `func` was changed; the analyses of unchanged
+    /// functions are kept whatever `preserves_analysis` says.
+    fn run_on_function(&mut self, func: &mut Function, analyses: &mut FunctionAnalyses) -> bool;
+}
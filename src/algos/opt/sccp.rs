@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+
+use super::OptPass;
+use crate::ir::{Algo, BlockId, Function, Instruction, Module, Operation, Terminator, ValueId};
+
+/// Sparse conditional constant propagation (Wegman & Zadeck).
+///
+/// Every value starts out as undefined and only moves down the lattice as
+/// the blocks defining and using it are found to be executable, so constants
+/// are propagated through Φs whose other operands come from edges that are
+/// never taken. Afterwards:
+///  - values found to be constant are replaced with `Integer`s (constant Φs
+///    are moved below the remaining Φs of their block),
+///  - branches with one executable edge become jumps,
+///  - blocks which are never executed are deleted.
+///
+/// Only edges are removed, so critical edges stay split if they were.
+#[derive(Default)]
+pub struct Sccp {}
+
+impl OptPass for Sccp {
+    fn name(&self) -> &'static str {
+        "sccp"
+    }
+
+    fn requires(&self) -> &'static [Algo] {
+        &[Algo::SsaConstruction]
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        for func in module.functions.iter_mut() {
+            if func.blocks.is_empty() {
+                continue;
+            }
+            let solution = Solver::solve(func);
+            rewrite(func, solution);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lattice {
+    /// Not known to be defined yet
+    Top,
+    Const(i64),
+    /// Not a constant
+    Bottom,
+}
+
+impl Lattice {
+    fn meet(self, other: Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Top, x) | (x, Lattice::Top) => x,
+            (Lattice::Const(a), Lattice::Const(b)) if a == b => Lattice::Const(a),
+            _ => Lattice::Bottom,
+        }
+    }
+}
+
+/// A use of a value: an instruction, or the terminator if `None`.
+type Use = (BlockId, Option<usize>);
+
+struct Solver<'a> {
+    func: &'a Function,
+    values: Vec<Lattice>,
+    executable: Vec<bool>,
+    edges: HashSet<(BlockId, BlockId)>,
+    flow_worklist: Vec<(BlockId, BlockId)>,
+    ssa_worklist: Vec<ValueId>,
+    uses: Vec<Vec<Use>>,
+}
+
+impl<'a> Solver<'a> {
+    fn solve(func: &'a Function) -> Solution {
+        // values not defined by an instruction (par-move targets) could be
+        // anything
+        let mut values = vec![Lattice::Bottom; func.values.len()];
+        let mut uses: Vec<Vec<Use>> = vec![Vec::new(); func.values.len()];
+        for (id, block) in func.blocks.iter().enumerate() {
+            for (pos, instr) in block.instructions.iter().enumerate() {
+                if let Some(val) = instr.yielded {
+                    values[val.0] = Lattice::Top;
+                }
+                for operand in instr.operation.operands() {
+                    uses[operand.0].push((BlockId(id), Some(pos)));
+                }
+            }
+            if let Some(operand) = block.terminator.operand() {
+                uses[operand.0].push((BlockId(id), None));
+            }
+        }
+
+        let mut solver = Solver {
+            func,
+            values,
+            executable: vec![false; func.blocks.len()],
+            edges: HashSet::new(),
+            flow_worklist: Vec::new(),
+            ssa_worklist: Vec::new(),
+            uses,
+        };
+        solver.visit_block(BlockId(0));
+        loop {
+            if let Some((from, to)) = solver.flow_worklist.pop() {
+                if !solver.edges.insert((from, to)) {
+                    continue;
+                }
+                if solver.executable[to.0] {
+                    // only the Φs can see the new edge
+                    let phis = func.blocks[to.0]
+                        .instructions
+                        .iter()
+                        .take_while(|i| matches!(i.operation, Operation::Phi(_)))
+                        .count();
+                    for pos in 0..phis {
+                        solver.visit_instr(to, pos);
+                    }
+                } else {
+                    solver.visit_block(to);
+                }
+            } else if let Some(val) = solver.ssa_worklist.pop() {
+                for (block, pos) in solver.uses[val.0].clone() {
+                    if !solver.executable[block.0] {
+                        continue;
+                    }
+                    match pos {
+                        Some(pos) => solver.visit_instr(block, pos),
+                        None => solver.visit_terminator(block),
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+        Solution {
+            values: solver.values,
+            executable: solver.executable,
+            edges: solver.edges,
+        }
+    }
+
+    fn visit_block(&mut self, block: BlockId) {
+        self.executable[block.0] = true;
+        for pos in 0..self.func.blocks[block.0].instructions.len() {
+            self.visit_instr(block, pos);
+        }
+        self.visit_terminator(block);
+    }
+
+    fn visit_instr(&mut self, block: BlockId, pos: usize) {
+        let bb = &self.func.blocks[block.0];
+        let instr = &bb.instructions[pos];
+        let Some(dst) = instr.yielded else { return };
+        let ty = &self.func.values[dst.0].ty;
+        let new = match &instr.operation {
+            Operation::Integer(val) => Lattice::Const(ty.wrap(*val)),
+            Operation::BinOp(op, lhs, rhs) => match (self.values[lhs.0], self.values[rhs.0]) {
+                (Lattice::Const(l), Lattice::Const(r)) => {
+                    match op.eval(l, r, &self.func.values[lhs.0].ty) {
+                        Some(res) => Lattice::Const(ty.wrap(res)),
+                        None => Lattice::Bottom,
+                    }
+                }
+                (Lattice::Bottom, _) | (_, Lattice::Bottom) => Lattice::Bottom,
+                _ => Lattice::Top,
+            },
+            Operation::UnOp(op, val) => match self.values[val.0] {
+                Lattice::Const(c) => Lattice::Const(op.eval(c, &self.func.values[val.0].ty)),
+                lattice => lattice,
+            },
+            Operation::Cast(op, val, to) => match self.values[val.0] {
+                Lattice::Const(c) => Lattice::Const(op.eval(c, &self.func.values[val.0].ty, to)),
+                lattice => lattice,
+            },
+            Operation::Select(cond, a, b) => match self.values[cond.0] {
+                Lattice::Top => Lattice::Top,
+                Lattice::Const(0) => self.values[b.0],
+                Lattice::Const(_) => self.values[a.0],
+                Lattice::Bottom => self.values[a.0].meet(self.values[b.0]),
+            },
+            Operation::Phi(vals) => vals
+                .iter()
+                .zip(bb.preds.iter())
+                .filter(|(_, pred)| self.edges.contains(&(**pred, block)))
+                .fold(Lattice::Top, |acc, (val, _)| acc.meet(self.values[val.0])),
+            _ => Lattice::Bottom,
+        };
+        let old = self.values[dst.0];
+        let new = old.meet(new);
+        if new != old {
+            self.values[dst.0] = new;
+            self.ssa_worklist.push(dst);
+        }
+    }
+
+    fn visit_terminator(&mut self, block: BlockId) {
+        match self.func.blocks[block.0].terminator {
+            Terminator::Jump(target) => self.flow_worklist.push((block, target)),
+            Terminator::Branch(cond, t, f) => match self.values[cond.0] {
+                Lattice::Top => (),
+                Lattice::Const(0) => self.flow_worklist.push((block, f)),
+                Lattice::Const(_) => self.flow_worklist.push((block, t)),
+                Lattice::Bottom => {
+                    self.flow_worklist.push((block, t));
+                    self.flow_worklist.push((block, f));
+                }
+            },
+            Terminator::Return(_) | Terminator::NoTerm => (),
+        }
+    }
+}
+
+/// The fixed point the solver reached.
+struct Solution {
+    values: Vec<Lattice>,
+    executable: Vec<bool>,
+    edges: HashSet<(BlockId, BlockId)>,
+}
+
+fn rewrite(func: &mut Function, solution: Solution) {
+    let Solution {
+        values,
+        executable,
+        edges,
+    } = solution;
+
+    for (id, block) in func.blocks.iter_mut().enumerate() {
+        if !executable[id] {
+            continue;
+        }
+        let instrs = std::mem::take(&mut block.instructions);
+        let (phis, rest): (Vec<Instruction>, Vec<Instruction>) = instrs
+            .into_iter()
+            .partition(|i| matches!(i.operation, Operation::Phi(_)));
+        let mut constant_phis = Vec::new();
+        for mut instr in phis {
+            match instr.yielded.map(|val| values[val.0]) {
+                Some(Lattice::Const(c)) => {
+                    instr.operation = Operation::Integer(c);
+                    constant_phis.push(instr);
+                }
+                _ => block.instructions.push(instr),
+            }
+        }
+        block.instructions.append(&mut constant_phis);
+        for mut instr in rest {
+            if let Some(Lattice::Const(c)) = instr.yielded.map(|val| values[val.0]) {
+                instr.operation = Operation::Integer(c);
+            }
+            block.instructions.push(instr);
+        }
+    }
+
+    for (id, live) in executable.iter().enumerate() {
+        let block = BlockId(id);
+        if !live {
+            continue;
+        }
+        if let Terminator::Branch(_, t, f) = func.blocks[id].terminator {
+            let (t_live, f_live) = (edges.contains(&(block, t)), edges.contains(&(block, f)));
+            if t_live != f_live {
+                let (taken, untaken) = if t_live { (t, f) } else { (f, t) };
+                func.blocks[id].terminator = Terminator::Jump(taken);
+                func.remove_edge(block, untaken);
+            }
+        }
+    }
+
+    if executable.contains(&false) {
+        func.retain_blocks(&executable);
+    } else {
+        func.rebuild_children();
+    }
+}
@@ -0,0 +1,199 @@
+use super::FunctionPass;
+use crate::{
+    algos::pass_manager::FunctionAnalyses,
+    analysis::dominators::reverse_postorder,
+    ir::{Algo, BlockId, Function, Operation, Terminator},
+};
+
+/// CFG simplification.
+///
+/// Repeats until nothing changes:
+///  - deletes the blocks unreachable from the entry, renumbering the rest,
+///  - turns branches whose targets are the same into jumps,
+///  - forwards the edges into an empty block which only jumps on to its
+///    target,
+///  - merges a block into its pred if it is the pred's only successor and
+///    the pred is its only pred.
+///
+/// Works on functions with or without Φs and par-moves: Φs of merged blocks
+/// have a single operand and are replaced by it, and a block is never merged
+/// into one with par-moves, as those have to run last. Edges are only
+/// forwarded when that doesn't give a block with Φs the same pred twice.
+///
+/// The empty blocks left by critical edge splitting are removed, so it
+/// doesn't preserve `Algo::CriticalEdgeSplitting`.
+#[derive(Default)]
+pub struct SimplifyCfg {}
+
+impl FunctionPass for SimplifyCfg {
+    fn name(&self) -> &'static str {
+        "simplify-cfg"
+    }
+
+    fn preserves(&self, algo: Algo) -> bool {
+        algo != Algo::CriticalEdgeSplitting
+    }
+
+    fn run_on_function(&mut self, func: &mut Function, _: &mut FunctionAnalyses) -> bool {
+        simplify_cfg(func)
+    }
+}
+
+/// Simplifies the CFG of `func` as `SimplifyCfg` does and returns whether it
+/// changed.
+pub fn simplify_cfg(func: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let mut keep = vec![false; func.blocks.len()];
+        for block in reverse_postorder(func) {
+            keep[block.0] = true;
+        }
+        if keep.contains(&false) {
+            func.retain_blocks(&keep);
+            changed = true;
+        }
+
+        let mut simplified = false;
+        for id in 0..func.blocks.len() {
+            let block = BlockId(id);
+            // blocks emptied by now are deleted on the next round
+            if id != 0 && func.blocks[id].preds.is_empty() {
+                continue;
+            }
+            simplified |= fold_branch(func, block) || thread_jump(func, block);
+            simplified |= merge_into_pred(func, block);
+        }
+        if !simplified {
+            break;
+        }
+        changed = true;
+    }
+    if changed {
+        func.rebuild_children();
+    }
+    changed
+}
+
+/// Turns `br %c, $t, $t` into `jmp $t`, unless the Φs of `$t` disagree on
+/// the two edges.
+fn fold_branch(func: &mut Function, block: BlockId) -> bool {
+    let Terminator::Branch(_, t, f) = func.blocks[block.0].terminator else {
+        return false;
+    };
+    if t != f {
+        return false;
+    }
+    let target = &func.blocks[t.0];
+    let edges: Vec<usize> = (0..target.preds.len())
+        .filter(|i| target.preds[*i] == block)
+        .collect();
+    let agree = target
+        .instructions
+        .iter()
+        .all(|instr| match &instr.operation {
+            Operation::Phi(vals) => edges.iter().all(|i| vals[*i] == vals[edges[0]]),
+            _ => true,
+        });
+    if !agree {
+        return false;
+    }
+    func.blocks[block.0].terminator = Terminator::Jump(t);
+    func.remove_edge(block, t);
+    true
+}
+
+/// Points the preds of an empty block which just jumps on straight at its
+/// target.
+fn thread_jump(func: &mut Function, block: BlockId) -> bool {
+    let bb = &func.blocks[block.0];
+    let Terminator::Jump(target) = bb.terminator else {
+        return false;
+    };
+    if block.0 == 0 || target == block || !bb.instructions.is_empty() || !bb.par_moves.is_empty() {
+        return false;
+    }
+    let preds = bb.preds.clone();
+    let has_phis = func.blocks[target.0]
+        .instructions
+        .iter()
+        .any(|i| matches!(i.operation, Operation::Phi(_)));
+    let duplicate = preds
+        .iter()
+        .enumerate()
+        .any(|(i, pred)| preds[..i].contains(pred) || func.blocks[target.0].preds.contains(pred));
+    if has_phis && duplicate {
+        return false;
+    }
+
+    for pred in preds.iter() {
+        for succ in func.blocks[pred.0].terminator.successors_mut() {
+            if *succ == block {
+                *succ = target;
+            }
+        }
+    }
+    let target_bb = &mut func.blocks[target.0];
+    let idx = target_bb
+        .preds
+        .iter()
+        .position(|pred| *pred == block)
+        .unwrap();
+    target_bb.preds.splice(idx..=idx, preds.iter().copied());
+    for instr in target_bb.instructions.iter_mut() {
+        if let Operation::Phi(vals) = &mut instr.operation {
+            let val = vals[idx];
+            vals.splice(idx..=idx, preds.iter().map(|_| val));
+        }
+    }
+    func.blocks[block.0].preds.clear();
+    true
+}
+
+/// Appends `block` to its only pred if that jumps nowhere else.
+fn merge_into_pred(func: &mut Function, block: BlockId) -> bool {
+    let [pred] = func.blocks[block.0].preds[..] else {
+        return false;
+    };
+    if block.0 == 0
+        || pred == block
+        || func.blocks[pred.0].terminator != Terminator::Jump(block)
+        || !func.blocks[pred.0].par_moves.is_empty()
+    {
+        return false;
+    }
+
+    let mut merged = std::mem::take(&mut func.blocks[block.0].instructions);
+    let mut phis = Vec::new();
+    merged.retain(|instr| match (&instr.operation, instr.yielded) {
+        (Operation::Phi(vals), Some(phi)) => {
+            phis.push((phi, vals[0]));
+            false
+        }
+        _ => true,
+    });
+
+    let bb = &mut func.blocks[block.0];
+    let terminator = std::mem::replace(&mut bb.terminator, Terminator::NoTerm);
+    let mut par_moves = std::mem::take(&mut bb.par_moves);
+    bb.preds.clear();
+    for succ in terminator.successors() {
+        for p in func.blocks[succ.0].preds.iter_mut() {
+            if *p == block {
+                *p = pred;
+            }
+        }
+    }
+    let pred_bb = &mut func.blocks[pred.0];
+    pred_bb.instructions.append(&mut merged);
+    pred_bb.par_moves.append(&mut par_moves);
+    pred_bb.terminator = terminator;
+    for var in func.variables.iter_mut() {
+        if var.bbs_assign_to.remove(&block) {
+            var.bbs_assign_to.insert(pred);
+        }
+    }
+    for (phi, val) in phis {
+        func.replace_children_with(phi, val);
+    }
+    true
+}
@@ -0,0 +1,152 @@
+use super::OptPass;
+use crate::{
+    algos::remove_critical_edges::move_entry_body,
+    ir::{Algo, BlockId, Function, Instruction, Module, Operation, Terminator, ValueId},
+};
+
+/// Turns calls of a function to itself whose result it returns straight
+/// away into jumps back to the start of its body, so the recursion runs as
+/// a loop instead of growing the stack.
+///
+/// The body of the entry block is moved into a new loop header first, as
+/// the entry can't have preds. The args read by the function are merged
+/// there from the entry and the args of every tail call, with Φs, or with
+/// par-moves if Φs were lowered already.
+///
+/// Functions with `Alloca`s are left alone, as a slot passed to the call
+/// must stay apart from the slot of the same `Alloca` in the callee.
+#[derive(Default)]
+pub struct TailRecursionElimination {}
+
+impl OptPass for TailRecursionElimination {
+    fn name(&self) -> &'static str {
+        "tailrec"
+    }
+
+    fn requires(&self) -> &'static [Algo] {
+        &[Algo::SsaConstruction]
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        let phis_lowered = module.algos_run.contains(&Algo::PhiLowering);
+        for func in module.functions.iter_mut() {
+            if !func.blocks.is_empty() && !func.has_allocas() {
+                eliminate_tail_recursion(func, phis_lowered);
+            }
+        }
+    }
+}
+
+fn eliminate_tail_recursion(func: &mut Function, phis_lowered: bool) -> bool {
+    let id = func.id;
+    let mut tail_calls: Vec<BlockId> = (0..func.blocks.len())
+        .filter(|b| {
+            func.blocks[*b].tail_call().is_some_and(
+                |call| matches!(call.operation, Operation::Call(callee, _) if callee.0 == id),
+            )
+        })
+        .map(BlockId)
+        .collect();
+    if tail_calls.is_empty() {
+        return false;
+    }
+
+    let header = move_entry_body(func);
+
+    // a single value per arg read, standing for it on every iteration
+    let mut params: Vec<Option<ValueId>> = vec![None; func.args.len()];
+    let mut duplicates = Vec::new();
+    for bb in func.blocks.iter_mut() {
+        bb.instructions
+            .retain(|instr| match (instr.yielded, &instr.operation) {
+                (Some(val), Operation::Arg(idx)) => {
+                    match params[*idx] {
+                        None => params[*idx] = Some(val),
+                        Some(param) => duplicates.push((val, param)),
+                    }
+                    false
+                }
+                _ => true,
+            });
+    }
+    let mut entry_args = vec![None; func.args.len()];
+    for (idx, param) in params.iter().enumerate() {
+        let Some(param) = param else { continue };
+        let arg = func.push_value(func.values[param.0].ty.clone());
+        func.blocks[0].instructions.push(Instruction {
+            yielded: Some(arg),
+            operation: Operation::Arg(idx),
+        });
+        entry_args[idx] = Some(arg);
+    }
+
+    let mut call_args = Vec::new();
+    for block in tail_calls.iter_mut() {
+        if block.0 == 0 {
+            *block = header;
+        }
+        let bb = &mut func.blocks[block.0];
+        let Some(Instruction {
+            operation: Operation::Call(_, args),
+            ..
+        }) = bb.instructions.pop()
+        else {
+            unreachable!()
+        };
+        bb.terminator = Terminator::Jump(header);
+        call_args.push((*block, args));
+        func.blocks[header.0].preds.push(*block);
+    }
+
+    // Φs of the moved body read the same from a tail call as from the entry
+    let header_bb = &mut func.blocks[header.0];
+    let mut phis = 0;
+    for instr in header_bb.instructions.iter_mut() {
+        let Operation::Phi(vals) = &mut instr.operation else {
+            break;
+        };
+        let from_entry = vals[vals.len() - 1];
+        vals.resize(header_bb.preds.len(), from_entry);
+        phis += 1;
+    }
+
+    // the arg flowing in from `pred`, if it changes on that edge
+    let incoming = |pred: BlockId, idx: usize| {
+        if pred.0 == 0 {
+            return entry_args[idx];
+        }
+        call_args
+            .iter()
+            .find(|(block, _)| *block == pred)
+            .map(|(_, args)| args[idx])
+    };
+    let preds = func.blocks[header.0].preds.clone();
+    for (idx, param) in params.iter().enumerate() {
+        let Some(param) = *param else { continue };
+        if phis_lowered {
+            for pred in preds.iter() {
+                if let Some(val) = incoming(*pred, idx) {
+                    func.blocks[pred.0].par_moves.push((param, val));
+                }
+            }
+        } else {
+            let vals = preds
+                .iter()
+                .map(|pred| incoming(*pred, idx).unwrap_or(param))
+                .collect();
+            func.blocks[header.0].instructions.insert(
+                phis,
+                Instruction {
+                    yielded: Some(param),
+                    operation: Operation::Phi(vals),
+                },
+            );
+            phis += 1;
+        }
+    }
+    for (val, param) in duplicates {
+        func.replace_children_with(val, param);
+    }
+    func.rebuild_children();
+    true
+}
@@ -0,0 +1,273 @@
+use crate::{
+    algos::opt::OptPass,
+    analysis::{dominators::DominatorTree, liveness::Liveness},
+    ir::{Algo, BlockId, Function, Module, Operation, ValueId},
+};
+
+/// How many copies the out-of-SSA translation of one function needed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoalescingStats {
+    pub function: String,
+    /// The Φ operands, each of which `lower_phis` turns into a par-move
+    pub phi_operands: usize,
+    /// The par-moves inserted instead
+    pub copies: usize,
+}
+
+impl CoalescingStats {
+    /// The copies naive Φ lowering would have inserted but coalescing didn't.
+    pub fn eliminated(&self) -> usize {
+        self.phi_operands - self.copies
+    }
+}
+
+/// `out_of_ssa` as a pass, keeping the stats of its last run.
+#[derive(Default)]
+pub struct OutOfSsa {
+    stats: Vec<CoalescingStats>,
+}
+
+impl OutOfSsa {
+    /// The stats of every function with a body, in order.
+    pub fn stats(&self) -> &[CoalescingStats] {
+        &self.stats
+    }
+}
+
+impl OptPass for OutOfSsa {
+    fn name(&self) -> &'static str {
+        "out-of-ssa"
+    }
+
+    fn requires(&self) -> &'static [Algo] {
+        &[Algo::CriticalEdgeSplitting, Algo::SsaConstruction]
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        self.stats = out_of_ssa(module);
+    }
+}
+
+/// Removes the Φs of every function like `lower_phis`, but only inserts the
+/// par-moves which are actually needed.
+///
+/// Every Φ starts a congruence class with its result. Its operands are
+/// merged into the class one by one unless one of their members
+/// interferes: two values interfere if one is defined where the other is
+/// live, unless they are copies of the same value. All the members of a
+/// class are then renamed to a single value, and a par-move is only
+/// inserted for the operands left in another class.
+///
+/// Afterwards, values may be defined several times, so the module is no
+/// longer in SSA form. As with `lower_phis`, critical edges have to be split
+/// first, which `OutOfSsa` requires.
+pub fn out_of_ssa(module: &mut Module) -> Vec<CoalescingStats> {
+    module
+        .algos_run
+        .retain(|algo| *algo != Algo::SsaConstruction);
+    module.algos_run.push(Algo::PhiLowering);
+    module.algos_run.push(Algo::PhiRemoval);
+    module
+        .functions
+        .iter_mut()
+        .filter(|func| !func.blocks.is_empty())
+        .map(coalesce)
+        .collect()
+}
+
+fn coalesce(func: &mut Function) -> CoalescingStats {
+    let interference = Interference::new(func);
+
+    let mut phis = Vec::new();
+    for (id, block) in func.blocks.iter().enumerate() {
+        for instr in block.instructions.iter() {
+            if let (Some(phi), Operation::Phi(vals)) = (instr.yielded, &instr.operation) {
+                phis.push((BlockId(id), phi, vals.clone()));
+            }
+        }
+    }
+
+    // union-find over the values, with the members of every class at its root
+    let mut parent: Vec<usize> = (0..func.values.len()).collect();
+    let mut members: Vec<Vec<ValueId>> = (0..func.values.len())
+        .map(|val| vec![ValueId(val)])
+        .collect();
+    fn find(parent: &mut [usize], val: usize) -> usize {
+        let mut root = val;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        parent[val] = root;
+        root
+    }
+    for (_, phi, vals) in phis.iter() {
+        for val in vals.iter() {
+            let class = find(&mut parent, phi.0);
+            let other = find(&mut parent, val.0);
+            if class == other || interference.classes_interfere(&members[class], &members[other]) {
+                continue;
+            }
+            let (root, child) = (class.min(other), class.max(other));
+            parent[child] = root;
+            let moved = std::mem::take(&mut members[child]);
+            members[root].extend(moved);
+        }
+    }
+
+    // rename every member to the root of its class
+    let mut rename = |val: &mut ValueId| *val = ValueId(find(&mut parent, val.0));
+    for block in func.blocks.iter_mut() {
+        for instr in block.instructions.iter_mut() {
+            instr.yielded.iter_mut().for_each(&mut rename);
+            instr
+                .operation
+                .operands_mut()
+                .into_iter()
+                .for_each(&mut rename);
+        }
+        block
+            .terminator
+            .operand_mut()
+            .into_iter()
+            .for_each(&mut rename);
+    }
+
+    let mut stats = CoalescingStats {
+        function: func.name.clone(),
+        phi_operands: 0,
+        copies: 0,
+    };
+    for id in 0..func.blocks.len() {
+        let block = &mut func.blocks[id];
+        let preds = block.preds.clone();
+        let mut moves = Vec::new();
+        block
+            .instructions
+            .retain(|instr| match (instr.yielded, &instr.operation) {
+                (Some(phi), Operation::Phi(vals)) => {
+                    moves.extend(
+                        vals.iter()
+                            .zip(preds.iter())
+                            .map(|(val, pred)| (*pred, phi, *val)),
+                    );
+                    false
+                }
+                _ => true,
+            });
+        stats.phi_operands += moves.len();
+        for (pred, dst, src) in moves {
+            if dst != src {
+                func.blocks[pred.0].par_moves.push((dst, src));
+                stats.copies += 1;
+            }
+        }
+    }
+    func.rebuild_children();
+    stats
+}
+
+/// Whether the live ranges of two SSA values intersect.
+struct Interference {
+    doms: DominatorTree,
+    liveness: Liveness,
+    /// The block and position of the instruction defining every value
+    defs: Vec<Option<(BlockId, usize)>>,
+    /// The value every value is a copy of, following `Operation::Copy`s
+    copy_of: Vec<ValueId>,
+    /// The number of Φs at the start of every block
+    phis: Vec<usize>,
+    /// The instruction operands and terminator operand of every block, by
+    /// position, leaving out Φs as they read their operands in the preds
+    uses: Vec<Vec<(usize, ValueId)>>,
+}
+
+impl Interference {
+    fn new(func: &Function) -> Interference {
+        let mut defs = vec![None; func.values.len()];
+        let mut copy_of: Vec<ValueId> = (0..func.values.len()).map(ValueId).collect();
+        let mut uses = Vec::with_capacity(func.blocks.len());
+        let mut phis = vec![0; func.blocks.len()];
+        for (id, block) in func.blocks.iter().enumerate() {
+            let mut block_uses = Vec::new();
+            for (pos, instr) in block.instructions.iter().enumerate() {
+                if let Some(val) = instr.yielded {
+                    defs[val.0] = Some((BlockId(id), pos));
+                }
+                match (&instr.operation, instr.yielded) {
+                    (Operation::Phi(_), _) => {
+                        phis[id] += 1;
+                        continue;
+                    }
+                    (Operation::Copy(src), Some(val)) => copy_of[val.0] = *src,
+                    _ => (),
+                }
+                block_uses.extend(instr.operation.operands().into_iter().map(|val| (pos, val)));
+            }
+            let end = block.instructions.len();
+            block_uses.extend(block.terminator.operand().map(|val| (end, val)));
+            uses.push(block_uses);
+        }
+        // copies are defined after their sources, so one pass in order of
+        // the values' ids isn't enough
+        for val in 0..copy_of.len() {
+            let mut root = copy_of[val];
+            while copy_of[root.0] != root {
+                root = copy_of[root.0];
+            }
+            copy_of[val] = root;
+        }
+        Interference {
+            doms: DominatorTree::compute(func),
+            liveness: Liveness::compute(func),
+            defs,
+            copy_of,
+            phis,
+            uses,
+        }
+    }
+
+    fn classes_interfere(&self, a: &[ValueId], b: &[ValueId]) -> bool {
+        a.iter()
+            .any(|x| b.iter().any(|y| self.values_interfere(*x, *y)))
+    }
+
+    fn values_interfere(&self, x: ValueId, y: ValueId) -> bool {
+        if self.copy_of[x.0] == self.copy_of[y.0] {
+            return false;
+        }
+        let (Some(dx), Some(dy)) = (self.defs[x.0], self.defs[y.0]) else {
+            return false;
+        };
+        // the Φs of a block are all defined at once on entry, so their
+        // classes must get distinct par-moves in every pred
+        if dx.0 == dy.0 && dx.1 < self.phis[dx.0 .0] && dy.1 < self.phis[dy.0 .0] {
+            return true;
+        }
+        // in SSA form, live ranges can only intersect at the later of two
+        // definitions, which the earlier one dominates
+        if self.def_dominates(dx, dy) {
+            self.live_after(x, dy)
+        } else if self.def_dominates(dy, dx) {
+            self.live_after(y, dx)
+        } else {
+            false
+        }
+    }
+
+    fn def_dominates(&self, a: (BlockId, usize), b: (BlockId, usize)) -> bool {
+        if a.0 == b.0 {
+            a.1 < b.1
+        } else {
+            self.doms.dominates(a.0, b.0)
+        }
+    }
+
+    /// Whether `val` is still needed after the instruction at `at`.
+    fn live_after(&self, val: ValueId, at: (BlockId, usize)) -> bool {
+        let (block, pos) = at;
+        self.liveness.is_live_out(val, block)
+            || self.uses[block.0]
+                .iter()
+                .any(|(use_pos, used)| *used == val && *use_pos > pos)
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    algos::{
+        lower_par_moves::LowerParMoves,
+        lower_to_ssa::LowerToSsa,
+        opt::{
+            constant_folding::ConstantFolding, dce::DeadCodeElimination,
+            dead_functions::DeadFunctionElimination, gvn::GlobalValueNumbering, inline::Inliner,
+            instcombine::InstCombine, licm::LoopInvariantCodeMotion, sccp::Sccp,
+            simplify_cfg::SimplifyCfg, tail_recursion::TailRecursionElimination, FunctionPass,
+            OptPass,
+        },
+        out_of_ssa::OutOfSsa,
+        phi_lowering::LowerPhis,
+        remove_critical_edges::RemoveCriticalEdges,
+    },
+    analysis::{
+        dominators::{DominatorTree, PostDominatorTree},
+        liveness::Liveness,
+    },
+    ir::{Algo, Function, Module},
+};
+
+/// The analyses the `PassManager` caches between passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Analysis {
+    Dominators,
+    PostDominators,
+    Liveness,
+}
+
+/// The analyses of one function, computed on first use and kept until a
+/// pass that doesn't preserve them changes the function.
+#[derive(Default)]
+pub struct FunctionAnalyses {
+    dominators: Option<DominatorTree>,
+    post_dominators: Option<PostDominatorTree>,
+    liveness: Option<Liveness>,
+}
+
+impl FunctionAnalyses {
+    pub fn dominators(&mut self, func: &Function) -> &DominatorTree {
+        self.dominators
+            .get_or_insert_with(|| DominatorTree::compute(func))
+    }
+
+    pub fn post_dominators(&mut self, func: &Function) -> &PostDominatorTree {
+        self.post_dominators
+            .get_or_insert_with(|| PostDominatorTree::compute(func))
+    }
+
+    pub fn liveness(&mut self, func: &Function) -> &Liveness {
+        self.liveness.get_or_insert_with(|| Liveness::compute(func))
+    }
+
+    pub fn is_cached(&self, analysis: Analysis) -> bool {
+        match analysis {
+            Analysis::Dominators => self.dominators.is_some(),
+            Analysis::PostDominators => self.post_dominators.is_some(),
+            Analysis::Liveness => self.liveness.is_some(),
+        }
+    }
+
+    /// Drops every analysis `preserved` returns false for.
+    pub fn invalidate(&mut self, preserved: impl Fn(Analysis) -> bool) {
+        if !preserved(Analysis::Dominators) {
+            self.dominators = None;
+        }
+        if !preserved(Analysis::PostDominators) {
+            self.post_dominators = None;
+        }
+        if !preserved(Analysis::Liveness) {
+            self.liveness = None;
+        }
+    }
+}
+
+pub enum Pass {
+    Module(Box<dyn OptPass>),
+    Function(Box<dyn FunctionPass>),
+}
+
+impl Pass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Module(pass) => pass.name(),
+            Pass::Function(pass) => pass.name(),
+        }
+    }
+
+    fn requires(&self) -> &'static [Algo] {
+        match self {
+            Pass::Module(pass) => pass.requires(),
+            Pass::Function(pass) => pass.requires(),
+        }
+    }
+
+    fn forbids(&self) -> &'static [Algo] {
+        match self {
+            Pass::Module(pass) => pass.forbids(),
+            Pass::Function(pass) => pass.forbids(),
+        }
+    }
+
+    fn preserves(&self, algo: Algo) -> bool {
+        match self {
+            Pass::Module(pass) => pass.preserves(algo),
+            Pass::Function(pass) => pass.preserves(algo),
+        }
+    }
+
+    /// Makes the pass registered under `name` in pipeline strings.
+    pub fn by_name(name: &str) -> Option<Pass> {
+        Some(match name {
+            "split-critical-edges" => Pass::Module(Box::new(RemoveCriticalEdges)),
+            "ssa" => Pass::Module(Box::new(LowerToSsa)),
+            "lower-phis" => Pass::Module(Box::new(LowerPhis)),
+            "lower-par-moves" => Pass::Module(Box::new(LowerParMoves)),
+            "out-of-ssa" => Pass::Module(Box::new(OutOfSsa::default())),
+            "constfold" => Pass::Module(Box::new(ConstantFolding::default())),
+            "dce" => Pass::Function(Box::new(DeadCodeElimination::default())),
+            "dfe" => Pass::Module(Box::new(DeadFunctionElimination::default())),
+            "sccp" => Pass::Module(Box::new(Sccp::default())),
+            "gvn" => Pass::Function(Box::new(GlobalValueNumbering::default())),
+            "inline" => Pass::Module(Box::new(Inliner::default())),
+            "instcombine" => Pass::Function(Box::new(InstCombine::default())),
+            "licm" => Pass::Function(Box::new(LoopInvariantCodeMotion::default())),
+            "simplify-cfg" => Pass::Function(Box::new(SimplifyCfg::default())),
+            "tailrec" => Pass::Module(Box::new(TailRecursionElimination::default())),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassError {
+    UnknownPass(String),
+    /// `pass` was scheduled on a module `requires` hasn't run on.
+    MissingRequirement {
+        pass: &'static str,
+        requires: Algo,
+    },
+    /// `pass` was scheduled on a module `forbids` has run on.
+    ForbiddenAlgo {
+        pass: &'static str,
+        forbids: Algo,
+    },
+}
+
+impl Display for PassError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PassError::UnknownPass(name) => write!(f, "unknown pass `{}`", name),
+            PassError::MissingRequirement { pass, requires } => {
+                write!(f, "pass `{}` requires {:?}", pass, requires)
+            }
+            PassError::ForbiddenAlgo { pass, forbids } => {
+                write!(f, "pass `{}` can't run after {:?}", pass, forbids)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PassError {}
+
+/// How long one pass took, summed over every function for function passes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassTiming {
+    pub name: &'static str,
+    pub duration: Duration,
+}
+
+/// Runs a sequence of passes over a module, checking the `Algo`s they
+/// require, dropping the ones they don't preserve from `Module::algos_run`,
+/// and caching the analyses of every function between them.
+///
+/// If `Module::set_verify_transforms` is on, the module is verified after
+/// every pass.
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Pass>,
+    analyses: Vec<FunctionAnalyses>,
+    timings: Vec<PassTiming>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The passes of `Module::apply_mandatory_transforms`.
+    pub fn mandatory() -> Self {
+        let mut pm = Self::new();
+        pm.add_pass(RemoveCriticalEdges)
+            .add_pass(LowerToSsa)
+            .add_pass(LowerPhis);
+        pm
+    }
+
+    /// Makes a pass manager from a comma separated list of pass names, like
+    /// `"constfold,dce,gvn"`.
+    pub fn from_pipeline(pipeline: &str) -> Result<Self, PassError> {
+        let mut pm = Self::new();
+        pm.add_pipeline(pipeline)?;
+        Ok(pm)
+    }
+
+    pub fn add_pipeline(&mut self, pipeline: &str) -> Result<&mut Self, PassError> {
+        for name in pipeline.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let pass =
+                Pass::by_name(name).ok_or_else(|| PassError::UnknownPass(name.to_string()))?;
+            self.passes.push(pass);
+        }
+        Ok(self)
+    }
+
+    pub fn add_pass<P: OptPass + 'static>(&mut self, pass: P) -> &mut Self {
+        self.passes.push(Pass::Module(Box::new(pass)));
+        self
+    }
+
+    pub fn add_function_pass<P: FunctionPass + 'static>(&mut self, pass: P) -> &mut Self {
+        self.passes.push(Pass::Function(Box::new(pass)));
+        self
+    }
+
+    /// The names of the scheduled passes, in order.
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    /// Runs every pass in order, stopping at the first one whose
+    /// requirements aren't met or which forbids an `Algo` that has run.
+    pub fn run(&mut self, module: &mut Module) -> Result<(), PassError> {
+        self.timings.clear();
+        self.analyses.clear();
+        self.analyses
+            .resize_with(module.functions.len(), FunctionAnalyses::default);
+
+        for pass in self.passes.iter_mut() {
+            if let Some(missing) = pass
+                .requires()
+                .iter()
+                .find(|algo| !module.algos_run.contains(algo))
+            {
+                return Err(PassError::MissingRequirement {
+                    pass: pass.name(),
+                    requires: *missing,
+                });
+            }
+            if let Some(forbidden) = pass
+                .forbids()
+                .iter()
+                .find(|algo| module.algos_run.contains(algo))
+            {
+                return Err(PassError::ForbiddenAlgo {
+                    pass: pass.name(),
+                    forbids: *forbidden,
+                });
+            }
+
+            let start = Instant::now();
+            match pass {
+                Pass::Module(pass) => {
+                    pass.run(module);
+                    // the functions may have been added, removed or reordered
+                    if module.functions.len() != self.analyses.len() {
+                        self.analyses.clear();
+                        self.analyses
+                            .resize_with(module.functions.len(), FunctionAnalyses::default);
+                    }
+                    for analyses in self.analyses.iter_mut() {
+                        analyses.invalidate(|a| pass.preserves_analysis(a));
+                    }
+                }
+                Pass::Function(pass) => {
+                    for (func, analyses) in
+                        module.functions.iter_mut().zip(self.analyses.iter_mut())
+                    {
+                        if func.blocks.is_empty() {
+                            continue;
+                        }
+                        if pass.run_on_function(func, analyses) {
+                            analyses.invalidate(|a| pass.preserves_analysis(a));
+                        }
+                    }
+                }
+            }
+            self.timings.push(PassTiming {
+                name: pass.name(),
+                duration: start.elapsed(),
+            });
+
+            module.algos_run.retain(|algo| pass.preserves(*algo));
+            module.verify_step(pass.name());
+        }
+        Ok(())
+    }
+
+    /// The cached analyses of the function at `index` after the last `run`.
+    pub fn analyses(&mut self, index: usize) -> Option<&mut FunctionAnalyses> {
+        self.analyses.get_mut(index)
+    }
+
+    /// The time taken by every pass of the last `run`.
+    pub fn timings(&self) -> &[PassTiming] {
+        &self.timings
+    }
+
+    /// A table of the time taken by every pass of the last `run`.
+    pub fn timing_report(&self) -> String {
+        let width = self.timings.iter().map(|t| t.name.len()).max().unwrap_or(0);
+        let total: Duration = self.timings.iter().map(|t| t.duration).sum();
+        let mut report = String::new();
+        for timing in self.timings.iter() {
+            report.push_str(&format!(
+                "{:width$}  {:>10.3?}\n",
+                timing.name,
+                timing.duration,
+                width = width
+            ));
+        }
+        report.push_str(&format!(
+            "{:width$}  {:>10.3?}\n",
+            "total",
+            total,
+            width = width
+        ));
+        report
+    }
+}
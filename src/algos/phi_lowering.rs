@@ -1,11 +1,34 @@
 use crate::{
-    algos::delete_instructions::delete,
+    algos::{delete_instructions::delete, opt::OptPass, pass_manager::Analysis},
     ir::{Algo, Module, Operation},
 };
 
+/// `lower_phis` as a pass.
+pub struct LowerPhis;
+
+impl OptPass for LowerPhis {
+    fn name(&self) -> &'static str {
+        "lower-phis"
+    }
+
+    fn requires(&self) -> &'static [Algo] {
+        &[Algo::CriticalEdgeSplitting, Algo::SsaConstruction]
+    }
+
+    fn preserves_analysis(&self, analysis: Analysis) -> bool {
+        analysis != Analysis::Liveness
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        lower_phis(module);
+    }
+}
+
+/// Replaces every Φ with par-moves at the end of its preds.
+///
+/// Critical edges have to be split first, as `LowerPhis` requires, or the
+/// moves would also run on paths which don't lead to the Φ.
 pub fn lower_phis(module: &mut Module) {
-    // Modules without critical edge splitting will have program semantics changed if phis are removed
-    assert!(module.algos_run.contains(&Algo::CriticalEdgeSplitting));
     module.algos_run.push(Algo::PhiLowering);
     // Function: V: BB: I -> should delete instruction
     let mut func_dels: Vec<Vec<Vec<bool>>> = Vec::new();
@@ -18,11 +41,12 @@ pub fn lower_phis(module: &mut Module) {
                 func_dels[func_id][block_id].push(false);
                 match &instr.operation {
                     Operation::Phi(defs) => {
-                        for val in defs {
-                            let target_block = func.values[val.0].owner;
-                            func.blocks[target_block.0]
+                        // operands line up with the preds, and with critical
+                        // edges split every pred only has this block as successor
+                        for (val, pred) in defs.iter().zip(block.preds.iter()) {
+                            func.blocks[pred.0]
                                 .par_moves
-                                .push((instr.yielded.unwrap(), val.clone()))
+                                .push((instr.yielded.unwrap(), *val))
                         }
                         func_dels[func_id][block_id][instr_id] = true;
                     }
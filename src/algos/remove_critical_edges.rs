@@ -1,8 +1,25 @@
-use crate::ir::{Algo, BasicBlock, BlockId, Module, Terminator};
+use crate::{
+    algos::opt::OptPass,
+    ir::{Algo, BasicBlock, BlockId, Function, Instruction, Module, Operation, Terminator},
+};
+
+/// `remove_critical_edges` as a pass.
+pub struct RemoveCriticalEdges;
+
+impl OptPass for RemoveCriticalEdges {
+    fn name(&self) -> &'static str {
+        "split-critical-edges"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        remove_critical_edges(module);
+    }
+}
 
 pub fn remove_critical_edges(module: &mut Module) {
     module.algos_run.push(Algo::CriticalEdgeSplitting);
     for func in module.functions.iter_mut() {
+        split_entry(func);
         let mut to_insert = Vec::new();
         let blocks = func.blocks.clone();
         for (id, block) in blocks.iter().enumerate() {
@@ -48,3 +65,81 @@ pub fn remove_critical_edges(module: &mut Module) {
         func.blocks.append(&mut to_insert)
     }
 }
+
+/// Makes sure nothing branches back to the entry block, by moving its body
+/// into a new block that the entry jumps to. SSA construction needs the
+/// entry to have no preds, as Φs can't be placed there.
+pub fn split_entry(func: &mut Function) {
+    if func.blocks.is_empty() || func.blocks[0].preds.is_empty() {
+        return;
+    }
+    move_entry_body(func);
+}
+
+/// Moves the body of the entry block into a new block which the entry jumps
+/// to, and returns the new block.
+pub(crate) fn move_entry_body(func: &mut Function) -> BlockId {
+    let entry = BlockId(0);
+    let new = BlockId(func.blocks.len());
+    let mut old = std::mem::replace(
+        &mut func.blocks[0],
+        BasicBlock {
+            instructions: vec![],
+            terminator: Terminator::Jump(new),
+            preds: vec![],
+            id: 0,
+            par_moves: vec![],
+        },
+    );
+    let retarget = |block: &mut BlockId| {
+        if *block == entry {
+            *block = new;
+        }
+    };
+    for succ in old.terminator.successors() {
+        func.blocks[succ.0].preds.iter_mut().for_each(retarget);
+    }
+    // a self-loop on the entry now loops on the moved body, keeping the
+    // position of its Φ operands
+    old.preds.iter_mut().for_each(retarget);
+    func.blocks.push(BasicBlock { id: new.0, ..old });
+
+    for block in func.blocks.iter_mut().skip(1) {
+        match block.terminator {
+            Terminator::Jump(ref mut target) => retarget(target),
+            Terminator::Branch(_, ref mut t, ref mut f) => {
+                retarget(t);
+                retarget(f);
+            }
+            _ => (),
+        }
+    }
+    func.blocks[new.0].preds.push(entry);
+    for val in func.values.iter_mut() {
+        retarget(&mut val.owner);
+    }
+
+    // Φs already in the moved body read zero when coming from the entry
+    let mut zeros = Vec::new();
+    for pos in 0..func.blocks[new.0].instructions.len() {
+        let instr = &func.blocks[new.0].instructions[pos];
+        let (Some(phi), Operation::Phi(_)) = (instr.yielded, &instr.operation) else {
+            break;
+        };
+        let zero = func.push_value(func.values[phi.0].ty.clone());
+        zeros.push(Instruction {
+            yielded: Some(zero),
+            operation: Operation::Integer(0),
+        });
+        if let Operation::Phi(ref mut vals) = func.blocks[new.0].instructions[pos].operation {
+            vals.push(zero);
+        }
+    }
+    func.blocks[0].instructions = zeros;
+    for var in func.variables.iter_mut() {
+        if var.bbs_assign_to.remove(&entry) {
+            var.bbs_assign_to.insert(new);
+        }
+    }
+    new
+}
@@ -0,0 +1,372 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    analysis::dominators::DominatorTree,
+    ir::{Algo, BlockId, Function, Global, Module, Operation, Terminator, ValueId},
+};
+
+/// A single broken invariant found by `verify`.
+///
+/// `block` and `instr` locate the offending instruction when there is one;
+/// an `instr` of `None` with a `block` refers to the block header, preds or
+/// terminator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub function: String,
+    pub block: Option<BlockId>,
+    pub instr: Option<usize>,
+    pub message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "in fn {}", self.function)?;
+        if let Some(block) = self.block {
+            write!(f, ", {}", block)?;
+        }
+        if let Some(instr) = self.instr {
+            write!(f, ", instr {}", instr)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Checks the SSA and CFG invariants of every function in the module.
+///
+/// Which invariants apply depends on `Module::algos_run`: loads and stores of
+/// variables are only allowed before SSA construction, Φ functions only
+/// between SSA construction and phi lowering, and par-moves only after it,
+/// until they are lowered to copies. Values may be defined more than once
+/// after par-move lowering or out-of-SSA translation.
+pub fn verify(module: &Module) -> Vec<VerifyError> {
+    let mut errors = Vec::new();
+    for func in module.functions.iter() {
+        verify_function(module, func, &mut errors);
+    }
+    errors
+}
+
+/// Where a value is defined.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Def {
+    Instr(BlockId, usize),
+    ParMove,
+}
+
+fn verify_function(module: &Module, func: &Function, errors: &mut Vec<VerifyError>) {
+    let ssa = module.algos_run.contains(&Algo::SsaConstruction);
+    let phis_lowered = module.algos_run.contains(&Algo::PhiLowering);
+    let par_moves_lowered = module.algos_run.contains(&Algo::LowerParMoves);
+    // out-of-SSA translation gives coalesced values a single name
+    let coalesced = module.algos_run.contains(&Algo::PhiRemoval);
+    let edges_split = module.algos_run.contains(&Algo::CriticalEdgeSplitting);
+
+    let mut error = |block: Option<BlockId>, instr: Option<usize>, message: String| {
+        errors.push(VerifyError {
+            function: func.name.clone(),
+            block,
+            instr,
+            message,
+        })
+    };
+
+    if func.blocks.is_empty() {
+        return;
+    }
+
+    // CFG structure; dominance is only meaningful if this is sound
+    let mut cfg_ok = true;
+    let mut computed_preds = vec![Vec::new(); func.blocks.len()];
+    for (id, block) in func.blocks.iter().enumerate() {
+        let bb = Some(BlockId(id));
+        if block.id != id {
+            error(bb, None, format!("block is numbered ${}", block.id));
+        }
+        if block.terminator == Terminator::NoTerm {
+            error(bb, None, "block has no terminator".to_string());
+        }
+        for succ in block.terminator.successors() {
+            if succ.0 >= func.blocks.len() {
+                error(bb, None, format!("branch to undefined block {}", succ));
+                cfg_ok = false;
+            } else {
+                computed_preds[succ.0].push(BlockId(id));
+            }
+        }
+        for pred in block.preds.iter() {
+            if pred.0 >= func.blocks.len() {
+                error(bb, None, format!("pred {} is not a block", pred));
+                cfg_ok = false;
+            }
+        }
+    }
+    for (id, block) in func.blocks.iter().enumerate() {
+        let mut listed = block.preds.clone();
+        let mut actual = computed_preds[id].clone();
+        listed.sort_by_key(|b| b.0);
+        actual.sort_by_key(|b| b.0);
+        if listed != actual {
+            error(
+                Some(BlockId(id)),
+                None,
+                format!(
+                    "preds are [{}] but the terminators branching here are in [{}]",
+                    fmt_list(&block.preds),
+                    fmt_list(&computed_preds[id])
+                ),
+            );
+            cfg_ok = false;
+        }
+        if edges_split && block.preds.len() > 1 {
+            for pred in computed_preds[id].iter() {
+                if func.blocks[pred.0].terminator.successors().len() > 1 {
+                    error(
+                        Some(BlockId(id)),
+                        None,
+                        format!("critical edge from {} after edge splitting", pred),
+                    );
+                }
+            }
+        }
+    }
+
+    // definitions
+    let mut defs: Vec<Option<Def>> = vec![None; func.values.len()];
+    for (id, block) in func.blocks.iter().enumerate() {
+        for (pos, instr) in block.instructions.iter().enumerate() {
+            let Some(val) = instr.yielded else { continue };
+            if val.0 >= func.values.len() {
+                error(
+                    Some(BlockId(id)),
+                    Some(pos),
+                    format!("{} is not a value", val),
+                );
+                continue;
+            }
+            // the copies par-moves were lowered to may share their target
+            let copy = par_moves_lowered && matches!(instr.operation, Operation::Copy(_));
+            match defs[val.0] {
+                Some(Def::ParMove) if copy => (),
+                None if copy => defs[val.0] = Some(Def::ParMove),
+                Some(_) if coalesced => defs[val.0] = Some(Def::ParMove),
+                Some(_) => error(
+                    Some(BlockId(id)),
+                    Some(pos),
+                    format!("{} is defined more than once", val),
+                ),
+                None => defs[val.0] = Some(Def::Instr(BlockId(id), pos)),
+            }
+        }
+    }
+    for (id, block) in func.blocks.iter().enumerate() {
+        if !block.par_moves.is_empty() && !phis_lowered {
+            error(
+                Some(BlockId(id)),
+                None,
+                "par-moves before phi lowering".to_string(),
+            );
+        }
+        if !block.par_moves.is_empty() && par_moves_lowered {
+            error(
+                Some(BlockId(id)),
+                None,
+                "par-moves after par-move lowering".to_string(),
+            );
+        }
+        for (dst, _) in block.par_moves.iter() {
+            match defs.get(dst.0) {
+                None => error(Some(BlockId(id)), None, format!("{} is not a value", dst)),
+                Some(Some(Def::Instr(..))) if !coalesced => error(
+                    Some(BlockId(id)),
+                    None,
+                    format!("par-move target {} is also defined by an instruction", dst),
+                ),
+                Some(_) => defs[dst.0] = Some(Def::ParMove),
+            }
+        }
+    }
+
+    // the immutable globals pointed to by `GlobalAddr`s
+    let constants: HashMap<ValueId, &Global> = func
+        .blocks
+        .iter()
+        .flat_map(|block| block.instructions.iter())
+        .filter_map(|instr| match (instr.yielded, &instr.operation) {
+            (Some(val), Operation::GlobalAddr(global)) => module
+                .globals
+                .get(global.0)
+                .filter(|global| !global.mutable)
+                .map(|global| (val, global)),
+            _ => None,
+        })
+        .collect();
+
+    let doms = if cfg_ok {
+        Some(DominatorTree::compute(func))
+    } else {
+        None
+    };
+
+    // checks that `val` is defined and available at the end of `block`, or
+    // before instruction `before` of it
+    let check_use = |val: ValueId, block: BlockId, before: Option<usize>| -> Option<String> {
+        let def = match defs.get(val.0) {
+            None => return Some(format!("{} is not a value", val)),
+            Some(None) => return Some(format!("{} is used but never defined", val)),
+            Some(Some(def)) => *def,
+        };
+        let (def_block, def_pos) = match def {
+            Def::ParMove => return None,
+            Def::Instr(def_block, def_pos) => (def_block, def_pos),
+        };
+        if def_block == block {
+            return match before {
+                Some(pos) if def_pos >= pos => {
+                    Some(format!("{} is used before its definition", val))
+                }
+                _ => None,
+            };
+        }
+        match &doms {
+            Some(doms) if doms.is_reachable(block) && !doms.dominates(def_block, block) => {
+                Some(format!(
+                    "definition of {} in {} does not dominate its use",
+                    val, def_block
+                ))
+            }
+            _ => None,
+        }
+    };
+
+    for (id, block) in func.blocks.iter().enumerate() {
+        let bb = BlockId(id);
+        let mut seen_non_phi = false;
+        for (pos, instr) in block.instructions.iter().enumerate() {
+            let at = |msg: String| (Some(bb), Some(pos), msg);
+            let mut found = Vec::new();
+            match &instr.operation {
+                Operation::Phi(vals) => {
+                    if !ssa {
+                        found.push(at("Φ before SSA construction".to_string()));
+                    }
+                    if phis_lowered {
+                        found.push(at("Φ after phi lowering".to_string()));
+                    }
+                    if seen_non_phi {
+                        found.push(at("Φ after a non-Φ instruction".to_string()));
+                    }
+                    if vals.len() != block.preds.len() {
+                        found.push(at(format!(
+                            "Φ has {} operands but the block has {} preds",
+                            vals.len(),
+                            block.preds.len()
+                        )));
+                    } else {
+                        // each operand must be available at the end of its pred
+                        for (val, pred) in vals.iter().zip(block.preds.iter()) {
+                            if pred.0 < func.blocks.len() {
+                                if let Some(msg) = check_use(*val, *pred, None) {
+                                    found.push(at(format!("{} (from {})", msg, pred)));
+                                }
+                            }
+                        }
+                    }
+                }
+                op => {
+                    seen_non_phi = true;
+                    if let Operation::LoadVar(var) | Operation::StoreVar(var, _) = op {
+                        if ssa {
+                            found.push(at("variable access after SSA construction".to_string()));
+                        }
+                        if var.0 >= func.variables.len() {
+                            found.push(at(format!("#{} is not a variable", var.0)));
+                        }
+                    }
+                    if let Operation::Arg(idx) = op {
+                        if *idx >= func.args.len() {
+                            found.push(at(format!(
+                                "argument {} of `{}`, which has {}",
+                                idx,
+                                func.name,
+                                func.args.len()
+                            )));
+                        }
+                    }
+                    if let Operation::Load(ptr)
+                    | Operation::Store(ptr, _)
+                    | Operation::Gep(ptr, _) = op
+                    {
+                        if func
+                            .values
+                            .get(ptr.0)
+                            .is_some_and(|v| v.ty.pointee().is_none())
+                        {
+                            found.push(at(format!("{} is not a pointer", ptr)));
+                        }
+                    }
+                    if let Operation::GlobalAddr(global) = op {
+                        if global.0 >= module.globals.len() {
+                            found.push(at(format!("@{} is not a global", global.0)));
+                        }
+                    }
+                    if let Operation::Store(ptr, _) = op {
+                        if let Some(global) = constants.get(ptr) {
+                            found.push(at(format!("store to constant `{}`", global.name)));
+                        }
+                    }
+                    if let Operation::Cast(cast, val, to) = op {
+                        if let Some(from) = func.values.get(val.0).map(|v| &v.ty) {
+                            if !cast.is_valid(from, to) {
+                                found.push(at(format!("cannot {} {} to {}", cast, from, to)));
+                            }
+                        }
+                    }
+                    if let Operation::Call(callee, args) = op {
+                        match module.functions.get(callee.0) {
+                            None => {
+                                found.push(at(format!("call to undefined function ${}", callee.0)))
+                            }
+                            Some(callee) if callee.args.len() != args.len() => {
+                                found.push(at(format!(
+                                    "call to `{}` with {} arguments, expected {}",
+                                    callee.name,
+                                    args.len(),
+                                    callee.args.len()
+                                )))
+                            }
+                            _ => (),
+                        }
+                    }
+                    let mut operands = op.operands();
+                    operands.dedup();
+                    for val in operands {
+                        if let Some(msg) = check_use(val, bb, Some(pos)) {
+                            found.push(at(msg));
+                        }
+                    }
+                }
+            }
+            for (block, instr, msg) in found {
+                error(block, instr, msg);
+            }
+        }
+        for (_, src) in block.par_moves.iter() {
+            if let Some(msg) = check_use(*src, bb, None) {
+                error(Some(bb), None, format!("par-move source: {}", msg));
+            }
+        }
+        if let Some(val) = block.terminator.operand() {
+            if let Some(msg) = check_use(val, bb, None) {
+                error(Some(bb), None, format!("terminator: {}", msg));
+            }
+        }
+    }
+}
+
+fn fmt_list(blocks: &[BlockId]) -> String {
+    blocks
+        .iter()
+        .map(|b| b.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
@@ -0,0 +1,154 @@
+use crate::ir::{FunctionId, Module, Operation};
+
+/// Which functions of a module call which, from their `Operation::Call`s,
+/// with the graph's strongly connected components.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallGraph {
+    /// The functions every function calls, sorted by id without duplicates
+    callees: Vec<Vec<FunctionId>>,
+    callers: Vec<Vec<FunctionId>>,
+    /// Callees come before their callers, except within a component
+    sccs: Vec<Vec<FunctionId>>,
+    scc_of: Vec<usize>,
+}
+
+impl CallGraph {
+    pub fn compute(module: &Module) -> CallGraph {
+        let count = module.functions.len();
+        let mut callees: Vec<Vec<FunctionId>> = module
+            .functions
+            .iter()
+            .map(|func| {
+                func.blocks
+                    .iter()
+                    .flat_map(|bb| bb.instructions.iter())
+                    .filter_map(|instr| match instr.operation {
+                        Operation::Call(callee, _) => Some(callee),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut callers = vec![Vec::new(); count];
+        for (caller, callees) in callees.iter_mut().enumerate() {
+            callees.sort_by_key(|f| f.0);
+            callees.dedup();
+            for callee in callees.iter() {
+                callers[callee.0].push(FunctionId(caller));
+            }
+        }
+
+        let (sccs, scc_of) = tarjan(&callees);
+        CallGraph {
+            callees,
+            callers,
+            sccs,
+            scc_of,
+        }
+    }
+
+    pub fn callees(&self, func: FunctionId) -> &[FunctionId] {
+        &self.callees[func.0]
+    }
+
+    pub fn callers(&self, func: FunctionId) -> &[FunctionId] {
+        &self.callers[func.0]
+    }
+
+    /// The strongly connected components, every one after the ones it calls
+    /// into (so in bottom-up order).
+    pub fn sccs(&self) -> &[Vec<FunctionId>] {
+        &self.sccs
+    }
+
+    /// The component `func` is part of.
+    pub fn scc(&self, func: FunctionId) -> &[FunctionId] {
+        &self.sccs[self.scc_of[func.0]]
+    }
+
+    /// Whether `func` can end up calling itself.
+    pub fn is_recursive(&self, func: FunctionId) -> bool {
+        self.scc(func).len() > 1 || self.callees(func).contains(&func)
+    }
+
+    /// Which functions can be called, directly or not, from `roots`
+    /// (including the roots themselves), indexed by function id.
+    pub fn reachable_from(&self, roots: &[FunctionId]) -> Vec<bool> {
+        let mut reachable = vec![false; self.callees.len()];
+        let mut worklist = roots.to_vec();
+        while let Some(func) = worklist.pop() {
+            if reachable[func.0] {
+                continue;
+            }
+            reachable[func.0] = true;
+            worklist.extend(self.callees(func).iter().copied());
+        }
+        reachable
+    }
+}
+
+/// Tarjan's algorithm, without recursion. Returns the components in reverse
+/// topological order and the component of every node.
+fn tarjan(succs: &[Vec<FunctionId>]) -> (Vec<Vec<FunctionId>>, Vec<usize>) {
+    let count = succs.len();
+    let mut index = vec![None; count];
+    let mut lowlink = vec![0; count];
+    let mut on_stack = vec![false; count];
+    let mut stack = Vec::new();
+    let mut sccs = Vec::new();
+    let mut scc_of = vec![0; count];
+    let mut next = 0;
+
+    for root in 0..count {
+        if index[root].is_some() {
+            continue;
+        }
+        // (node, how many of its successors were visited)
+        let mut dfs = vec![(root, 0)];
+        index[root] = Some(next);
+        lowlink[root] = next;
+        next += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some((node, visited)) = dfs.last_mut() {
+            let node = *node;
+            if let Some(succ) = succs[node].get(*visited) {
+                *visited += 1;
+                let succ = succ.0;
+                match index[succ] {
+                    None => {
+                        index[succ] = Some(next);
+                        lowlink[succ] = next;
+                        next += 1;
+                        stack.push(succ);
+                        on_stack[succ] = true;
+                        dfs.push((succ, 0));
+                    }
+                    Some(idx) if on_stack[succ] => lowlink[node] = lowlink[node].min(idx),
+                    Some(_) => (),
+                }
+                continue;
+            }
+
+            dfs.pop();
+            if let Some((parent, _)) = dfs.last() {
+                lowlink[*parent] = lowlink[*parent].min(lowlink[node]);
+            }
+            if Some(lowlink[node]) == index[node] {
+                let mut scc = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    scc_of[member] = sccs.len();
+                    scc.push(FunctionId(member));
+                    if member == node {
+                        break;
+                    }
+                }
+                scc.sort_by_key(|f| f.0);
+                sccs.push(scc);
+            }
+        }
+    }
+    (sccs, scc_of)
+}
@@ -0,0 +1,406 @@
+use std::collections::BTreeSet;
+
+use crate::ir::{BlockId, Function, Terminator};
+
+/// The dominator tree of a function, computed with the Cooper–Harvey–Kennedy
+/// iterative algorithm over `BasicBlock::preds`, with block 0 as the entry.
+///
+/// Blocks unreachable from the entry have no immediate dominator and are not
+/// dominated by (nor dominate) anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DominatorTree {
+    tree: Tree,
+    rpo: Vec<BlockId>,
+}
+
+impl DominatorTree {
+    pub fn compute(func: &Function) -> DominatorTree {
+        let succs: Vec<Vec<usize>> = func
+            .blocks
+            .iter()
+            .map(|bb| bb.terminator.successors().iter().map(|b| b.0).collect())
+            .collect();
+        let preds: Vec<Vec<usize>> = func
+            .blocks
+            .iter()
+            .map(|bb| bb.preds.iter().map(|b| b.0).collect())
+            .collect();
+        let tree = Tree::compute(0, &succs, &preds);
+        let rpo = tree.rpo.iter().copied().map(BlockId).collect();
+        DominatorTree { tree, rpo }
+    }
+
+    /// The immediate dominator of `block`, or `None` for the entry block and
+    /// unreachable blocks.
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.tree.idom[block.0].map(BlockId)
+    }
+
+    /// The blocks immediately dominated by `block`.
+    pub fn children(&self, block: BlockId) -> Vec<BlockId> {
+        self.tree.children[block.0]
+            .iter()
+            .copied()
+            .map(BlockId)
+            .collect()
+    }
+
+    /// Whether `a` dominates `b`. Every reachable block dominates itself.
+    pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        self.tree.contains(a.0, b.0)
+    }
+
+    /// Whether `a` dominates `b` and is not `b`.
+    pub fn strictly_dominates(&self, a: BlockId, b: BlockId) -> bool {
+        a != b && self.dominates(a, b)
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.tree.is_reachable(block.0)
+    }
+
+    /// The reachable blocks in reverse postorder of the CFG.
+    pub fn reverse_postorder(&self) -> &[BlockId] {
+        &self.rpo
+    }
+
+    /// The reachable blocks in preorder of the dominator tree, so every block
+    /// comes after its dominators.
+    pub fn preorder(&self) -> Vec<BlockId> {
+        self.tree.preorder().into_iter().map(BlockId).collect()
+    }
+}
+
+/// The post-dominator tree of a function: `a` post-dominates `b` if every
+/// path from `b` to a `Terminator::Return` goes through `a`.
+///
+/// The tree is rooted at a virtual exit joining every returning block, so
+/// blocks which can't reach a return (e.g. infinite loops) aren't part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostDominatorTree {
+    tree: Tree,
+    exit: usize,
+}
+
+impl PostDominatorTree {
+    pub fn compute(func: &Function) -> PostDominatorTree {
+        let exit = func.blocks.len();
+        // the reversed CFG, with the virtual exit as its entry
+        let mut succs: Vec<Vec<usize>> = func
+            .blocks
+            .iter()
+            .map(|bb| bb.preds.iter().map(|b| b.0).collect())
+            .collect();
+        let mut preds: Vec<Vec<usize>> = func
+            .blocks
+            .iter()
+            .map(|bb| bb.terminator.successors().iter().map(|b| b.0).collect())
+            .collect();
+        succs.push(vec![]);
+        preds.push(vec![]);
+        for (id, bb) in func.blocks.iter().enumerate() {
+            if let Terminator::Return(_) = bb.terminator {
+                succs[exit].push(id);
+                preds[id].push(exit);
+            }
+        }
+        PostDominatorTree {
+            tree: Tree::compute(exit, &succs, &preds),
+            exit,
+        }
+    }
+
+    /// The immediate post-dominator of `block`, or `None` if it returns
+    /// directly or can't reach a return.
+    pub fn ipdom(&self, block: BlockId) -> Option<BlockId> {
+        self.tree.idom[block.0]
+            .filter(|b| *b != self.exit)
+            .map(BlockId)
+    }
+
+    /// The blocks immediately post-dominated by `block`.
+    pub fn children(&self, block: BlockId) -> Vec<BlockId> {
+        self.tree.children[block.0]
+            .iter()
+            .copied()
+            .map(BlockId)
+            .collect()
+    }
+
+    /// The returning blocks, i.e. the roots of the tree below the virtual exit.
+    pub fn exits(&self) -> Vec<BlockId> {
+        self.tree.children[self.exit]
+            .iter()
+            .copied()
+            .map(BlockId)
+            .collect()
+    }
+
+    /// Whether `a` post-dominates `b`. Every block reaching a return
+    /// post-dominates itself.
+    pub fn post_dominates(&self, a: BlockId, b: BlockId) -> bool {
+        self.tree.contains(a.0, b.0)
+    }
+
+    pub fn strictly_post_dominates(&self, a: BlockId, b: BlockId) -> bool {
+        a != b && self.post_dominates(a, b)
+    }
+
+    /// Whether a return is reachable from `block`.
+    pub fn reaches_exit(&self, block: BlockId) -> bool {
+        self.tree.is_reachable(block.0)
+    }
+}
+
+/// The dominance frontier of every block: the blocks where its dominance
+/// ends, i.e. where Φ functions for definitions in it are needed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DominanceFrontiers {
+    frontiers: Vec<Vec<BlockId>>,
+}
+
+impl DominanceFrontiers {
+    pub fn compute(func: &Function, doms: &DominatorTree) -> DominanceFrontiers {
+        let mut frontiers: Vec<Vec<BlockId>> = vec![Vec::new(); func.blocks.len()];
+        for block in doms.reverse_postorder() {
+            let preds = &func.blocks[block.0].preds;
+            if preds.len() < 2 {
+                continue;
+            }
+            for pred in preds.iter() {
+                let mut runner = *pred;
+                while doms.is_reachable(runner) && Some(runner) != doms.idom(*block) {
+                    if !frontiers[runner.0].contains(block) {
+                        frontiers[runner.0].push(*block);
+                    }
+                    match doms.idom(runner) {
+                        Some(idom) => runner = idom,
+                        None => break,
+                    }
+                }
+            }
+        }
+        DominanceFrontiers { frontiers }
+    }
+
+    pub fn frontier(&self, block: BlockId) -> &[BlockId] {
+        &self.frontiers[block.0]
+    }
+
+    /// The iterated dominance frontier of a set of blocks, sorted by id.
+    pub fn iterated<I: IntoIterator<Item = BlockId>>(&self, blocks: I) -> Vec<BlockId> {
+        let mut result = BTreeSet::new();
+        let mut worklist: Vec<BlockId> = blocks.into_iter().collect();
+        while let Some(block) = worklist.pop() {
+            for df in self.frontiers[block.0].iter() {
+                if result.insert(df.0) {
+                    worklist.push(*df);
+                }
+            }
+        }
+        result.into_iter().map(BlockId).collect()
+    }
+}
+
+/// A dominator tree over an arbitrary graph of `usize` nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Tree {
+    idom: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    rpo: Vec<usize>,
+    // pre/post numbering of the tree, for O(1) dominance queries
+    pre: Vec<usize>,
+    post: Vec<usize>,
+}
+
+impl Tree {
+    fn compute(entry: usize, succs: &[Vec<usize>], preds: &[Vec<usize>]) -> Tree {
+        let count = succs.len();
+        let rpo = graph_reverse_postorder(entry, succs);
+        let mut rpo_index = vec![usize::MAX; count];
+        for (i, node) in rpo.iter().enumerate() {
+            rpo_index[*node] = i;
+        }
+
+        let mut idom: Vec<Option<usize>> = vec![None; count];
+        idom[entry] = Some(entry);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for pred in preds[*node].iter() {
+                    if idom[*pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => *pred,
+                        Some(other) => intersect(&idom, &rpo_index, *pred, other),
+                    });
+                }
+                if new_idom.is_some() && idom[*node] != new_idom {
+                    idom[*node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[entry] = None;
+
+        let mut children = vec![Vec::new(); count];
+        for (node, parent) in idom.iter().enumerate() {
+            if let Some(parent) = parent {
+                children[*parent].push(node);
+            }
+        }
+
+        let mut pre = vec![usize::MAX; count];
+        let mut post = vec![usize::MAX; count];
+        let mut counter = 0;
+        let mut stack = vec![(entry, 0)];
+        pre[entry] = counter;
+        while let Some((node, child)) = stack.pop() {
+            if let Some(next) = children[node].get(child) {
+                stack.push((node, child + 1));
+                counter += 1;
+                pre[*next] = counter;
+                stack.push((*next, 0));
+            } else {
+                counter += 1;
+                post[node] = counter;
+            }
+        }
+
+        Tree {
+            idom,
+            children,
+            rpo,
+            pre,
+            post,
+        }
+    }
+
+    fn contains(&self, a: usize, b: usize) -> bool {
+        self.is_reachable(a)
+            && self.is_reachable(b)
+            && self.pre[a] <= self.pre[b]
+            && self.post[b] <= self.post[a]
+    }
+
+    fn is_reachable(&self, node: usize) -> bool {
+        self.pre[node] != usize::MAX
+    }
+
+    fn preorder(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.pre.len())
+            .filter(|node| self.is_reachable(*node))
+            .collect();
+        order.sort_by_key(|node| self.pre[*node]);
+        order
+    }
+}
+
+fn intersect(idom: &[Option<usize>], rpo_index: &[usize], a: usize, b: usize) -> usize {
+    let (mut a, mut b) = (a, b);
+    while a != b {
+        while rpo_index[a] > rpo_index[b] {
+            a = idom[a].unwrap();
+        }
+        while rpo_index[b] > rpo_index[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    a
+}
+
+fn graph_reverse_postorder(entry: usize, succs: &[Vec<usize>]) -> Vec<usize> {
+    let mut order = Vec::new();
+    let mut visited = vec![false; succs.len()];
+    let mut stack = vec![(entry, 0)];
+    visited[entry] = true;
+    while let Some((node, succ)) = stack.pop() {
+        if let Some(next) = succs[node].get(succ) {
+            stack.push((node, succ + 1));
+            if !visited[*next] {
+                visited[*next] = true;
+                stack.push((*next, 0));
+            }
+        } else {
+            order.push(node);
+        }
+    }
+    order.reverse();
+    order
+}
+
+/// A depth-first walk of the blocks reachable from the entry, following
+/// terminator successors in order and yielding each block in preorder.
+pub struct Dfs<'a> {
+    func: &'a Function,
+    stack: Vec<BlockId>,
+    visited: Vec<bool>,
+}
+
+impl<'a> Dfs<'a> {
+    pub fn new(func: &'a Function) -> Self {
+        let stack = if func.blocks.is_empty() {
+            vec![]
+        } else {
+            vec![BlockId(0)]
+        };
+        Dfs {
+            func,
+            stack,
+            visited: vec![false; func.blocks.len()],
+        }
+    }
+}
+
+impl Iterator for Dfs<'_> {
+    type Item = BlockId;
+
+    fn next(&mut self) -> Option<BlockId> {
+        loop {
+            let block = self.stack.pop()?;
+            if self.visited[block.0] {
+                continue;
+            }
+            self.visited[block.0] = true;
+            for succ in self.func.blocks[block.0]
+                .terminator
+                .successors()
+                .iter()
+                .rev()
+            {
+                if !self.visited[succ.0] {
+                    self.stack.push(*succ);
+                }
+            }
+            return Some(block);
+        }
+    }
+}
+
+/// Computes the postorder of the blocks reachable from the entry, following
+/// terminator successors.
+pub fn postorder(func: &Function) -> Vec<BlockId> {
+    if func.blocks.is_empty() {
+        return vec![];
+    }
+    let succs: Vec<Vec<usize>> = func
+        .blocks
+        .iter()
+        .map(|bb| bb.terminator.successors().iter().map(|b| b.0).collect())
+        .collect();
+    let mut order = graph_reverse_postorder(0, &succs);
+    order.reverse();
+    order.into_iter().map(BlockId).collect()
+}
+
+/// Computes the reverse postorder of the blocks reachable from the entry,
+/// following terminator successors. Every block comes before its successors,
+/// except along back edges.
+pub fn reverse_postorder(func: &Function) -> Vec<BlockId> {
+    let mut order = postorder(func);
+    order.reverse();
+    order
+}
@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    analysis::dominators::postorder,
+    ir::{BlockId, Function, Operation, ValueId},
+    regalloc::{Regalloc, VReg},
+    vcode::{VCodeFunction, VCodeInstr},
+};
+
+/// The values live on entry to and exit from every block of a function.
+///
+/// Follows the SSA conventions of the rest of the crate:
+///  - a Φ defines its value at the top of its block, and its operands are
+///    used at the end of the matching pred rather than in the Φ's block
+///  - par-moves read their sources and then write their targets at the end
+///    of the block, after the terminator's operand is read
+///
+/// Blocks unreachable from the entry are still analysed, so their sets are
+/// meaningful too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Liveness {
+    live_in: Vec<HashSet<ValueId>>,
+    live_out: Vec<HashSet<ValueId>>,
+}
+
+impl Liveness {
+    pub fn compute(func: &Function) -> Liveness {
+        let count = func.blocks.len();
+        let mut uses: Vec<HashSet<ValueId>> = vec![HashSet::new(); count];
+        let mut defs: Vec<HashSet<ValueId>> = vec![HashSet::new(); count];
+        // Φ operands, live out of the pred they flow from
+        let mut phi_uses: Vec<HashSet<ValueId>> = vec![HashSet::new(); count];
+
+        for (id, block) in func.blocks.iter().enumerate() {
+            for instr in block.instructions.iter() {
+                match &instr.operation {
+                    Operation::Phi(vals) => {
+                        for (val, pred) in vals.iter().zip(block.preds.iter()) {
+                            phi_uses[pred.0].insert(*val);
+                        }
+                    }
+                    op => {
+                        for val in op.operands() {
+                            if !defs[id].contains(&val) {
+                                uses[id].insert(val);
+                            }
+                        }
+                    }
+                }
+                if let Some(val) = instr.yielded {
+                    defs[id].insert(val);
+                }
+            }
+            let end_uses = block
+                .terminator
+                .operand()
+                .into_iter()
+                .chain(block.par_moves.iter().map(|(_, src)| *src));
+            for val in end_uses {
+                if !defs[id].contains(&val) {
+                    uses[id].insert(val);
+                }
+            }
+            for (dst, _) in block.par_moves.iter() {
+                defs[id].insert(*dst);
+            }
+        }
+
+        // reachable blocks in postorder converge fastest; unreachable ones
+        // are tacked on so they get sets as well
+        let mut order = postorder(func);
+        let mut seen = vec![false; count];
+        for block in order.iter() {
+            seen[block.0] = true;
+        }
+        order.extend((0..count).filter(|b| !seen[*b]).map(BlockId));
+
+        let mut live_in = uses.clone();
+        let mut live_out = phi_uses;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in order.iter() {
+                for succ in func.blocks[block.0].terminator.successors() {
+                    let flowing: Vec<ValueId> = live_in[succ.0].iter().copied().collect();
+                    for val in flowing {
+                        live_out[block.0].insert(val);
+                    }
+                }
+                let through: Vec<ValueId> = live_out[block.0]
+                    .iter()
+                    .filter(|val| !defs[block.0].contains(*val))
+                    .copied()
+                    .collect();
+                for val in through {
+                    changed |= live_in[block.0].insert(val);
+                }
+            }
+        }
+
+        Liveness { live_in, live_out }
+    }
+
+    pub fn live_in(&self, block: BlockId) -> &HashSet<ValueId> {
+        &self.live_in[block.0]
+    }
+
+    pub fn live_out(&self, block: BlockId) -> &HashSet<ValueId> {
+        &self.live_out[block.0]
+    }
+
+    pub fn is_live_in(&self, val: ValueId, block: BlockId) -> bool {
+        self.live_in[block.0].contains(&val)
+    }
+
+    pub fn is_live_out(&self, val: ValueId, block: BlockId) -> bool {
+        self.live_out[block.0].contains(&val)
+    }
+}
+
+/// The `VReg::Virtual` registers live on entry to and exit from every block
+/// of a `VCodeFunction`, as reported by `VCodeInstr::collect_registers`.
+///
+/// A block's successors are the `VCodeInstr::jump_targets` of its
+/// instructions, plus the next block if its last instruction falls through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VCodeLiveness {
+    live_in: Vec<HashSet<VReg>>,
+    live_out: Vec<HashSet<VReg>>,
+}
+
+impl VCodeLiveness {
+    pub fn compute<I: VCodeInstr>(func: &VCodeFunction<I>) -> VCodeLiveness {
+        let count = func.instrs.len();
+        let mut uses: Vec<HashSet<VReg>> = vec![HashSet::new(); count];
+        let mut defs: Vec<HashSet<VReg>> = vec![HashSet::new(); count];
+        let mut succs: Vec<Vec<usize>> = vec![Vec::new(); count];
+
+        for (id, block) in func.instrs.iter().enumerate() {
+            for instr in block.instrs.iter() {
+                // an instruction reads its operands before writing its result
+                let mut regs = RegCollector::default();
+                instr.collect_registers(&mut regs);
+                for reg in regs.uses {
+                    if !defs[id].contains(&reg) {
+                        uses[id].insert(reg);
+                    }
+                }
+                defs[id].extend(regs.defs);
+                succs[id].extend(instr.jump_targets().into_iter().filter(|b| *b < count));
+            }
+            let falls_through = block.instrs.last().is_none_or(|i| i.falls_through());
+            if falls_through && id + 1 < count {
+                succs[id].push(id + 1);
+            }
+        }
+
+        let mut live_in = uses;
+        let mut live_out: Vec<HashSet<VReg>> = vec![HashSet::new(); count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in (0..count).rev() {
+                for succ in succs[id].iter() {
+                    let flowing: Vec<VReg> = live_in[*succ].iter().copied().collect();
+                    live_out[id].extend(flowing);
+                }
+                let through: Vec<VReg> = live_out[id]
+                    .iter()
+                    .filter(|reg| !defs[id].contains(*reg))
+                    .copied()
+                    .collect();
+                for reg in through {
+                    changed |= live_in[id].insert(reg);
+                }
+            }
+        }
+
+        VCodeLiveness { live_in, live_out }
+    }
+
+    pub fn live_in(&self, block: usize) -> &HashSet<VReg> {
+        &self.live_in[block]
+    }
+
+    pub fn live_out(&self, block: usize) -> &HashSet<VReg> {
+        &self.live_out[block]
+    }
+}
+
+/// Records the virtual registers an instruction defines and uses.
+#[derive(Default)]
+struct RegCollector {
+    defs: Vec<VReg>,
+    uses: Vec<VReg>,
+}
+
+impl Regalloc for RegCollector {
+    fn add_def(&mut self, reg: VReg) {
+        if let VReg::Virtual(_) = reg {
+            self.defs.push(reg);
+        }
+    }
+    fn add_use(&mut self, reg: VReg) {
+        if let VReg::Virtual(_) = reg {
+            self.uses.push(reg);
+        }
+    }
+    fn next_instr(&mut self) {}
+    fn coalesce_move(&mut self, _from: VReg, _to: VReg) {}
+    fn extend_live_range(&mut self, _reg: VReg, _from: usize, _to: usize) {}
+    fn alloc_regs<I: VCodeInstr>(&self) -> HashMap<VReg, VReg> {
+        HashMap::new()
+    }
+}
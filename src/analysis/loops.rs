@@ -0,0 +1,131 @@
+use crate::{
+    analysis::dominators::DominatorTree,
+    ir::{BlockId, Function},
+};
+
+/// A natural loop: the blocks which can reach one of its back edges without
+/// going through its header. Loops sharing a header are merged into one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: BlockId,
+    /// Every block of the loop, including the header and the blocks of
+    /// nested loops, sorted by id
+    pub blocks: Vec<BlockId>,
+    /// The sources of the back edges to the header
+    pub latches: Vec<BlockId>,
+    /// The index of the innermost loop containing this one
+    pub parent: Option<usize>,
+    /// 1 for outermost loops
+    pub depth: usize,
+}
+
+impl Loop {
+    pub fn contains(&self, block: BlockId) -> bool {
+        self.blocks.binary_search_by_key(&block.0, |b| b.0).is_ok()
+    }
+
+    /// The preds of the header from outside the loop.
+    pub fn entries(&self, func: &Function) -> Vec<BlockId> {
+        func.blocks[self.header.0]
+            .preds
+            .iter()
+            .filter(|pred| !self.contains(**pred))
+            .copied()
+            .collect()
+    }
+
+    /// The single block outside the loop which jumps to the header, if there
+    /// is one which does nothing else.
+    pub fn preheader(&self, func: &Function) -> Option<BlockId> {
+        match self.entries(func)[..] {
+            [pred] if func.blocks[pred.0].terminator.successors() == [self.header] => Some(pred),
+            _ => None,
+        }
+    }
+}
+
+/// The natural loops of a function, found from the back edges of the CFG
+/// (edges whose target dominates their source).
+///
+/// Loops are sorted so every loop comes after the loops containing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopInfo {
+    loops: Vec<Loop>,
+    /// The innermost loop of every block
+    innermost: Vec<Option<usize>>,
+}
+
+impl LoopInfo {
+    pub fn compute(func: &Function, doms: &DominatorTree) -> LoopInfo {
+        let count = func.blocks.len();
+        let mut loops: Vec<Loop> = Vec::new();
+        for header in doms.reverse_postorder() {
+            let latches: Vec<BlockId> = func.blocks[header.0]
+                .preds
+                .iter()
+                .filter(|pred| doms.dominates(*header, **pred))
+                .copied()
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+
+            let mut in_loop = vec![false; count];
+            in_loop[header.0] = true;
+            let mut worklist = latches.clone();
+            while let Some(block) = worklist.pop() {
+                if in_loop[block.0] || !doms.is_reachable(block) {
+                    continue;
+                }
+                in_loop[block.0] = true;
+                worklist.extend(func.blocks[block.0].preds.iter().copied());
+            }
+            let mut latches = latches;
+            latches.sort_by_key(|b| b.0);
+            latches.dedup();
+            loops.push(Loop {
+                header: *header,
+                blocks: (0..count).filter(|b| in_loop[*b]).map(BlockId).collect(),
+                latches,
+                parent: None,
+                depth: 0,
+            });
+        }
+
+        // headers come in reverse postorder, so outer loops come first and
+        // the last loop containing a header is the innermost one
+        let mut innermost = vec![None; count];
+        for (idx, lp) in loops.iter().enumerate() {
+            for block in lp.blocks.iter() {
+                innermost[block.0] = Some(idx);
+            }
+        }
+        for idx in 0..loops.len() {
+            let parent = (0..idx)
+                .rev()
+                .find(|outer| loops[*outer].contains(loops[idx].header));
+            loops[idx].parent = parent;
+            loops[idx].depth = parent.map_or(1, |p| loops[p].depth + 1);
+        }
+
+        LoopInfo { loops, innermost }
+    }
+
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    /// The innermost loop `block` is part of.
+    pub fn innermost_loop(&self, block: BlockId) -> Option<&Loop> {
+        self.innermost[block.0].map(|idx| &self.loops[idx])
+    }
+
+    /// How many loops `block` is nested in, 0 outside of any loop.
+    pub fn loop_depth(&self, block: BlockId) -> usize {
+        self.innermost_loop(block).map_or(0, |lp| lp.depth)
+    }
+
+    pub fn is_header(&self, block: BlockId) -> bool {
+        self.loops.iter().any(|lp| lp.header == block)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod call_graph;
+pub mod dominators;
+pub mod liveness;
+pub mod loops;
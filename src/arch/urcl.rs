@@ -1,7 +1,13 @@
-use std::fmt::Display;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+};
 
 use crate::{
-    ir::{BinOp, Instruction, Operation, Terminator, ValueId},
+    analysis::liveness::Liveness,
+    ir::{
+        BinOp, BlockId, CastOp, Function, Instruction, Operation, Terminator, Type, UnOp, ValueId,
+    },
     regalloc::{apply_alloc, VReg},
     vcode::{InstrSelector, LabelDest, VCodeGenerator, VCodeInstr},
 };
@@ -15,10 +21,18 @@ pub const URCL_REG_5: usize = 5;
 pub const URCL_REG_6: usize = 6;
 pub const URCL_REG_7: usize = 7;
 pub const URCL_REG_8: usize = 8;
+/// The frame pointer, reserved in functions with stack slots
+pub const URCL_REG_FP: usize = URCL_REG_8;
 
 /// URCL DEFAULT CALLING CONV:
+/// - args: pushed by the caller from last to first, so on entry arg N is at
+///   `sp + N + 1` (above the return address); the caller frees them
 /// - r1: return value
-
+/// - r1-r7: free for the callee to overwrite, so the caller pushes the
+///   values it still needs after a call and pops them afterwards
+/// - r8: frame pointer, saved by the callee. Functions with `Alloca`s push
+///   it, point it at the saved copy and keep their stack slots below it, so
+///   arg N is at `r8 + N + 2`
 pub enum UrclInstr {
     PhiPlaceholder {
         dst: VReg,
@@ -30,6 +44,12 @@ pub enum UrclInstr {
         src1: VReg,
         src2: VReg,
     },
+    /// `Not` or `Neg`, which take a single source
+    UnOp {
+        op: UrclAluOp,
+        dst: VReg,
+        src: VReg,
+    },
     Jmp {
         dst: LabelDest,
     },
@@ -41,6 +61,11 @@ pub enum UrclInstr {
         dst: VReg,
         val: i64,
     },
+    /// Loads the address of a label
+    ImmLabel {
+        dst: VReg,
+        label: LabelDest,
+    },
     Mov {
         dst: VReg,
         src: VReg,
@@ -49,6 +74,65 @@ pub enum UrclInstr {
         dst: LabelDest,
     },
     Ret,
+    Psh {
+        src: VReg,
+    },
+    Pop {
+        dst: VReg,
+    },
+    /// Loads the word at `base + offset`
+    Llod {
+        dst: VReg,
+        base: StackBase,
+        offset: i64,
+    },
+    /// Stores to the word at `base + offset`
+    Lstr {
+        base: StackBase,
+        offset: i64,
+        src: VReg,
+    },
+    Lod {
+        dst: VReg,
+        ptr: VReg,
+    },
+    Str {
+        ptr: VReg,
+        src: VReg,
+    },
+    /// Pops `words` words without reading them
+    FreeStack {
+        words: usize,
+    },
+    /// Pushes `words` words without writing them
+    AllocStack {
+        words: usize,
+    },
+    /// Points the frame pointer at the top of the stack
+    SetFp,
+    /// Frees the stack slots of the frame
+    RestoreSp,
+    /// The address `offset` words below the frame pointer
+    FrameAddr {
+        dst: VReg,
+        offset: usize,
+    },
+}
+
+/// The register the offset of a `Llod` or `Lstr` is from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackBase {
+    Sp,
+    Fp,
+}
+
+impl Display for StackBase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackBase::Sp => write!(f, "sp"),
+            StackBase::Fp => write!(f, "{}", VReg::Real(URCL_REG_FP)),
+        }
+    }
 }
 
 pub enum UrclAluOp {
@@ -56,6 +140,7 @@ pub enum UrclAluOp {
     Sub,
     Mul,
     Div,
+    Sdiv,
     Mod,
     And,
     Or,
@@ -63,34 +148,57 @@ pub enum UrclAluOp {
     Not,
     Neg,
     Rsh,
+    Srs,
     Lsh,
-    Ssete,
-    Ssetne,
+    Sete,
+    Setne,
+    Setl,
+    Setle,
+    Setg,
+    Setge,
     Ssetl,
     Ssetle,
     Ssetg,
     Ssetge,
 }
 
-impl From<BinOp> for UrclAluOp {
-    fn from(op: BinOp) -> Self {
+impl UrclAluOp {
+    /// The instruction computing `op` on operands of a signed or unsigned
+    /// type. URCL has no signed remainder, so that one is `None`.
+    pub fn from_binop(op: BinOp, signed: bool) -> Option<Self> {
+        Some(match (op, signed) {
+            (BinOp::Add, _) => UrclAluOp::Add,
+            (BinOp::Sub, _) => UrclAluOp::Sub,
+            (BinOp::Mul, _) => UrclAluOp::Mul,
+            (BinOp::Div, false) => UrclAluOp::Div,
+            (BinOp::Div, true) => UrclAluOp::Sdiv,
+            (BinOp::Mod, false) => UrclAluOp::Mod,
+            (BinOp::Mod, true) => return None,
+            (BinOp::And, _) => UrclAluOp::And,
+            (BinOp::Or, _) => UrclAluOp::Or,
+            (BinOp::Xor, _) => UrclAluOp::Xor,
+            (BinOp::Eq, _) => UrclAluOp::Sete,
+            (BinOp::Ne, _) => UrclAluOp::Setne,
+            (BinOp::Lt, false) => UrclAluOp::Setl,
+            (BinOp::Le, false) => UrclAluOp::Setle,
+            (BinOp::Gt, false) => UrclAluOp::Setg,
+            (BinOp::Ge, false) => UrclAluOp::Setge,
+            (BinOp::Lt, true) => UrclAluOp::Ssetl,
+            (BinOp::Le, true) => UrclAluOp::Ssetle,
+            (BinOp::Gt, true) => UrclAluOp::Ssetg,
+            (BinOp::Ge, true) => UrclAluOp::Ssetge,
+            (BinOp::Shl, _) => UrclAluOp::Lsh,
+            (BinOp::Shr, false) => UrclAluOp::Rsh,
+            (BinOp::Shr, true) => UrclAluOp::Srs,
+        })
+    }
+}
+
+impl From<UnOp> for UrclAluOp {
+    fn from(op: UnOp) -> Self {
         match op {
-            BinOp::Add => UrclAluOp::Add,
-            BinOp::Sub => UrclAluOp::Sub,
-            BinOp::Mul => UrclAluOp::Mul,
-            BinOp::Div => UrclAluOp::Div,
-            BinOp::Mod => UrclAluOp::Mod,
-            BinOp::And => UrclAluOp::And,
-            BinOp::Or => UrclAluOp::Or,
-            BinOp::Xor => UrclAluOp::Xor,
-            BinOp::Eq => UrclAluOp::Ssete,
-            BinOp::Ne => UrclAluOp::Ssetne,
-            BinOp::Lt => UrclAluOp::Ssetl,
-            BinOp::Le => UrclAluOp::Ssetle,
-            BinOp::Gt => UrclAluOp::Ssetg,
-            BinOp::Ge => UrclAluOp::Ssetge,
-            BinOp::Shl => UrclAluOp::Lsh,
-            BinOp::Shr => UrclAluOp::Rsh,
+            UnOp::Neg => UrclAluOp::Neg,
+            UnOp::Not => UrclAluOp::Not,
         }
     }
 }
@@ -105,7 +213,6 @@ impl VCodeInstr for UrclInstr {
             VReg::Real(URCL_REG_5),
             VReg::Real(URCL_REG_6),
             VReg::Real(URCL_REG_7),
-            VReg::Real(URCL_REG_8),
         ]
     }
 
@@ -122,7 +229,7 @@ impl VCodeInstr for UrclInstr {
             Self::Beq { src1, .. } => {
                 regalloc.add_use(*src1);
             }
-            Self::Imm { dst, .. } => {
+            Self::Imm { dst, .. } | Self::ImmLabel { dst, .. } => {
                 regalloc.add_def(*dst);
             }
             Self::Mov { dst, src } => {
@@ -130,6 +237,24 @@ impl VCodeInstr for UrclInstr {
                 regalloc.add_use(*src);
                 regalloc.coalesce_move(*src, *dst);
             }
+            Self::UnOp { dst, src, .. } => {
+                regalloc.add_def(*dst);
+                regalloc.add_use(*src);
+            }
+            Self::Psh { src } | Self::Lstr { src, .. } => {
+                regalloc.add_use(*src);
+            }
+            Self::Llod { dst, .. } | Self::FrameAddr { dst, .. } | Self::Pop { dst } => {
+                regalloc.add_def(*dst);
+            }
+            Self::Lod { dst, ptr } => {
+                regalloc.add_def(*dst);
+                regalloc.add_use(*ptr);
+            }
+            Self::Str { ptr, src } => {
+                regalloc.add_use(*ptr);
+                regalloc.add_use(*src);
+            }
             _ => (),
         }
     }
@@ -147,16 +272,47 @@ impl VCodeInstr for UrclInstr {
             Self::Beq { src1, .. } => {
                 apply_alloc(src1, allocs);
             }
-            Self::Imm { dst, .. } => {
+            Self::Imm { dst, .. } | Self::ImmLabel { dst, .. } => {
                 apply_alloc(dst, allocs);
             }
-            Self::Mov { dst, src } => {
+            Self::Mov { dst, src } | Self::UnOp { dst, src, .. } => {
                 apply_alloc(dst, allocs);
                 apply_alloc(src, allocs);
             }
+            Self::Psh { src } | Self::Lstr { src, .. } => {
+                apply_alloc(src, allocs);
+            }
+            Self::Llod { dst, .. } | Self::FrameAddr { dst, .. } | Self::Pop { dst } => {
+                apply_alloc(dst, allocs);
+            }
+            Self::Lod { dst, ptr } => {
+                apply_alloc(dst, allocs);
+                apply_alloc(ptr, allocs);
+            }
+            Self::Str { ptr, src } => {
+                apply_alloc(ptr, allocs);
+                apply_alloc(src, allocs);
+            }
             _ => (),
         }
     }
+
+    fn jump_targets(&self) -> Vec<usize> {
+        match self {
+            Self::Jmp {
+                dst: LabelDest::Block(b),
+            }
+            | Self::Beq {
+                dst: LabelDest::Block(b),
+                ..
+            } => vec![*b],
+            _ => vec![],
+        }
+    }
+
+    fn falls_through(&self) -> bool {
+        !matches!(self, Self::Jmp { .. } | Self::Ret)
+    }
 }
 
 impl Display for UrclInstr {
@@ -170,12 +326,27 @@ impl Display for UrclInstr {
             } => {
                 write!(f, "{} {} {} {}", op, dst, src1, src2)
             }
+            UrclInstr::UnOp { op, dst, src } => write!(f, "{} {} {}", op, dst, src),
             UrclInstr::Jmp { dst } => write!(f, "jmp {}", dst),
             UrclInstr::Imm { dst, val } => write!(f, "imm {} {}", dst, val),
+            UrclInstr::ImmLabel { dst, label } => write!(f, "imm {} {}", dst, label),
             UrclInstr::Beq { src1, dst } => write!(f, "bgr {} {} 0", dst, src1),
             UrclInstr::Mov { dst, src } => write!(f, "mov {} {}", dst, src),
             UrclInstr::Cal { dst } => write!(f, "cal {}", dst),
             UrclInstr::Ret => write!(f, "ret"),
+            UrclInstr::Psh { src } => write!(f, "psh {}", src),
+            UrclInstr::Pop { dst } => write!(f, "pop {}", dst),
+            UrclInstr::Llod { dst, base, offset } => write!(f, "llod {} {} {}", dst, base, offset),
+            UrclInstr::Lstr { base, offset, src } => write!(f, "lstr {} {} {}", base, offset, src),
+            UrclInstr::Lod { dst, ptr } => write!(f, "lod {} {}", dst, ptr),
+            UrclInstr::Str { ptr, src } => write!(f, "str {} {}", ptr, src),
+            UrclInstr::FreeStack { words } => write!(f, "add sp sp {}", words),
+            UrclInstr::AllocStack { words } => write!(f, "sub sp sp {}", words),
+            UrclInstr::SetFp => write!(f, "mov {} sp", StackBase::Fp),
+            UrclInstr::RestoreSp => write!(f, "mov sp {}", StackBase::Fp),
+            UrclInstr::FrameAddr { dst, offset } => {
+                write!(f, "sub {} {} {}", dst, StackBase::Fp, offset)
+            }
             UrclInstr::PhiPlaceholder { dst, ops } => write!(
                 f,
                 "phi {} {}",
@@ -196,6 +367,7 @@ impl Display for UrclAluOp {
             UrclAluOp::Sub => write!(f, "sub"),
             UrclAluOp::Mul => write!(f, "mul"),
             UrclAluOp::Div => write!(f, "div"),
+            UrclAluOp::Sdiv => write!(f, "sdiv"),
             UrclAluOp::Mod => write!(f, "mod"),
             UrclAluOp::And => write!(f, "and"),
             UrclAluOp::Or => write!(f, "or"),
@@ -203,9 +375,14 @@ impl Display for UrclAluOp {
             UrclAluOp::Not => write!(f, "not"),
             UrclAluOp::Neg => write!(f, "neg"),
             UrclAluOp::Rsh => write!(f, "rsh"),
+            UrclAluOp::Srs => write!(f, "srs"),
             UrclAluOp::Lsh => write!(f, "lsh"),
-            UrclAluOp::Ssete => write!(f, "ssete"),
-            UrclAluOp::Ssetne => write!(f, "ssetne"),
+            UrclAluOp::Sete => write!(f, "sete"),
+            UrclAluOp::Setne => write!(f, "setne"),
+            UrclAluOp::Setl => write!(f, "setl"),
+            UrclAluOp::Setle => write!(f, "setle"),
+            UrclAluOp::Setg => write!(f, "setg"),
+            UrclAluOp::Setge => write!(f, "setge"),
             UrclAluOp::Ssetl => write!(f, "ssetl"),
             UrclAluOp::Ssetle => write!(f, "ssetle"),
             UrclAluOp::Ssetg => write!(f, "ssetg"),
@@ -215,7 +392,20 @@ impl Display for UrclAluOp {
 }
 
 #[derive(Default)]
-pub struct UrclSelector;
+pub struct UrclSelector {
+    /// How far below the frame pointer the stack slot of every `Alloca` of
+    /// the current function is
+    slots: HashMap<ValueId, usize>,
+    frame_size: usize,
+    /// The types of the values of the current function
+    types: Vec<Type>,
+    /// The vregs made for intermediate results so far, numbered after the
+    /// values'
+    temps: usize,
+    /// The values to save around every call of the current function not
+    /// selected yet, in order
+    live_across_calls: VecDeque<Vec<ValueId>>,
+}
 
 impl InstrSelector for UrclSelector {
     type Instr = UrclInstr;
@@ -230,16 +420,42 @@ impl InstrSelector for UrclSelector {
             Operation::BinOp(op, lhs, rhs) => {
                 let src1 = self.get_vreg(*lhs);
                 let src2 = self.get_vreg(*rhs);
-                gen.push_instr(UrclInstr::AluOp {
-                    op: (*op).into(),
-                    dst,
-                    src1,
-                    src2,
-                });
+                // the signedness of the operands picks the instruction
+                let signed = self.types[lhs.0].is_signed();
+                match UrclAluOp::from_binop(*op, signed) {
+                    Some(op) => gen.push_instr(UrclInstr::AluOp {
+                        op,
+                        dst,
+                        src1,
+                        src2,
+                    }),
+                    // a - (a / b) * b, with division rounding towards zero
+                    None => {
+                        let quot = self.push_temp();
+                        for (op, dst, src1, src2) in [
+                            (UrclAluOp::Sdiv, quot, src1, src2),
+                            (UrclAluOp::Mul, quot, quot, src2),
+                            (UrclAluOp::Sub, dst, src1, quot),
+                        ] {
+                            gen.push_instr(UrclInstr::AluOp {
+                                op,
+                                dst,
+                                src1,
+                                src2,
+                            });
+                        }
+                    }
+                }
             }
             Operation::Integer(val) => {
                 gen.push_instr(UrclInstr::Imm { dst, val: *val });
             }
+            Operation::GlobalAddr(global) => {
+                gen.push_instr(UrclInstr::ImmLabel {
+                    dst,
+                    label: LabelDest::Global(global.0),
+                });
+            }
             Operation::LoadVar(_) | Operation::StoreVar(..) => (), // THESE NEVER GET EXECUTED (removed in algos::lower_to_ssa::lower())
             Operation::Phi(vals) => {
                 gen.push_instr(UrclInstr::PhiPlaceholder {
@@ -247,7 +463,151 @@ impl InstrSelector for UrclSelector {
                     ops: vals.iter().map(|v| self.get_vreg(*v)).collect(),
                 });
             }
-            _ => todo!(),
+            Operation::Arg(idx) => {
+                // skip the return address, and the saved frame pointer
+                let (base, offset) = if self.frame_size > 0 {
+                    (StackBase::Fp, *idx as i64 + 2)
+                } else {
+                    (StackBase::Sp, *idx as i64 + 1)
+                };
+                gen.push_instr(UrclInstr::Llod { dst, base, offset });
+            }
+            Operation::Alloca(_) => {
+                let offset = self.slots[&instr.yielded.unwrap()];
+                gen.push_instr(UrclInstr::FrameAddr { dst, offset });
+            }
+            Operation::Load(ptr) => match self.slots.get(ptr) {
+                Some(slot) => gen.push_instr(UrclInstr::Llod {
+                    dst,
+                    base: StackBase::Fp,
+                    offset: -(*slot as i64),
+                }),
+                None => gen.push_instr(UrclInstr::Lod {
+                    dst,
+                    ptr: self.get_vreg(*ptr),
+                }),
+            },
+            Operation::Store(ptr, val) => {
+                let src = self.get_vreg(*val);
+                match self.slots.get(ptr) {
+                    Some(slot) => gen.push_instr(UrclInstr::Lstr {
+                        base: StackBase::Fp,
+                        offset: -(*slot as i64),
+                        src,
+                    }),
+                    None => gen.push_instr(UrclInstr::Str {
+                        ptr: self.get_vreg(*ptr),
+                        src,
+                    }),
+                }
+            }
+            // every value takes a single word
+            Operation::Gep(ptr, idx) => {
+                gen.push_instr(UrclInstr::AluOp {
+                    op: UrclAluOp::Add,
+                    dst,
+                    src1: self.get_vreg(*ptr),
+                    src2: self.get_vreg(*idx),
+                });
+            }
+            Operation::Copy(val) => {
+                gen.push_instr(UrclInstr::Mov {
+                    dst,
+                    src: self.get_vreg(*val),
+                });
+            }
+            Operation::UnOp(op, val) => {
+                gen.push_instr(UrclInstr::UnOp {
+                    op: (*op).into(),
+                    dst,
+                    src: self.get_vreg(*val),
+                });
+            }
+            Operation::Cast(op, val, to) => {
+                let from = self.types[val.0].clone();
+                let src = self.get_vreg(*val);
+                // arithmetic on narrow types leaves whatever it carries out
+                // above their width, so the source is first wrapped to the
+                // bits `CastOp::eval` extends
+                let (bits, signed) = match op {
+                    CastOp::ZExt => (from.bits(), false),
+                    CastOp::SExt => (from.bits(), true),
+                    _ => (from.bits(), from.is_signed()),
+                };
+                let fits = to.bits() >= 64
+                    || if to.is_signed() == signed {
+                        to.bits() >= bits
+                    } else {
+                        !signed && to.bits() > bits
+                    };
+                if to.bits() <= bits {
+                    // only the bits `to` keeps matter
+                    self.wrap(gen, dst, src, to.bits(), to.is_signed());
+                } else if fits {
+                    self.wrap(gen, dst, src, bits, signed);
+                } else {
+                    let extended = self.push_temp();
+                    self.wrap(gen, extended, src, bits, signed);
+                    self.wrap(gen, dst, extended, to.bits(), to.is_signed());
+                }
+            }
+            Operation::Select(cond, a, b) => {
+                // b ^ ((a ^ b) & mask), where the mask has every bit set if
+                // the condition holds
+                let (a, b) = (self.get_vreg(*a), self.get_vreg(*b));
+                let mask = self.push_temp();
+                let diff = self.push_temp();
+                gen.push_instr(UrclInstr::AluOp {
+                    op: UrclAluOp::Setne,
+                    dst: mask,
+                    src1: self.get_vreg(*cond),
+                    src2: VReg::Real(URCL_REG_ZR),
+                });
+                for (op, dst, src1, src2) in [
+                    (UrclAluOp::Xor, diff, a, b),
+                    (UrclAluOp::And, diff, diff, mask),
+                    (UrclAluOp::Xor, dst, diff, b),
+                ] {
+                    gen.push_instr(UrclInstr::AluOp {
+                        op,
+                        dst,
+                        src1,
+                        src2,
+                    });
+                }
+            }
+            Operation::Call(func, args) => {
+                let saved: Vec<VReg> = self
+                    .live_across_calls
+                    .pop_front()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|val| self.get_vreg(val))
+                    .collect();
+                for src in saved.iter() {
+                    gen.push_instr(UrclInstr::Psh { src: *src });
+                }
+                for arg in args.iter().rev() {
+                    gen.push_instr(UrclInstr::Psh {
+                        src: self.get_vreg(*arg),
+                    });
+                }
+                gen.push_instr(UrclInstr::Cal {
+                    dst: LabelDest::Function(func.0),
+                });
+                if !args.is_empty() {
+                    gen.push_instr(UrclInstr::FreeStack { words: args.len() });
+                }
+                if instr.yielded.is_some() {
+                    gen.push_instr(UrclInstr::Mov {
+                        dst,
+                        src: VReg::Real(URCL_REG_1),
+                    });
+                }
+                for dst in saved.iter().rev() {
+                    gen.push_instr(UrclInstr::Pop { dst: *dst });
+                }
+            }
         }
     }
 
@@ -272,24 +632,166 @@ impl InstrSelector for UrclSelector {
                     dst: VReg::Real(URCL_REG_1),
                     src: self.get_vreg(*val),
                 });
+                self.leave_frame(gen);
                 gen.push_instr(UrclInstr::Ret);
             }
             _ => todo!(),
         }
     }
 
-    fn get_post_function_instructions(&mut self, gen: &mut VCodeGenerator<Self::Instr>) {
+    fn select_tail_call(
+        &mut self,
+        gen: &mut VCodeGenerator<Self::Instr>,
+        call: &Instruction,
+    ) -> bool {
+        let Operation::Call(func, args) = &call.operation else {
+            return false;
+        };
+        // the callee's args go in our own arg slots, which our caller frees,
+        // and our frame is freed before the jump while the args may still
+        // point into it
+        if args.len() > gen.arg_count() || self.frame_size > 0 {
+            return false;
+        }
+        // nothing is live after a call in tail position
+        self.live_across_calls.pop_front();
+        self.leave_frame(gen);
+        for (idx, arg) in args.iter().enumerate() {
+            gen.push_instr(UrclInstr::Lstr {
+                base: StackBase::Sp,
+                offset: idx as i64 + 1,
+                src: self.get_vreg(*arg),
+            });
+        }
+        // the callee leaves its result in r1 and returns to our caller
+        gen.push_instr(UrclInstr::Jmp {
+            dst: LabelDest::Function(func.0),
+        });
+        true
+    }
+
+    fn get_post_function_instructions(&mut self, _gen: &mut VCodeGenerator<Self::Instr>) {
         
     }
 
+    fn begin_function(&mut self, _gen: &mut VCodeGenerator<Self::Instr>, func: &Function) {
+        self.slots.clear();
+        self.frame_size = 0;
+        self.types = func.values.iter().map(|val| val.ty.clone()).collect();
+        self.temps = 0;
+        self.live_across_calls = live_across_calls(func);
+        for instr in func.blocks.iter().flat_map(|bb| bb.instructions.iter()) {
+            if let (Some(val), Operation::Alloca(ty)) = (instr.yielded, &instr.operation) {
+                self.frame_size += ty.size().max(1);
+                self.slots.insert(val, self.frame_size);
+            }
+        }
+    }
+
     fn get_pre_function_instructions(&mut self, gen: &mut VCodeGenerator<Self::Instr>) {
-        
+        if self.frame_size > 0 {
+            gen.push_instr(UrclInstr::Psh {
+                src: VReg::Real(URCL_REG_FP),
+            });
+            gen.push_instr(UrclInstr::SetFp);
+            gen.push_instr(UrclInstr::AllocStack {
+                words: self.frame_size,
+            });
+        }
     }
 }
 
 impl UrclSelector {
+    /// Frees the stack slots and restores the caller's frame pointer, if the
+    /// function has any.
+    fn leave_frame(&self, gen: &mut VCodeGenerator<UrclInstr>) {
+        if self.frame_size > 0 {
+            gen.push_instr(UrclInstr::RestoreSp);
+            gen.push_instr(UrclInstr::Pop {
+                dst: VReg::Real(URCL_REG_FP),
+            });
+        }
+    }
+
     #[inline]
     pub fn get_vreg(&self, val: ValueId) -> VReg {
         VReg::Virtual(val.0)
     }
+
+    /// A vreg no value of the current function maps to.
+    fn push_temp(&mut self) -> VReg {
+        self.temps += 1;
+        VReg::Virtual(self.types.len() + self.temps - 1)
+    }
+
+    /// Puts the low `bits` bits of `src` in `dst`, sign extended if `signed`
+    /// and zero extended otherwise.
+    fn wrap(
+        &mut self,
+        gen: &mut VCodeGenerator<UrclInstr>,
+        dst: VReg,
+        src: VReg,
+        bits: u32,
+        signed: bool,
+    ) {
+        if bits >= 64 {
+            gen.push_instr(UrclInstr::Mov { dst, src });
+            return;
+        }
+        let tmp = self.push_temp();
+        gen.push_instr(UrclInstr::Imm {
+            dst: tmp,
+            val: (1 << bits) - 1,
+        });
+        gen.push_instr(UrclInstr::AluOp {
+            op: UrclAluOp::And,
+            dst,
+            src1: src,
+            src2: tmp,
+        });
+        if signed {
+            // (x ^ sign) - sign flips the sign bit, then borrows from every
+            // bit above it if it was set
+            gen.push_instr(UrclInstr::Imm {
+                dst: tmp,
+                val: 1 << (bits - 1),
+            });
+            for op in [UrclAluOp::Xor, UrclAluOp::Sub] {
+                gen.push_instr(UrclInstr::AluOp {
+                    op,
+                    dst,
+                    src1: dst,
+                    src2: tmp,
+                });
+            }
+        }
+    }
+}
+
+/// The values live after every call of `func`, in order, leaving out the
+/// result of the call itself.
+fn live_across_calls(func: &Function) -> VecDeque<Vec<ValueId>> {
+    let liveness = Liveness::compute(func);
+    let mut calls = VecDeque::new();
+    for (id, block) in func.blocks.iter().enumerate() {
+        let mut live = liveness.live_out(BlockId(id)).clone();
+        live.extend(block.terminator.operand());
+        let mut block_calls = Vec::new();
+        for instr in block.instructions.iter().rev() {
+            if let Some(val) = instr.yielded {
+                live.remove(&val);
+            }
+            if let Operation::Call(..) = instr.operation {
+                let mut vals: Vec<ValueId> = live.iter().copied().collect();
+                vals.sort_by_key(|val| val.0);
+                block_calls.push(vals);
+            }
+            // Φ operands are read in the preds
+            if !matches!(instr.operation, Operation::Phi(_)) {
+                live.extend(instr.operation.operands());
+            }
+        }
+        calls.extend(block_calls.into_iter().rev());
+    }
+    calls
 }
@@ -1,14 +1,27 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::ir::{
-    BasicBlock, BinOp, BlockId, Function, FunctionId, Instruction, Linkage, Module, Operation,
-    Terminator, Type, Value, ValueId, Variable, VariableId,
+    Algo, BasicBlock, BinOp, BlockId, CastOp, Function, FunctionId, Global, GlobalId, Instruction,
+    Linkage, Module, Operation, Terminator, Type, UnOp, Value, ValueId, Variable, VariableId,
 };
 
 pub struct ModuleBuilder {
     module: Module,
     current_func: Option<FunctionId>,
     current_block: Option<BlockId>,
+    ssa: HashMap<FunctionId, SsaState>,
+    emitted_var_access: bool,
+    args: HashMap<(FunctionId, usize), ValueId>,
+}
+
+/// Per-function state of the on-the-fly SSA construction done by
+/// `read_variable`/`write_variable`/`seal_block`.
+#[derive(Default)]
+struct SsaState {
+    current_defs: HashMap<(VariableId, BlockId), ValueId>,
+    incomplete_phis: HashMap<BlockId, Vec<(VariableId, ValueId)>>,
+    sealed: HashSet<BlockId>,
+    zeros: HashMap<Type, ValueId>,
 }
 
 impl ModuleBuilder {
@@ -17,6 +30,9 @@ impl ModuleBuilder {
             module: Module::new(name, Vec::new()),
             current_block: None,
             current_func: None,
+            ssa: HashMap::new(),
+            emitted_var_access: false,
+            args: HashMap::new(),
         }
     }
 
@@ -24,8 +40,15 @@ impl ModuleBuilder {
         println!("{}", self.module);
     }
 
+    /// Builds the module. If it was built entirely with `read_variable` and
+    /// `write_variable` rather than loads and stores, it is already in SSA
+    /// form and marked as such.
     pub fn build(&self) -> Module {
-        self.module.clone()
+        let mut module = self.module.clone();
+        if !self.ssa.is_empty() && !self.emitted_var_access {
+            module.algos_run.push(Algo::SsaConstruction);
+        }
+        module
     }
 
     pub fn build_nossa(&self) -> Module {
@@ -50,6 +73,32 @@ impl ModuleBuilder {
         FunctionId(self.module.functions.len() - 1)
     }
 
+    /// A global holding `init`, or zeros if `None`. See `Global::init` for
+    /// arrays.
+    pub fn push_global(
+        &mut self,
+        name: &str,
+        ty: Type,
+        linkage: Option<Linkage>,
+        mutable: bool,
+        init: Option<Vec<i64>>,
+    ) -> GlobalId {
+        self.module.globals.push(Global {
+            name: name.to_string(),
+            ty,
+            linkage: linkage.unwrap_or(Linkage::Private),
+            mutable,
+            init,
+        });
+        GlobalId(self.module.globals.len() - 1)
+    }
+
+    /// A private constant holding the bytes of `text`, terminated by a zero.
+    pub fn push_string(&mut self, name: &str, text: &str) -> GlobalId {
+        let bytes = text.bytes().chain([0]).map(i64::from).collect();
+        self.push_global(name, Type::Integer(8, false), None, false, Some(bytes))
+    }
+
     pub fn push_block(&mut self) -> BlockId {
         let id = self
             .module
@@ -130,6 +179,7 @@ impl ModuleBuilder {
     }
 
     pub fn build_store(&mut self, var: VariableId, value: ValueId) {
+        self.emitted_var_access = true;
         let cur_blk = self.current_block.unwrap();
 
         let block = self.get_block_mut(cur_blk);
@@ -143,6 +193,7 @@ impl ModuleBuilder {
     }
 
     pub fn build_load(&mut self, var: VariableId) -> ValueId {
+        self.emitted_var_access = true;
         let val = self.push_value(
             self.get_func(self.current_func.unwrap()).variables[var.0]
                 .ty
@@ -156,6 +207,131 @@ impl ModuleBuilder {
         val
     }
 
+    /// A stack slot for a value of type `ty`, returning a pointer to it.
+    pub fn build_alloca(&mut self, ty: Type) -> ValueId {
+        let val = self.push_value(Type::Pointer(Box::new(ty.clone())));
+        self.get_block_mut(self.current_block.unwrap())
+            .instructions
+            .push(Instruction {
+                yielded: Some(val),
+                operation: Operation::Alloca(ty),
+            });
+        val
+    }
+
+    /// Reads the value `ptr` points to.
+    pub fn build_load_ptr(&mut self, ptr: ValueId) -> ValueId {
+        let ty = self.get_func(self.current_func.unwrap()).values[ptr.0]
+            .ty
+            .pointee()
+            .expect("load through a non-pointer")
+            .clone();
+        let val = self.push_value(ty);
+        let cur_fn = self.get_func_mut(self.current_func.unwrap());
+        cur_fn.values[ptr.0].children.push(val);
+        self.get_block_mut(self.current_block.unwrap())
+            .instructions
+            .push(Instruction {
+                yielded: Some(val),
+                operation: Operation::Load(ptr),
+            });
+        val
+    }
+
+    /// Writes `value` to where `ptr` points.
+    pub fn build_store_ptr(&mut self, ptr: ValueId, value: ValueId) {
+        self.get_block_mut(self.current_block.unwrap())
+            .instructions
+            .push(Instruction {
+                yielded: None,
+                operation: Operation::Store(ptr, value),
+            });
+    }
+
+    /// `ptr` moved by `index` values of the type it points to.
+    pub fn build_gep(&mut self, ptr: ValueId, index: ValueId) -> ValueId {
+        let ty = self.get_func(self.current_func.unwrap()).values[ptr.0]
+            .ty
+            .clone();
+        let val = self.push_value(ty);
+        let cur_fn = self.get_func_mut(self.current_func.unwrap());
+        cur_fn.values[ptr.0].children.push(val);
+        cur_fn.values[index.0].children.push(val);
+        self.get_block_mut(self.current_block.unwrap())
+            .instructions
+            .push(Instruction {
+                yielded: Some(val),
+                operation: Operation::Gep(ptr, index),
+            });
+        val
+    }
+
+    /// A pointer to `global`.
+    pub fn build_global_addr(&mut self, global: GlobalId) -> ValueId {
+        let ty = Type::Pointer(Box::new(self.module.globals[global.0].ty.clone()));
+        self.push_instruction(ty, Operation::GlobalAddr(global))
+    }
+
+    /// `op` applied to `value`, of the same type.
+    pub fn build_unop(&mut self, op: UnOp, value: ValueId) -> ValueId {
+        let ty = self.get_func(self.current_func.unwrap()).values[value.0]
+            .ty
+            .clone();
+        self.push_instruction(ty, Operation::UnOp(op, value))
+    }
+
+    /// `value` converted to `ty` by `op`.
+    pub fn build_cast(&mut self, op: CastOp, value: ValueId, ty: Type) -> ValueId {
+        self.push_instruction(ty.clone(), Operation::Cast(op, value, ty))
+    }
+
+    /// `a` if `cond` is non-zero, else `b`.
+    pub fn build_select(&mut self, cond: ValueId, a: ValueId, b: ValueId) -> ValueId {
+        let ty = self.get_func(self.current_func.unwrap()).values[a.0]
+            .ty
+            .clone();
+        self.push_instruction(ty, Operation::Select(cond, a, b))
+    }
+
+    /// Appends `operation` yielding a new value of type `ty` to the current
+    /// block.
+    fn push_instruction(&mut self, ty: Type, operation: Operation) -> ValueId {
+        let val = self.push_value(ty);
+        let cur_fn = self.get_func_mut(self.current_func.unwrap());
+        for operand in operation.operands() {
+            cur_fn.values[operand.0].children.push(val);
+        }
+        self.get_block_mut(self.current_block.unwrap())
+            .instructions
+            .push(Instruction {
+                yielded: Some(val),
+                operation,
+            });
+        val
+    }
+
+    /// The value of the current function's argument at `index`, defined at
+    /// the top of the entry block the first time it's asked for.
+    pub fn get_arg(&mut self, index: usize) -> ValueId {
+        let func_id = self.current_func.unwrap();
+        if let Some(val) = self.args.get(&(func_id, index)) {
+            return *val;
+        }
+        let func = self.get_func_mut(func_id);
+        let ty = func.args[index].1.clone();
+        let val = func.push_value(ty);
+        func.values[val.0].owner = BlockId(0);
+        func.blocks[0].instructions.insert(
+            0,
+            Instruction {
+                yielded: Some(val),
+                operation: Operation::Arg(index),
+            },
+        );
+        self.args.insert((func_id, index), val);
+        val
+    }
+
     pub fn set_terminator(&mut self, terminator: Terminator) {
         let cur_blk = self.current_block.unwrap();
         match terminator {
@@ -172,6 +348,192 @@ impl ModuleBuilder {
         self.get_block_mut(self.current_block.unwrap()).terminator = terminator;
     }
 
+    /// Assigns `value` to `var` in the current block, building SSA form
+    /// directly instead of emitting a store (Braun et al., "Simple and
+    /// Efficient Construction of Static Single Assignment Form").
+    ///
+    /// A function should use either `read_variable`/`write_variable` or
+    /// `build_load`/`build_store`, not both.
+    pub fn write_variable(&mut self, var: VariableId, value: ValueId) {
+        let block = self.current_block.unwrap();
+        self.ssa_state().current_defs.insert((var, block), value);
+    }
+
+    /// Returns the value of `var` reaching the current block, inserting Φs
+    /// where needed. Reading a variable that was never written yields zero.
+    pub fn read_variable(&mut self, var: VariableId) -> ValueId {
+        let block = self.current_block.unwrap();
+        self.read_variable_in(var, block)
+    }
+
+    /// Declares that all preds of `block` are known, i.e. every block
+    /// jumping to it has its terminator set. Φs in unsealed blocks are left
+    /// incomplete until then.
+    pub fn seal_block(&mut self, block: BlockId) {
+        let incomplete = self
+            .ssa_state()
+            .incomplete_phis
+            .remove(&block)
+            .unwrap_or_default();
+        for (var, phi) in incomplete {
+            self.add_phi_operands(var, phi, block);
+        }
+        self.ssa_state().sealed.insert(block);
+    }
+
+    fn ssa_state(&mut self) -> &mut SsaState {
+        self.ssa.entry(self.current_func.unwrap()).or_default()
+    }
+
+    fn read_variable_in(&mut self, var: VariableId, block: BlockId) -> ValueId {
+        if let Some(val) = self.ssa_state().current_defs.get(&(var, block)) {
+            return *val;
+        }
+        let preds = self.get_block(block).preds.clone();
+        let val = if !self.ssa_state().sealed.contains(&block) {
+            let phi = self.push_phi(var, block);
+            self.ssa_state()
+                .incomplete_phis
+                .entry(block)
+                .or_default()
+                .push((var, phi));
+            phi
+        } else if preds.is_empty() {
+            let ty = self.get_func(self.current_func.unwrap()).variables[var.0]
+                .ty
+                .clone();
+            self.zero(ty)
+        } else if preds.len() == 1 {
+            self.read_variable_in(var, preds[0])
+        } else {
+            // break cycles through loops with an operandless Φ
+            let phi = self.push_phi(var, block);
+            self.ssa_state().current_defs.insert((var, block), phi);
+            self.add_phi_operands(var, phi, block)
+        };
+        self.ssa_state().current_defs.insert((var, block), val);
+        val
+    }
+
+    fn add_phi_operands(&mut self, var: VariableId, phi: ValueId, block: BlockId) -> ValueId {
+        for pred in self.get_block(block).preds.clone() {
+            let val = self.read_variable_in(var, pred);
+            let func = self.get_func_mut(self.current_func.unwrap());
+            func.values[val.0].children.push(phi);
+            if let Some(Operation::Phi(vals)) = find_def(func, block, phi) {
+                vals.push(val);
+            }
+        }
+        self.try_remove_trivial_phi(phi, block)
+    }
+
+    /// Removes `phi` if it only merges itself and one other value, replacing
+    /// it by that value, and recursively retries the Φs that used it.
+    fn try_remove_trivial_phi(&mut self, phi: ValueId, block: BlockId) -> ValueId {
+        let func = self.get_func_mut(self.current_func.unwrap());
+        let Some(Operation::Phi(vals)) = find_def(func, block, phi) else {
+            return phi;
+        };
+        let mut same = None;
+        for val in vals.iter() {
+            if Some(*val) == same || *val == phi {
+                continue;
+            }
+            if same.is_some() {
+                return phi;
+            }
+            same = Some(*val);
+        }
+        let vals = vals.clone();
+        let same = match same {
+            Some(same) => same,
+            None => {
+                // unreachable block or a Φ only fed by itself
+                let ty = self.get_func(self.current_func.unwrap()).values[phi.0]
+                    .ty
+                    .clone();
+                self.zero(ty)
+            }
+        };
+
+        let func = self.get_func_mut(self.current_func.unwrap());
+        let mut users = Vec::new();
+        for (id, bb) in func.blocks.iter().enumerate() {
+            for instr in bb.instructions.iter() {
+                if let (Some(user), Operation::Phi(ops)) = (instr.yielded, &instr.operation) {
+                    if user != phi && ops.contains(&phi) {
+                        users.push((user, BlockId(id)));
+                    }
+                }
+            }
+        }
+        func.blocks[block.0]
+            .instructions
+            .retain(|instr| instr.yielded != Some(phi));
+        for val in vals {
+            func.values[val.0].children.retain(|child| *child != phi);
+        }
+        func.replace_children_with(phi, same);
+
+        let state = self.ssa_state();
+        for def in state.current_defs.values_mut() {
+            if *def == phi {
+                *def = same;
+            }
+        }
+        for incomplete in state.incomplete_phis.values_mut() {
+            incomplete.retain(|(_, val)| *val != phi);
+        }
+
+        for (user, user_block) in users {
+            self.try_remove_trivial_phi(user, user_block);
+        }
+        same
+    }
+
+    /// Inserts an operandless Φ for `var` after the existing Φs of `block`.
+    fn push_phi(&mut self, var: VariableId, block: BlockId) -> ValueId {
+        let func = self.get_func_mut(self.current_func.unwrap());
+        let phi = func.push_value(func.variables[var.0].ty.clone());
+        func.values[phi.0].owner = block;
+        let instrs = &mut func.blocks[block.0].instructions;
+        let pos = instrs
+            .iter()
+            .take_while(|instr| matches!(instr.operation, Operation::Phi(_)))
+            .count();
+        instrs.insert(
+            pos,
+            Instruction {
+                yielded: Some(phi),
+                operation: Operation::Phi(vec![]),
+            },
+        );
+        phi
+    }
+
+    /// A zero of the given type, defined at the top of the entry block.
+    fn zero(&mut self, ty: Type) -> ValueId {
+        if let Some(val) = self.ssa_state().zeros.get(&ty) {
+            return *val;
+        }
+        let func = self.get_func_mut(self.current_func.unwrap());
+        let val = func.push_value(ty.clone());
+        let instrs = &mut func.blocks[0].instructions;
+        let pos = instrs
+            .iter()
+            .take_while(|instr| matches!(instr.operation, Operation::Phi(_)))
+            .count();
+        instrs.insert(
+            pos,
+            Instruction {
+                yielded: Some(val),
+                operation: Operation::Integer(0),
+            },
+        );
+        self.ssa_state().zeros.insert(ty, val);
+        val
+    }
+
     // internal function to init values
     #[inline]
     fn push_value(&mut self, ty: Type) -> ValueId {
@@ -186,3 +548,11 @@ impl ModuleBuilder {
         ValueId(self.get_func(self.current_func.unwrap()).values.len() - 1)
     }
 }
+
+fn find_def(func: &mut Function, block: BlockId, val: ValueId) -> Option<&mut Operation> {
+    func.blocks[block.0]
+        .instructions
+        .iter_mut()
+        .find(|instr| instr.yielded == Some(val))
+        .map(|instr| &mut instr.operation)
+}
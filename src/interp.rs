@@ -0,0 +1,432 @@
+use std::fmt::Display;
+
+use crate::ir::{BlockId, Function, FunctionId, Module, Operation, Terminator, ValueId};
+
+/// A reference interpreter for `Module`s, usable at every lowering stage so
+/// the output of a transform can be checked against its input:
+///
+/// - before SSA construction, variables are read and written by
+///   `LoadVar`/`StoreVar` and start out as zero,
+/// - in SSA form, every `Φ` takes the operand matching the edge the block was
+///   entered through,
+/// - after phi lowering, the `par_moves` of a block are executed in parallel
+///   when it is left.
+///
+/// Every value is truncated to the width of its `Type::Integer` when it is
+/// defined, and arithmetic follows `BinOp::eval`.
+///
+/// Memory is a stack of words growing with every `Alloca`, and pointers are
+/// indices into it. Address 0 is never allocated, so null pointers fault.
+/// The globals of the module come right after it, initialized once when the
+/// interpreter is made and kept across calls to `run`.
+pub struct Interpreter<'a> {
+    module: &'a Module,
+    step_limit: Option<usize>,
+    steps: usize,
+    memory: Vec<i64>,
+    /// The address of every global
+    globals: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterpError {
+    /// More instructions were executed than the step limit allows.
+    StepLimitExceeded,
+    UnknownFunction(String),
+    /// A function without a body (usually `Linkage::External`) was called.
+    NoBody(String),
+    ArgCount {
+        func: String,
+        expected: usize,
+        found: usize,
+    },
+    UndefinedValue {
+        func: String,
+        block: BlockId,
+        value: ValueId,
+    },
+    DivisionByZero {
+        func: String,
+        block: BlockId,
+    },
+    /// A `Φ` was reached without an incoming edge matching it.
+    BadPhi {
+        func: String,
+        block: BlockId,
+    },
+    NoTerminator {
+        func: String,
+        block: BlockId,
+    },
+    /// A load or store through a pointer to no allocated memory.
+    BadAddress {
+        func: String,
+        block: BlockId,
+        addr: i64,
+    },
+    /// A store to a global which isn't mutable.
+    ConstantWrite {
+        func: String,
+        block: BlockId,
+        addr: i64,
+    },
+}
+
+impl Display for InterpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpError::StepLimitExceeded => write!(f, "step limit exceeded"),
+            InterpError::UnknownFunction(name) => write!(f, "unknown function `{}`", name),
+            InterpError::NoBody(name) => write!(f, "function `{}` has no body", name),
+            InterpError::ArgCount {
+                func,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} arguments but {} were given",
+                func, expected, found
+            ),
+            InterpError::UndefinedValue { func, block, value } => {
+                write!(f, "in fn {}, {}: {} is undefined", func, block, value)
+            }
+            InterpError::DivisionByZero { func, block } => {
+                write!(f, "in fn {}, {}: division by zero", func, block)
+            }
+            InterpError::BadPhi { func, block } => {
+                write!(f, "in fn {}, {}: Φ without a matching pred", func, block)
+            }
+            InterpError::NoTerminator { func, block } => {
+                write!(f, "in fn {}, {}: reached noterm", func, block)
+            }
+            InterpError::BadAddress { func, block, addr } => {
+                write!(f, "in fn {}, {}: bad address {}", func, block, addr)
+            }
+            InterpError::ConstantWrite { func, block, addr } => {
+                write!(
+                    f,
+                    "in fn {}, {}: store to constant at {}",
+                    func, block, addr
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InterpError {}
+
+struct Frame<'a> {
+    func: &'a Function,
+    block: BlockId,
+    prev: Option<BlockId>,
+    pos: usize,
+    values: Vec<Option<i64>>,
+    vars: Vec<i64>,
+    args: Vec<i64>,
+    /// Where the caller wants the return value
+    ret_to: Option<ValueId>,
+    /// The size of the memory when the frame was entered, so its stack
+    /// slots can be freed on return
+    stack_base: usize,
+}
+
+impl<'a> Frame<'a> {
+    fn new(func: &'a Function, args: Vec<i64>, ret_to: Option<ValueId>, stack_base: usize) -> Self {
+        Frame {
+            func,
+            block: BlockId(0),
+            prev: None,
+            pos: 0,
+            values: vec![None; func.values.len()],
+            vars: vec![0; func.variables.len()],
+            args,
+            ret_to,
+            stack_base,
+        }
+    }
+
+    fn get(&self, val: ValueId) -> Result<i64, InterpError> {
+        self.values
+            .get(val.0)
+            .copied()
+            .flatten()
+            .ok_or_else(|| InterpError::UndefinedValue {
+                func: self.func.name.clone(),
+                block: self.block,
+                value: val,
+            })
+    }
+
+    fn set(&mut self, val: ValueId, res: i64) {
+        self.values[val.0] = Some(self.func.values[val.0].ty.wrap(res));
+    }
+
+    fn phi_operand(&self, vals: &[ValueId]) -> Result<ValueId, InterpError> {
+        let bad_phi = || InterpError::BadPhi {
+            func: self.func.name.clone(),
+            block: self.block,
+        };
+        let prev = self.prev.ok_or_else(bad_phi)?;
+        let idx = self.func.blocks[self.block.0]
+            .preds
+            .iter()
+            .position(|pred| *pred == prev)
+            .ok_or_else(bad_phi)?;
+        vals.get(idx).copied().ok_or_else(bad_phi)
+    }
+
+    /// Moves to `target`, running the par-moves of the current block and the
+    /// leading Φs of the target.
+    fn jump(&mut self, target: BlockId) -> Result<(), InterpError> {
+        let block = &self.func.blocks[self.block.0];
+        let moves = block
+            .par_moves
+            .iter()
+            .map(|(dst, src)| Ok((*dst, self.get(*src)?)))
+            .collect::<Result<Vec<_>, InterpError>>()?;
+        for (dst, val) in moves {
+            self.set(dst, val);
+        }
+
+        self.prev = Some(self.block);
+        self.block = target;
+        self.pos = 0;
+
+        let mut phis = Vec::new();
+        for instr in self.func.blocks[target.0].instructions.iter() {
+            let Operation::Phi(vals) = &instr.operation else {
+                break;
+            };
+            phis.push((instr.yielded, self.get(self.phi_operand(vals)?)?));
+            self.pos += 1;
+        }
+        for (dst, val) in phis {
+            if let Some(dst) = dst {
+                self.set(dst, val);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(module: &'a Module) -> Self {
+        let mut memory = vec![0];
+        let mut globals = Vec::with_capacity(module.globals.len());
+        for global in module.globals.iter() {
+            globals.push(memory.len());
+            memory.extend(global.words());
+        }
+        Interpreter {
+            module,
+            step_limit: None,
+            steps: 0,
+            memory,
+            globals,
+        }
+    }
+
+    /// Limits the number of instructions and terminators executed, counted
+    /// across every call to `run`.
+    pub fn with_step_limit(mut self, limit: usize) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    /// The number of instructions and terminators executed so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Runs the function with the given name.
+    pub fn run_by_name(&mut self, name: &str, args: &[i64]) -> Result<i64, InterpError> {
+        let func = self
+            .module
+            .functions
+            .iter()
+            .position(|f| f.name == name)
+            .ok_or_else(|| InterpError::UnknownFunction(name.to_string()))?;
+        self.run(FunctionId(func), args)
+    }
+
+    /// Runs `func` with `args` and returns the value it returns.
+    pub fn run(&mut self, func: FunctionId, args: &[i64]) -> Result<i64, InterpError> {
+        let mut stack = vec![self.enter(func, args.to_vec(), None)?];
+
+        loop {
+            let frame = stack.last_mut().unwrap();
+            let func: &'a Function = frame.func;
+            let block = &func.blocks[frame.block.0];
+            self.step()?;
+
+            let Some(instr) = block.instructions.get(frame.pos) else {
+                let ret = match block.terminator {
+                    Terminator::Jump(target) => {
+                        frame.jump(target)?;
+                        continue;
+                    }
+                    Terminator::Branch(cond, t, f) => {
+                        let target = if frame.get(cond)? != 0 { t } else { f };
+                        frame.jump(target)?;
+                        continue;
+                    }
+                    Terminator::Return(val) => frame.get(val)?,
+                    Terminator::NoTerm => {
+                        return Err(InterpError::NoTerminator {
+                            func: frame.func.name.clone(),
+                            block: frame.block,
+                        })
+                    }
+                };
+                let ret_to = frame.ret_to;
+                self.memory.truncate(frame.stack_base);
+                stack.pop();
+                match stack.last_mut() {
+                    None => return Ok(ret),
+                    Some(caller) => {
+                        if let Some(dst) = ret_to {
+                            caller.set(dst, ret);
+                        }
+                        continue;
+                    }
+                }
+            };
+            frame.pos += 1;
+
+            let res = match &instr.operation {
+                Operation::Integer(val) => *val,
+                Operation::BinOp(op, lhs, rhs) => {
+                    let ty = &frame.func.values[lhs.0].ty;
+                    op.eval(frame.get(*lhs)?, frame.get(*rhs)?, ty)
+                        .ok_or_else(|| InterpError::DivisionByZero {
+                            func: frame.func.name.clone(),
+                            block: frame.block,
+                        })?
+                }
+                Operation::LoadVar(var) => frame.vars[var.0],
+                Operation::Arg(idx) => frame.args[*idx],
+                Operation::Copy(val) => frame.get(*val)?,
+                Operation::UnOp(op, val) => op.eval(frame.get(*val)?, &frame.func.values[val.0].ty),
+                Operation::Cast(op, val, ty) => {
+                    op.eval(frame.get(*val)?, &frame.func.values[val.0].ty, ty)
+                }
+                Operation::Select(cond, a, b) => match frame.get(*cond)? {
+                    0 => frame.get(*b)?,
+                    _ => frame.get(*a)?,
+                },
+                Operation::GlobalAddr(global) => self.globals[global.0] as i64,
+                Operation::Alloca(ty) => {
+                    let addr = self.memory.len();
+                    self.memory.resize(addr + ty.size().max(1), 0);
+                    addr as i64
+                }
+                Operation::Load(ptr) => {
+                    let addr = self.address(frame, *ptr)?;
+                    self.memory[addr]
+                }
+                Operation::Store(ptr, val) => {
+                    let addr = self.address(frame, *ptr)?;
+                    if self.is_constant(addr) {
+                        return Err(InterpError::ConstantWrite {
+                            func: frame.func.name.clone(),
+                            block: frame.block,
+                            addr: addr as i64,
+                        });
+                    }
+                    let val = frame.get(*val)?;
+                    self.memory[addr] = match frame.func.values[ptr.0].ty.pointee() {
+                        Some(ty) => ty.wrap(val),
+                        None => val,
+                    };
+                    continue;
+                }
+                Operation::Gep(ptr, idx) => {
+                    let size = frame.func.values[ptr.0]
+                        .ty
+                        .pointee()
+                        .map_or(1, |ty| ty.size());
+                    frame.get(*ptr)? + frame.get(*idx)? * size as i64
+                }
+                Operation::StoreVar(var, val) => {
+                    let val = frame.get(*val)?;
+                    frame.vars[var.0] = frame.func.variables[var.0].ty.wrap(val);
+                    continue;
+                }
+                Operation::Phi(vals) => frame.get(frame.phi_operand(vals)?)?,
+                Operation::Call(callee, args) => {
+                    let args = args
+                        .iter()
+                        .map(|arg| frame.get(*arg))
+                        .collect::<Result<Vec<i64>, InterpError>>()?;
+                    let callee = self.enter(*callee, args, instr.yielded)?;
+                    stack.push(callee);
+                    continue;
+                }
+            };
+            if let Some(dst) = instr.yielded {
+                frame.set(dst, res);
+            }
+        }
+    }
+
+    fn enter(
+        &self,
+        func: FunctionId,
+        args: Vec<i64>,
+        ret_to: Option<ValueId>,
+    ) -> Result<Frame<'a>, InterpError> {
+        let func = self
+            .module
+            .functions
+            .get(func.0)
+            .ok_or_else(|| InterpError::UnknownFunction(format!("${}", func.0)))?;
+        if func.blocks.is_empty() {
+            return Err(InterpError::NoBody(func.name.clone()));
+        }
+        if func.args.len() != args.len() {
+            return Err(InterpError::ArgCount {
+                func: func.name.clone(),
+                expected: func.args.len(),
+                found: args.len(),
+            });
+        }
+        let args = args
+            .into_iter()
+            .zip(func.args.iter())
+            .map(|(val, (_, ty))| ty.wrap(val))
+            .collect();
+        Ok(Frame::new(func, args, ret_to, self.memory.len()))
+    }
+
+    /// The memory index `ptr` points to, if it was allocated.
+    fn address(&self, frame: &Frame, ptr: ValueId) -> Result<usize, InterpError> {
+        let addr = frame.get(ptr)?;
+        match usize::try_from(addr) {
+            Ok(idx) if idx > 0 && idx < self.memory.len() => Ok(idx),
+            _ => Err(InterpError::BadAddress {
+                func: frame.func.name.clone(),
+                block: frame.block,
+                addr,
+            }),
+        }
+    }
+
+    /// Whether `addr` is in a global which isn't mutable.
+    fn is_constant(&self, addr: usize) -> bool {
+        self.module
+            .globals
+            .iter()
+            .zip(self.globals.iter())
+            .any(|(global, start)| {
+                !global.mutable && (*start..*start + global.size()).contains(&addr)
+            })
+    }
+
+    fn step(&mut self) -> Result<(), InterpError> {
+        self.steps += 1;
+        match self.step_limit {
+            Some(limit) if self.steps > limit => Err(InterpError::StepLimitExceeded),
+            _ => Ok(()),
+        }
+    }
+}
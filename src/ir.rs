@@ -3,21 +3,27 @@ use std::{
     fmt::{Debug, Display}, ops::Deref,
 };
 
+pub mod parse;
+
 use crate::{
+    algos::{lower_par_moves::lower_par_moves, pass_manager::PassManager},
+    analysis::{dominators::DominatorTree, liveness::VCodeLiveness, loops::LoopInfo},
     regalloc::Regalloc,
-    vcode::{InstrSelector, VCode, VCodeGenerator, VCodeInstr},
+    vcode::{InstrSelector, VCode, VCodeGenerator, VCodeInstr, VCodeOptions},
 };
 
-/// `Module` is the struct containing all the functions and info about the
-/// passes run on the SSA.
+/// `Module` is the struct containing all the functions and globals, and info
+/// about the passes run on the SSA.
 ///
 /// It is intended to be generated by the `ModuleBuilder` struct and then have
 /// `.apply_mandatory_transforms()` called on it to lower to SSA form
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Module {
     pub(crate) functions: Vec<Function>,
+    pub(crate) globals: Vec<Global>,
     pub name: String,
     pub(crate) algos_run: Vec<Algo>,
+    pub(crate) verify_transforms: bool,
 }
 
 /// Algo contains everything run on the module, and is useful for some sanity checks
@@ -25,6 +31,7 @@ pub struct Module {
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Algo {
     CriticalEdgeSplitting,
+    SsaConstruction,
     PhiLowering,
     PhiRemoval,
     LowerParMoves,
@@ -35,17 +42,48 @@ impl Module {
     pub fn new(name: &str, functions: Vec<Function>) -> Module {
         Module {
             functions,
+            globals: vec![],
             name: name.to_string(),
             algos_run: vec![],
+            verify_transforms: false,
         }
     }
 
+    /// When enabled, `apply_mandatory_transforms` runs `algos::verify` on its
+    /// input and after every step, panicking with the errors found.
+    pub fn set_verify_transforms(&mut self, verify: bool) {
+        self.verify_transforms = verify;
+    }
+
     /// Applies the mandatory transforms to the module and lowers it to SSA form
     pub fn apply_mandatory_transforms(&mut self) {
-        crate::algos::remove_critical_edges::remove_critical_edges(self);
-        crate::algos::lower_to_ssa::lower(self);
+        self.verify_step("input");
+        if let Err(err) = PassManager::mandatory().run(self) {
+            panic!(
+                "mandatory transforms failed on module {}: {}",
+                self.name, err
+            );
+        }
+    }
 
-        crate::algos::phi_lowering::lower_phis(self);
+    pub(crate) fn verify_step(&self, step: &str) {
+        if !self.verify_transforms {
+            return;
+        }
+        let errors = crate::algos::verify::verify(self);
+        if !errors.is_empty() {
+            panic!(
+                "module {} failed verification after {}:\n{}\n{}",
+                self.name,
+                step,
+                errors
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                self
+            );
+        }
     }
 
     /// Lowers the module to vcode using the given instruction selector.
@@ -59,7 +97,35 @@ impl Module {
     >(
         &self,
     ) -> VCode<I> {
+        self.lower_to_vcode_with::<I, S, R>(&VCodeOptions::default())
+    }
+
+    /// `lower_to_vcode`, with `options`.
+    pub fn lower_to_vcode_with<
+        I: VCodeInstr,
+        S: InstrSelector<Instr = I> + Default,
+        R: Regalloc + Default,
+    >(
+        &self,
+        options: &VCodeOptions,
+    ) -> VCode<I> {
+        // selectors only see instructions, so par-moves become copies first
+        let has_par_moves = self
+            .functions
+            .iter()
+            .any(|func| func.blocks.iter().any(|bb| !bb.par_moves.is_empty()));
+        if has_par_moves {
+            let mut module = self.clone();
+            lower_par_moves(&mut module);
+            return module.lower_to_vcode_with::<I, S, R>(options);
+        }
         let mut gen = VCodeGenerator::new();
+        // external globals are defined by another module
+        for (id, global) in self.globals.iter().enumerate() {
+            if global.linkage != Linkage::External {
+                gen.push_data(&global.name, id, global.linkage, global.words());
+            }
+        }
         let mut selector = S::default();
         for func in self.functions.iter() {
             let f = gen.push_function(&func.name, func.linkage, func.args.len());
@@ -68,21 +134,69 @@ impl Module {
             for bb in func.blocks.iter() {
                 let b = gen.push_block();
                 gen.switch_to_block(b);
-                
-                for instr in bb.instructions.iter() {
+                if b == 0 {
+                    selector.begin_function(&mut gen, func);
+                    selector.get_pre_function_instructions(&mut gen);
+                }
+
+                let tail_call = if options.sibling_tail_calls {
+                    bb.tail_call()
+                } else {
+                    None
+                };
+                let body = match tail_call {
+                    Some(_) => &bb.instructions[..bb.instructions.len() - 1],
+                    None => &bb.instructions[..],
+                };
+                for instr in body.iter() {
                     selector.select(&mut gen, instr);
                 }
-                selector.select_terminator(&mut gen, &bb.terminator);
+                match tail_call {
+                    Some(call) if selector.select_tail_call(&mut gen, call) => (),
+                    Some(call) => {
+                        selector.select(&mut gen, call);
+                        selector.select_terminator(&mut gen, &bb.terminator);
+                    }
+                    None => selector.select_terminator(&mut gen, &bb.terminator),
+                }
             }
             selector.get_post_function_instructions(&mut gen);
         }
         let mut v = gen.build();
         let mut regalloc = R::default();
-        for func in &v.functions {
-            for block in &func.instrs {
+        let mut pos = 0;
+        for (func, ir_func) in v.functions.iter().zip(self.functions.iter()) {
+            // external declarations have no code to allocate
+            if ir_func.blocks.is_empty() {
+                continue;
+            }
+            let loops = LoopInfo::compute(ir_func, &DominatorTree::compute(ir_func));
+            // positions of the first and last instruction of every block
+            let mut bounds = Vec::with_capacity(func.instrs.len());
+            for (id, block) in func.instrs.iter().enumerate() {
+                if id < ir_func.blocks.len() {
+                    regalloc.set_loop_depth(loops.loop_depth(BlockId(id)));
+                }
+                let start = pos;
                 for instr in &block.instrs {
                     instr.collect_registers(&mut regalloc);
                     regalloc.next_instr();
+                    pos += 1;
+                }
+                bounds.push((start, pos));
+            }
+            // keep registers live across the blocks they flow through, not
+            // just between their first and last mention
+            let liveness = VCodeLiveness::compute(func);
+            for (block, (start, end)) in bounds.into_iter().enumerate() {
+                if start == end {
+                    continue;
+                }
+                for reg in liveness.live_in(block) {
+                    regalloc.extend_live_range(*reg, start, start);
+                }
+                for reg in liveness.live_out(block) {
+                    regalloc.extend_live_range(*reg, end - 1, end - 1);
                 }
             }
         }
@@ -99,6 +213,36 @@ impl Module {
     }
 }
 
+/// A module-level variable or constant, living in memory for the whole run
+/// of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Global {
+    pub name: String,
+    pub(crate) ty: Type,
+    pub(crate) linkage: Linkage,
+    /// Whether it may be stored to
+    pub(crate) mutable: bool,
+    /// The words it starts out with, zeroed if `None`. An initializer longer
+    /// than a single value of `ty` makes the global an array of them, like
+    /// the bytes of a string.
+    pub(crate) init: Option<Vec<i64>>,
+}
+
+impl Global {
+    /// The number of memory words the global takes.
+    pub fn size(&self) -> usize {
+        let init = self.init.as_ref().map_or(0, |words| words.len());
+        init.max(self.ty.size()).max(1)
+    }
+
+    /// The words the global starts out with, padded with zeros to its size.
+    pub fn words(&self) -> Vec<i64> {
+        let mut words = self.init.clone().unwrap_or_default();
+        words.resize(self.size(), 0);
+        words
+    }
+}
+
 /// The struct containing info about functions as well as its body.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Function {
@@ -147,50 +291,43 @@ impl Function {
         ValueId(id)
     }
 
-    pub(crate) fn replace_children_with(&mut self, original: ValueId, to_replace_to: ValueId) {
-        for bb in self.blocks.iter_mut() {
-            for instr in bb.instructions.iter_mut() {
-                match &mut instr.operation {
-                    Operation::BinOp(_, ref mut lhs, ref mut rhs) => {
-                        if *lhs == original {
-                            *lhs = to_replace_to;
-                        }
-                        if *rhs == original {
-                            *rhs = to_replace_to;
-                        }
-                    }
-                    Operation::Call(_, ref mut args) => {
-                        for arg in args.iter_mut() {
-                            if *arg == original {
-                                *arg = to_replace_to;
-                            }
-                        }
-                    }
-                    Operation::StoreVar(.., ref mut val) => {
-                        if *val == original {
-                            *val = to_replace_to;
-                        }
-                    }
-                    Operation::Phi(ref mut vals) => {
-                        vals.iter_mut().for_each(|val| {
-                            if *val == original {
-                                *val = to_replace_to;
-                            }
-                        });
+    /// Recomputes `Value::children` and `Value::owner` from the instructions
+    /// of the function.
+    pub(crate) fn rebuild_children(&mut self) {
+        for val in self.values.iter_mut() {
+            val.children.clear();
+        }
+        for (id, bb) in self.blocks.iter().enumerate() {
+            for instr in bb.instructions.iter() {
+                if let Some(val) = instr.yielded {
+                    self.values[val.0].owner = BlockId(id);
+                    for operand in instr.operation.operands() {
+                        self.values[operand.0].children.push(val);
                     }
-                    _ => (),
                 }
             }
-            match bb.terminator {
-                Terminator::Return(ref mut val) => {
+        }
+    }
+
+    pub(crate) fn replace_children_with(&mut self, original: ValueId, to_replace_to: ValueId) {
+        for bb in self.blocks.iter_mut() {
+            for instr in bb.instructions.iter_mut() {
+                for val in instr.operation.operands_mut() {
                     if *val == original {
                         *val = to_replace_to;
                     }
                 }
-                Terminator::Branch(ref mut val, ..) => {
-                    if *val == original {
-                        *val = to_replace_to;
-                    }
+            }
+            for (_, src) in bb.par_moves.iter_mut() {
+                if *src == original {
+                    *src = to_replace_to;
+                }
+            }
+            match bb.terminator {
+                Terminator::Return(ref mut val) | Terminator::Branch(ref mut val, ..)
+                    if *val == original =>
+                {
+                    *val = to_replace_to;
                 }
                 _ => (),
             }
@@ -200,9 +337,89 @@ impl Function {
         self.values[original.0].children.clear();
     }
 
+    /// Removes one `from -> to` edge from the preds of `to`, along with the
+    /// matching operand of every Φ in `to`. The terminator of `from` is left
+    /// to the caller.
+    pub(crate) fn remove_edge(&mut self, from: BlockId, to: BlockId) {
+        let block = &mut self.blocks[to.0];
+        let Some(idx) = block.preds.iter().position(|pred| *pred == from) else {
+            return;
+        };
+        block.preds.remove(idx);
+        for instr in block.instructions.iter_mut() {
+            if let Operation::Phi(vals) = &mut instr.operation {
+                if idx < vals.len() {
+                    vals.remove(idx);
+                }
+            }
+        }
+    }
+
+    /// Deletes every block `keep` is false for and renumbers the rest in
+    /// order, fixing up terminators, preds, Φ operands, value owners and
+    /// `Variable::bbs_assign_to`. Kept blocks must not branch to deleted ones.
+    pub(crate) fn retain_blocks(&mut self, keep: &[bool]) {
+        for id in 0..self.blocks.len() {
+            if !keep[id] {
+                continue;
+            }
+            let dead_preds: Vec<BlockId> = self.blocks[id]
+                .preds
+                .iter()
+                .filter(|pred| !keep[pred.0])
+                .copied()
+                .collect();
+            for pred in dead_preds {
+                self.remove_edge(pred, BlockId(id));
+            }
+        }
+
+        let mut remap = vec![None; self.blocks.len()];
+        let mut next = 0;
+        for (id, kept) in keep.iter().enumerate() {
+            if *kept {
+                remap[id] = Some(BlockId(next));
+                next += 1;
+            }
+        }
+        let renumber = |b: BlockId| remap[b.0].expect("branch to a deleted block");
+
+        let blocks = std::mem::take(&mut self.blocks);
+        for (id, mut block) in blocks.into_iter().enumerate() {
+            if !keep[id] {
+                continue;
+            }
+            block.id = self.blocks.len();
+            for target in block.terminator.successors_mut() {
+                *target = renumber(*target);
+            }
+            for pred in block.preds.iter_mut() {
+                *pred = renumber(*pred);
+            }
+            self.blocks.push(block);
+        }
+        for var in self.variables.iter_mut() {
+            var.bbs_assign_to = var
+                .bbs_assign_to
+                .iter()
+                .filter_map(|b| remap[b.0])
+                .collect();
+        }
+        self.rebuild_children();
+    }
+
     pub fn replace_instruction(&mut self, block: BlockId, instr: usize, new_instr: Instruction) {
         self.blocks[block.0].instructions[instr] = new_instr;
     }
+
+    /// Whether the function has stack slots, which pointers passed to its
+    /// calls may point into.
+    pub fn has_allocas(&self) -> bool {
+        self.blocks
+            .iter()
+            .flat_map(|bb| bb.instructions.iter())
+            .any(|instr| matches!(instr.operation, Operation::Alloca(_)))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -229,6 +446,54 @@ pub enum Type {
     Pointer(Box<Type>),
 }
 
+impl Type {
+    /// The number of bits a value of this type holds. Pointers are treated as
+    /// 64 bit unsigned integers.
+    pub fn bits(&self) -> u32 {
+        match self {
+            Type::Integer(size, _) if *size > 0 && *size < 64 => *size as u32,
+            _ => 64,
+        }
+    }
+
+    /// The number of memory words a value of this type takes. Memory is word
+    /// addressed, and every integer and pointer fits in one word.
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Void => 0,
+            Type::Integer(..) | Type::Pointer(_) => 1,
+        }
+    }
+
+    /// The type a pointer points to.
+    pub fn pointee(&self) -> Option<&Type> {
+        match self {
+            Type::Pointer(ty) => Some(ty),
+            _ => None,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type::Integer(_, true))
+    }
+
+    /// Truncates `val` to the width of the type, sign extending it back to an
+    /// `i64` for signed types and zero extending it otherwise.
+    pub fn wrap(&self, val: i64) -> i64 {
+        let bits = self.bits();
+        if bits >= 64 {
+            return val;
+        }
+        let mask = (1i64 << bits) - 1;
+        let val = val & mask;
+        if self.is_signed() && val & (1 << (bits - 1)) != 0 {
+            val | !mask
+        } else {
+            val
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct BasicBlock {
     pub(crate) instructions: Vec<Instruction>,
@@ -238,6 +503,22 @@ pub struct BasicBlock {
     pub(crate) par_moves: Vec<(ValueId, ValueId)>,
 }
 
+impl BasicBlock {
+    /// The last instruction, if it is a call whose result the block returns
+    /// and nothing happens in between.
+    pub fn tail_call(&self) -> Option<&Instruction> {
+        let last = self.instructions.last()?;
+        match (last.yielded, &last.operation, self.terminator) {
+            (Some(val), Operation::Call(..), Terminator::Return(ret))
+                if val == ret && self.par_moves.is_empty() =>
+            {
+                Some(last)
+            }
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Terminator {
     Return(ValueId),
@@ -246,6 +527,40 @@ pub enum Terminator {
     NoTerm,
 }
 
+impl Terminator {
+    /// The value read by the terminator, if any.
+    pub fn operand(&self) -> Option<ValueId> {
+        match self {
+            Terminator::Return(val) | Terminator::Branch(val, ..) => Some(*val),
+            Terminator::Jump(_) | Terminator::NoTerm => None,
+        }
+    }
+
+    pub fn operand_mut(&mut self) -> Option<&mut ValueId> {
+        match self {
+            Terminator::Return(val) | Terminator::Branch(val, ..) => Some(val),
+            Terminator::Jump(_) | Terminator::NoTerm => None,
+        }
+    }
+
+    /// The blocks control can be transferred to, in order.
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, t, f) => vec![*t, *f],
+            Terminator::Return(_) | Terminator::NoTerm => vec![],
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(_, t, f) => vec![t, f],
+            Terminator::Return(_) | Terminator::NoTerm => vec![],
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Linkage {
     Public,
@@ -281,8 +596,76 @@ pub enum Operation {
     LoadVar(VariableId),
     StoreVar(VariableId, ValueId),
     Phi(Vec<ValueId>),
+    /// The argument at the given index of the function it's in
+    Arg(usize),
+    /// A stack slot holding a value of the type, freed when the function
+    /// returns
+    Alloca(Type),
+    /// Reads the value the pointer points to
+    Load(ValueId),
+    /// Writes the value (second) to where the pointer (first) points
+    Store(ValueId, ValueId),
+    /// The pointer moved by a number of values of the type it points to
+    Gep(ValueId, ValueId),
+    /// The value of its operand. Unlike every other operation, the value it
+    /// yields may be defined by several copies, once par-moves are lowered.
+    Copy(ValueId),
+    UnOp(UnOp, ValueId),
+    /// The value converted to the type
+    Cast(CastOp, ValueId, Type),
+    /// The second operand if the first is non-zero, else the third
+    Select(ValueId, ValueId, ValueId),
+    /// A pointer to the global
+    GlobalAddr(GlobalId),
 }
 
+impl Operation {
+    /// The values read by the operation, in order.
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Operation::Integer(_)
+            | Operation::LoadVar(_)
+            | Operation::Arg(_)
+            | Operation::Alloca(_)
+            | Operation::GlobalAddr(_) => vec![],
+            Operation::BinOp(_, lhs, rhs)
+            | Operation::Store(lhs, rhs)
+            | Operation::Gep(lhs, rhs) => vec![*lhs, *rhs],
+            Operation::Load(ptr)
+            | Operation::Copy(ptr)
+            | Operation::UnOp(_, ptr)
+            | Operation::Cast(_, ptr, _) => vec![*ptr],
+            Operation::Select(cond, a, b) => vec![*cond, *a, *b],
+            Operation::Call(_, args) => args.clone(),
+            Operation::StoreVar(_, val) => vec![*val],
+            Operation::Phi(vals) => vals.clone(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Operation::Integer(_)
+            | Operation::LoadVar(_)
+            | Operation::Arg(_)
+            | Operation::Alloca(_)
+            | Operation::GlobalAddr(_) => vec![],
+            Operation::BinOp(_, lhs, rhs)
+            | Operation::Store(lhs, rhs)
+            | Operation::Gep(lhs, rhs) => vec![lhs, rhs],
+            Operation::Load(ptr)
+            | Operation::Copy(ptr)
+            | Operation::UnOp(_, ptr)
+            | Operation::Cast(_, ptr, _) => vec![ptr],
+            Operation::Select(cond, a, b) => vec![cond, a, b],
+            Operation::Call(_, args) => args.iter_mut().collect(),
+            Operation::StoreVar(_, val) => vec![val],
+            Operation::Phi(vals) => vals.iter_mut().collect(),
+        }
+    }
+}
+
+/// A binary operation. Whether `Div`, `Mod`, `Shr` and the ordered
+/// comparisons are signed follows from the type of their operands.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum BinOp {
     Add,
@@ -303,6 +686,61 @@ pub enum BinOp {
     Ge,
 }
 
+impl BinOp {
+    /// Whether `a op b` always equals `b op a`.
+    pub fn is_commutative(&self) -> bool {
+        matches!(
+            self,
+            BinOp::Add | BinOp::Mul | BinOp::And | BinOp::Or | BinOp::Xor | BinOp::Eq | BinOp::Ne
+        )
+    }
+
+    /// Evaluates the operation on two operands of type `ty`, which decides the
+    /// width and whether division, remainder, right shifts and comparisons
+    /// are signed. Arithmetic wraps; comparisons yield 0 or 1.
+    ///
+    /// Returns `None` on division or remainder by zero.
+    pub fn eval(&self, lhs: i64, rhs: i64, ty: &Type) -> Option<i64> {
+        let (lhs, rhs) = (ty.wrap(lhs), ty.wrap(rhs));
+        let bits = ty.bits();
+        let mask = if bits >= 64 { u64::MAX } else { (1u64 << bits) - 1 };
+        let (ulhs, urhs) = (lhs as u64 & mask, rhs as u64 & mask);
+        let signed = ty.is_signed();
+        let res = match self {
+            BinOp::Add => lhs.wrapping_add(rhs),
+            BinOp::Sub => lhs.wrapping_sub(rhs),
+            BinOp::Mul => lhs.wrapping_mul(rhs),
+            BinOp::Div | BinOp::Mod if rhs == 0 => return None,
+            BinOp::Div if signed => lhs.wrapping_div(rhs),
+            BinOp::Div => (ulhs / urhs) as i64,
+            BinOp::Mod if signed => lhs.wrapping_rem(rhs),
+            BinOp::Mod => (ulhs % urhs) as i64,
+            BinOp::And => lhs & rhs,
+            BinOp::Or => lhs | rhs,
+            BinOp::Xor => lhs ^ rhs,
+            BinOp::Shl if urhs >= bits as u64 => 0,
+            BinOp::Shl => lhs << urhs,
+            BinOp::Shr if signed => lhs >> urhs.min(63),
+            BinOp::Shr if urhs >= bits as u64 => 0,
+            BinOp::Shr => (ulhs >> urhs) as i64,
+            BinOp::Eq => (lhs == rhs) as i64,
+            BinOp::Ne => (lhs != rhs) as i64,
+            BinOp::Lt if signed => (lhs < rhs) as i64,
+            BinOp::Le if signed => (lhs <= rhs) as i64,
+            BinOp::Gt if signed => (lhs > rhs) as i64,
+            BinOp::Ge if signed => (lhs >= rhs) as i64,
+            BinOp::Lt => (ulhs < urhs) as i64,
+            BinOp::Le => (ulhs <= urhs) as i64,
+            BinOp::Gt => (ulhs > urhs) as i64,
+            BinOp::Ge => (ulhs >= urhs) as i64,
+        };
+        Some(match self {
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => res,
+            _ => ty.wrap(res),
+        })
+    }
+}
+
 impl Display for BinOp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -326,6 +764,86 @@ impl Display for BinOp {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+impl UnOp {
+    /// Evaluates the operation on an operand of type `ty`, wrapping to its
+    /// width.
+    pub fn eval(&self, val: i64, ty: &Type) -> i64 {
+        ty.wrap(match self {
+            UnOp::Neg => val.wrapping_neg(),
+            UnOp::Not => !val,
+        })
+    }
+}
+
+impl Display for UnOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnOp::Neg => write!(f, "neg"),
+            UnOp::Not => write!(f, "not"),
+        }
+    }
+}
+
+/// A conversion between integer types of different widths, or between
+/// pointers and integers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CastOp {
+    /// Fills the new high bits with zeros
+    ZExt,
+    /// Fills the new high bits with the sign bit
+    SExt,
+    /// Drops the high bits
+    Trunc,
+    PtrToInt,
+    IntToPtr,
+}
+
+impl CastOp {
+    /// Whether a value of type `from` can be converted to `to`. Extensions
+    /// may not narrow and truncations may not widen, but either may change
+    /// the signedness alone.
+    pub fn is_valid(&self, from: &Type, to: &Type) -> bool {
+        match (self, from, to) {
+            (CastOp::ZExt | CastOp::SExt, Type::Integer(..), Type::Integer(..)) => {
+                to.bits() >= from.bits()
+            }
+            (CastOp::Trunc, Type::Integer(..), Type::Integer(..)) => to.bits() <= from.bits(),
+            (CastOp::PtrToInt, Type::Pointer(_), Type::Integer(..))
+            | (CastOp::IntToPtr, Type::Integer(..), Type::Pointer(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Converts `val` of type `from` to `to`, extending it as the operation
+    /// says and wrapping it to the width of `to`.
+    pub fn eval(&self, val: i64, from: &Type, to: &Type) -> i64 {
+        let bits = from.bits();
+        to.wrap(match self {
+            CastOp::ZExt => Type::Integer(bits as usize, false).wrap(val),
+            CastOp::SExt => Type::Integer(bits as usize, true).wrap(val),
+            CastOp::Trunc | CastOp::PtrToInt | CastOp::IntToPtr => val,
+        })
+    }
+}
+
+impl Display for CastOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CastOp::ZExt => write!(f, "zext"),
+            CastOp::SExt => write!(f, "sext"),
+            CastOp::Trunc => write!(f, "trunc"),
+            CastOp::PtrToInt => write!(f, "ptrtoint"),
+            CastOp::IntToPtr => write!(f, "inttoptr"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct BlockId(pub(crate) usize);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -333,6 +851,8 @@ pub struct FunctionId(pub(crate) usize);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct VariableId(pub(crate) usize);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlobalId(pub(crate) usize);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ValueId(pub(crate) usize);
 
 impl Deref for BlockId {
@@ -356,6 +876,13 @@ impl Deref for VariableId {
     }
 }
 
+impl Deref for GlobalId {
+    type Target = usize;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl Deref for ValueId {
     type Target = usize;
     fn deref(&self) -> &Self::Target {
@@ -367,6 +894,29 @@ impl Display for Module {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "/* {:?} module {} */", self.algos_run, self.name)?;
 
+        for (id, global) in self.globals.iter().enumerate() {
+            write!(
+                f,
+                "@{}: {} {} {}: {}",
+                id,
+                global.linkage,
+                if global.mutable { "global" } else { "const" },
+                global.name,
+                global.ty
+            )?;
+            if let Some(init) = &global.init {
+                write!(
+                    f,
+                    " = [{}]",
+                    init.iter()
+                        .map(|word| word.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )?;
+            }
+            writeln!(f)?;
+        }
+
         for func in &self.functions {
             writeln!(f, "{}", func)?;
         }
@@ -381,6 +931,9 @@ impl Debug for Algo {
             Algo::CriticalEdgeSplitting => {
                 write!(f, "@edges_splitted")
             }
+            Algo::SsaConstruction => {
+                write!(f, "@ssa_constructed")
+            }
             Algo::PhiLowering => {
                 write!(f, "@phis_lowered")
             }
@@ -410,8 +963,12 @@ impl Display for Function {
             self.ret_type
         )?;
 
+        for (id, var) in self.variables.iter().enumerate() {
+            writeln!(f, "    var #{} {}: {}", id, var.name, var.ty)?;
+        }
+
         for block in &self.blocks {
-            write!(f, "{}", block)?;
+            block.fmt_with_values(f, Some(&self.values))?;
         }
 
         write!(f, "}}")?;
@@ -434,6 +991,18 @@ impl Display for Type {
 
 impl Display for BasicBlock {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with_values(f, None)
+    }
+}
+
+impl BasicBlock {
+    /// Prints the block, annotating every yielded value with its type when the
+    /// owning function's values are given.
+    fn fmt_with_values(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        values: Option<&[Value]>,
+    ) -> std::fmt::Result {
         writeln!(
             f,
             "${}: ; preds = {}",
@@ -445,7 +1014,12 @@ impl Display for BasicBlock {
                 .join(", ")
         )?;
         for instr in &self.instructions {
-            writeln!(f, "    {}", instr)?;
+            match (instr.yielded, values) {
+                (Some(val), Some(values)) => {
+                    writeln!(f, "    {}: {} = {}", val, values[val.0].ty, instr.operation)?
+                }
+                _ => writeln!(f, "    {}", instr)?,
+            }
         }
         if !self.par_moves.is_empty() {
             let tmp = self
@@ -515,6 +1089,16 @@ impl Display for Operation {
             Operation::LoadVar(var) => write!(f, "load #{}", var.0)?,
             Operation::StoreVar(var, val) => write!(f, "store #{} {}", var.0, val)?,
             Operation::Integer(val) => write!(f, "{}", val)?,
+            Operation::Arg(idx) => write!(f, "arg {}", idx)?,
+            Operation::Alloca(ty) => write!(f, "alloca {}", ty)?,
+            Operation::Load(ptr) => write!(f, "load {}", ptr)?,
+            Operation::Store(ptr, val) => write!(f, "store {} {}", ptr, val)?,
+            Operation::Gep(ptr, idx) => write!(f, "gep {} {}", ptr, idx)?,
+            Operation::Copy(val) => write!(f, "copy {}", val)?,
+            Operation::UnOp(op, val) => write!(f, "{} {}", op, val)?,
+            Operation::Cast(op, val, ty) => write!(f, "{} {} {}", op, val, ty)?,
+            Operation::Select(cond, a, b) => write!(f, "select {} {} {}", cond, a, b)?,
+            Operation::GlobalAddr(global) => write!(f, "addr @{}", global.0)?,
             Operation::Phi(vals) => write!(
                 f,
                 "Φ {}",
@@ -0,0 +1,1097 @@
+//! Parser for the textual IR printed by `impl Display for Module`.
+//!
+//! The accepted syntax is exactly what the printer emits, e.g.
+//!
+//! ```text
+//! /* [@edges_splitted] module test */
+//! $0: public fn main() s32 {
+//!     var #0 x: s32
+//! $0: ; preds =
+//!     %0: s32 = 3
+//!     store #0 %0
+//!     %1: s32 = load #0
+//!     ret %1
+//! }
+//! ```
+//!
+//! Type annotations on yielded values are optional when writing fixtures by
+//! hand; missing types are inferred from the operands (integer constants
+//! default to `s32`).
+
+use std::{collections::HashSet, fmt::Display, str::FromStr};
+
+use super::{
+    Algo, BasicBlock, BinOp, BlockId, CastOp, Function, FunctionId, Global, GlobalId, Instruction,
+    Linkage, Module, Operation, Terminator, Type, UnOp, Value, ValueId, Variable, VariableId,
+};
+
+/// An error encountered while parsing, with a 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Module {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_module(s)
+    }
+}
+
+/// Parses the textual form of a module.
+pub fn parse_module(src: &str) -> Result<Module, ParseError> {
+    Parser::new(src).parse()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Value(usize),
+    Block(usize),
+    Var(usize),
+    Global(usize),
+    Int(i64),
+    Ident(String),
+    Str(String),
+    Phi,
+    Arrow,
+    Punct(char),
+}
+
+impl Display for Tok {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tok::Value(id) => write!(f, "`%{}`", id),
+            Tok::Block(id) => write!(f, "`${}`", id),
+            Tok::Var(id) => write!(f, "`#{}`", id),
+            Tok::Global(id) => write!(f, "`@{}`", id),
+            Tok::Int(val) => write!(f, "`{}`", val),
+            Tok::Ident(name) => write!(f, "`{}`", name),
+            Tok::Str(s) => write!(f, "{:?}", s),
+            Tok::Phi => write!(f, "`Φ`"),
+            Tok::Arrow => write!(f, "`<-`"),
+            Tok::Punct(c) => write!(f, "`{}`", c),
+        }
+    }
+}
+
+/// The tokens of a single line, each paired with its 1-based column.
+struct Line {
+    number: usize,
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+    end_col: usize,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+fn lex_line(number: usize, text: &str) -> Result<Line, ParseError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut toks = Vec::new();
+    let mut i = 0;
+    let err = |col: usize, message: String| ParseError {
+        line: number,
+        col,
+        message,
+    };
+    let read_number = |i: &mut usize| -> Option<usize> {
+        let start = *i;
+        while *i < chars.len() && chars[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>().parse().ok()
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let col = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let tok = match c {
+            '%' | '$' | '#' | '@' => {
+                i += 1;
+                let id = read_number(&mut i)
+                    .ok_or_else(|| err(col, format!("expected a number after `{}`", c)))?;
+                match c {
+                    '%' => Tok::Value(id),
+                    '$' => Tok::Block(id),
+                    '#' => Tok::Var(id),
+                    _ => Tok::Global(id),
+                }
+            }
+            '-' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                i += 1;
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits: String = chars[start..i].iter().collect();
+                Tok::Int(
+                    format!("-{}", digits)
+                        .parse()
+                        .map_err(|_| err(col, "integer literal out of range".to_string()))?,
+                )
+            }
+            '0'..='9' => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits: String = chars[start..i].iter().collect();
+                Tok::Int(
+                    digits
+                        .parse()
+                        .map_err(|_| err(col, "integer literal out of range".to_string()))?,
+                )
+            }
+            '<' if chars.get(i + 1) == Some(&'-') => {
+                i += 2;
+                Tok::Arrow
+            }
+            'Φ' => {
+                i += 1;
+                Tok::Phi
+            }
+            '"' => {
+                i += 1;
+                let start = i;
+                while i < chars.len() && chars[i] != '"' {
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(err(col, "unterminated string".to_string()));
+                }
+                let s = chars[start..i].iter().collect();
+                i += 1;
+                Tok::Str(s)
+            }
+            '=' | ',' | '(' | ')' | '{' | '}' | ':' | ';' | '*' | '[' | ']' => {
+                i += 1;
+                Tok::Punct(c)
+            }
+            c if is_ident_char(c) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && is_ident_char(chars[i]) {
+                    i += 1;
+                }
+                Tok::Ident(chars[start..i].iter().collect())
+            }
+            c => return Err(err(col, format!("unexpected character `{}`", c))),
+        };
+        toks.push((tok, col));
+    }
+
+    Ok(Line {
+        number,
+        toks,
+        pos: 0,
+        end_col: chars.len() + 1,
+    })
+}
+
+impl Line {
+    fn err_at(&self, col: usize, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.number,
+            col,
+            message: message.into(),
+        }
+    }
+
+    fn col(&self) -> usize {
+        self.toks
+            .get(self.pos)
+            .map(|(_, col)| *col)
+            .unwrap_or(self.end_col)
+    }
+
+    fn err(&self, message: impl Into<String>) -> ParseError {
+        self.err_at(self.col(), message)
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos).map(|(tok, _)| tok)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Tok> {
+        self.toks.get(self.pos + offset).map(|(tok, _)| tok)
+    }
+
+    fn next(&mut self, what: &str) -> Result<Tok, ParseError> {
+        match self.toks.get(self.pos) {
+            Some((tok, _)) => {
+                self.pos += 1;
+                Ok(tok.clone())
+            }
+            None => Err(self.err(format!("expected {}, found end of line", what))),
+        }
+    }
+
+    fn unexpected(&self, what: &str) -> ParseError {
+        match self.peek() {
+            Some(tok) => self.err(format!("expected {}, found {}", what, tok)),
+            None => self.err(format!("expected {}, found end of line", what)),
+        }
+    }
+
+    fn eat(&mut self, tok: &Tok) -> bool {
+        if self.peek() == Some(tok) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), ParseError> {
+        if self.eat(&Tok::Punct(c)) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", c)))
+        }
+    }
+
+    fn expect_end(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(tok) => Err(self.err(format!("unexpected {} at end of line", tok))),
+        }
+    }
+
+    fn ident(&mut self, what: &str) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Tok::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected(what)),
+        }
+    }
+
+    fn value(&mut self) -> Result<ValueId, ParseError> {
+        match self.peek() {
+            Some(Tok::Value(id)) => {
+                let id = *id;
+                self.pos += 1;
+                Ok(ValueId(id))
+            }
+            _ => Err(self.unexpected("a value")),
+        }
+    }
+
+    fn block(&mut self) -> Result<BlockId, ParseError> {
+        match self.peek() {
+            Some(Tok::Block(id)) => {
+                let id = *id;
+                self.pos += 1;
+                Ok(BlockId(id))
+            }
+            _ => Err(self.unexpected("a block")),
+        }
+    }
+
+    fn var(&mut self) -> Result<VariableId, ParseError> {
+        match self.peek() {
+            Some(Tok::Var(id)) => {
+                let id = *id;
+                self.pos += 1;
+                Ok(VariableId(id))
+            }
+            _ => Err(self.unexpected("a variable")),
+        }
+    }
+
+    fn linkage(&mut self) -> Result<Linkage, ParseError> {
+        let col = self.col();
+        Ok(match self.ident("a linkage")?.as_str() {
+            "public" => Linkage::Public,
+            "private" => Linkage::Private,
+            "external" => Linkage::External,
+            other => return Err(self.err_at(col, format!("unknown linkage `{}`", other))),
+        })
+    }
+
+    fn ty(&mut self) -> Result<Type, ParseError> {
+        let col = self.col();
+        let name = self.ident("a type")?;
+        let mut ty = if name == "void" {
+            Type::Void
+        } else {
+            let (signed, size) = name.split_at(1);
+            match (signed, size.parse::<usize>()) {
+                ("s", Ok(size)) => Type::Integer(size, true),
+                ("u", Ok(size)) => Type::Integer(size, false),
+                _ => return Err(self.err_at(col, format!("unknown type `{}`", name))),
+            }
+        };
+        while self.eat(&Tok::Punct('*')) {
+            ty = Type::Pointer(Box::new(ty));
+        }
+        Ok(ty)
+    }
+
+    /// Parses a comma separated list of values, stopping at `end` or at the
+    /// end of the line.
+    fn value_list(&mut self, end: Option<char>) -> Result<Vec<ValueId>, ParseError> {
+        let mut vals = Vec::new();
+        if self.peek().is_none() || end.is_some_and(|c| self.peek() == Some(&Tok::Punct(c))) {
+            return Ok(vals);
+        }
+        loop {
+            vals.push(self.value()?);
+            if !self.eat(&Tok::Punct(',')) {
+                return Ok(vals);
+            }
+        }
+    }
+
+    /// Parses a par-move operand list such as `["%1", "%2"]`.
+    fn quoted_values(&mut self) -> Result<Vec<ValueId>, ParseError> {
+        self.expect_punct('[')?;
+        let mut vals = Vec::new();
+        if self.eat(&Tok::Punct(']')) {
+            return Ok(vals);
+        }
+        loop {
+            let col = self.col();
+            match self.next("a quoted value")? {
+                Tok::Str(s) => match lex_line(self.number, &s)?.toks.as_slice() {
+                    [(Tok::Value(id), _)] => vals.push(ValueId(*id)),
+                    _ => return Err(self.err_at(col, format!("expected a value, found {:?}", s))),
+                },
+                tok => {
+                    return Err(self.err_at(col, format!("expected a quoted value, found {}", tok)))
+                }
+            }
+            if !self.eat(&Tok::Punct(',')) {
+                self.expect_punct(']')?;
+                return Ok(vals);
+            }
+        }
+    }
+}
+
+fn parse_binop(name: &str) -> Option<BinOp> {
+    Some(match name {
+        "add" => BinOp::Add,
+        "sub" => BinOp::Sub,
+        "mul" => BinOp::Mul,
+        "div" => BinOp::Div,
+        "mod" => BinOp::Mod,
+        "and" => BinOp::And,
+        "or" => BinOp::Or,
+        "xor" => BinOp::Xor,
+        "shl" => BinOp::Shl,
+        "shr" => BinOp::Shr,
+        "eq" => BinOp::Eq,
+        "ne" => BinOp::Ne,
+        "lt" => BinOp::Lt,
+        "le" => BinOp::Le,
+        "gt" => BinOp::Gt,
+        "ge" => BinOp::Ge,
+        _ => return None,
+    })
+}
+
+fn parse_unop(name: &str) -> Option<UnOp> {
+    Some(match name {
+        "neg" => UnOp::Neg,
+        "not" => UnOp::Not,
+        _ => return None,
+    })
+}
+
+fn parse_cast(name: &str) -> Option<CastOp> {
+    Some(match name {
+        "zext" => CastOp::ZExt,
+        "sext" => CastOp::SExt,
+        "trunc" => CastOp::Trunc,
+        "ptrtoint" => CastOp::PtrToInt,
+        "inttoptr" => CastOp::IntToPtr,
+        _ => return None,
+    })
+}
+
+fn parse_algo(name: &str) -> Option<Algo> {
+    Some(match name {
+        "@edges_splitted" => Algo::CriticalEdgeSplitting,
+        "@ssa_constructed" => Algo::SsaConstruction,
+        "@phis_lowered" => Algo::PhiLowering,
+        "@phis_removed" => Algo::PhiRemoval,
+        "@par_moves_lowered" => Algo::LowerParMoves,
+        _ => return None,
+    })
+}
+
+/// Per-function state which is resolved once the closing `}` is reached.
+struct FunctionState {
+    func: Function,
+    /// Explicit or inferred type of every value, indexed by `ValueId`.
+    types: Vec<Option<Type>>,
+    /// Block and line of every definition, used for owners and diagnostics.
+    defs: Vec<Option<(BlockId, usize)>>,
+    /// Every block referenced by a terminator, with the line it appears on.
+    block_refs: Vec<(BlockId, usize, usize)>,
+    /// The type of every global of the module, which precede its functions.
+    globals: Vec<Type>,
+}
+
+impl FunctionState {
+    fn ensure_value(&mut self, val: ValueId) {
+        if val.0 >= self.types.len() {
+            self.types.resize(val.0 + 1, None);
+            self.defs.resize(val.0 + 1, None);
+        }
+    }
+}
+
+struct Parser<'a> {
+    lines: Vec<(usize, &'a str)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            lines: src
+                .lines()
+                .enumerate()
+                .map(|(i, line)| (i + 1, line))
+                .filter(|(_, line)| !line.trim().is_empty())
+                .collect(),
+            pos: 0,
+        }
+    }
+
+    fn next_line(&mut self) -> Option<Result<Line, ParseError>> {
+        let (number, text) = *self.lines.get(self.pos)?;
+        self.pos += 1;
+        Some(lex_line(number, text))
+    }
+
+    fn parse(&mut self) -> Result<Module, ParseError> {
+        let mut module = Module::new("", vec![]);
+        if let Some((number, text)) = self.lines.first().copied() {
+            if text.trim_start().starts_with("/*") {
+                self.pos += 1;
+                self.parse_header(&mut module, number, text)?;
+            }
+        }
+
+        let mut calls = Vec::new();
+        while let Some(line) = self.next_line() {
+            let line = line?;
+            // globals come before the functions using them
+            if matches!(line.peek(), Some(Tok::Global(_))) && module.functions.is_empty() {
+                let global = Self::parse_global(line, module.globals.len())?;
+                module.globals.push(global);
+                continue;
+            }
+            let globals = module.globals.iter().map(|g| g.ty.clone()).collect();
+            let func = self.parse_function(line, module.functions.len(), globals, &mut calls)?;
+            module.functions.push(func);
+        }
+
+        for (func, val, callee, line, col) in calls {
+            let Some(ret_type) = module.functions.get(callee.0).map(|f| f.ret_type.clone()) else {
+                return Err(ParseError {
+                    line,
+                    col,
+                    message: format!("call to undefined function ${}", callee.0),
+                });
+            };
+            if let Some(val) = val {
+                let values = &mut module.functions[func].values;
+                if values[val.0].ty == Type::Void {
+                    values[val.0].ty = ret_type;
+                }
+            }
+        }
+
+        Ok(module)
+    }
+
+    fn parse_header(
+        &self,
+        module: &mut Module,
+        number: usize,
+        text: &str,
+    ) -> Result<(), ParseError> {
+        let offset = text.len() - text.trim_start().len();
+        let err = |col: usize, message: &str| ParseError {
+            line: number,
+            col: col + 1,
+            message: message.to_string(),
+        };
+        let inner = text
+            .trim()
+            .strip_prefix("/*")
+            .and_then(|s| s.strip_suffix("*/"))
+            .ok_or_else(|| err(offset, "unterminated module header"))?;
+        let inner_offset = offset + 2;
+        let open = inner
+            .find('[')
+            .ok_or_else(|| err(inner_offset, "expected `[` in module header"))?;
+        let close = inner
+            .find(']')
+            .ok_or_else(|| err(inner_offset + open, "expected `]` in module header"))?;
+        let mut col = inner_offset + open + 1;
+        for name in inner[open + 1..close].split(',') {
+            let trimmed = name.trim();
+            if !trimmed.is_empty() {
+                let start = col + name.len() - name.trim_start().len();
+                let algo = parse_algo(trimmed)
+                    .ok_or_else(|| err(start, &format!("unknown algo `{}`", trimmed)))?;
+                module.algos_run.push(algo);
+            }
+            col += name.len() + 1;
+        }
+        let rest = &inner[close + 1..];
+        let name = rest.trim().strip_prefix("module").ok_or_else(|| {
+            err(
+                inner_offset + close + 1,
+                "expected `module` in module header",
+            )
+        })?;
+        module.name = name.trim().to_string();
+        Ok(())
+    }
+
+    fn parse_global(mut line: Line, expected_id: usize) -> Result<Global, ParseError> {
+        // @0: private const msg: u8 = [104, 105, 0]
+        let col = line.col();
+        match line.next("a global")? {
+            Tok::Global(id) if id == expected_id => {}
+            Tok::Global(id) => {
+                return Err(line.err_at(
+                    col,
+                    format!("expected global @{}, found @{}", expected_id, id),
+                ))
+            }
+            tok => return Err(line.err_at(col, format!("expected a global, found {}", tok))),
+        }
+        line.expect_punct(':')?;
+        let linkage = line.linkage()?;
+        let col = line.col();
+        let mutable = match line.ident("`global` or `const`")?.as_str() {
+            "global" => true,
+            "const" => false,
+            _ => return Err(line.err_at(col, "expected `global` or `const`")),
+        };
+        let name = line.ident("a global name")?;
+        line.expect_punct(':')?;
+        let ty = line.ty()?;
+        let init = if line.eat(&Tok::Punct('=')) {
+            line.expect_punct('[')?;
+            let mut words = Vec::new();
+            while !line.eat(&Tok::Punct(']')) {
+                if !words.is_empty() {
+                    line.expect_punct(',')?;
+                }
+                let col = line.col();
+                match line.next("a word")? {
+                    Tok::Int(word) => words.push(word),
+                    tok => return Err(line.err_at(col, format!("expected a word, found {}", tok))),
+                }
+            }
+            Some(words)
+        } else {
+            None
+        };
+        line.expect_end()?;
+        Ok(Global {
+            name,
+            ty,
+            linkage,
+            mutable,
+            init,
+        })
+    }
+
+    fn parse_function(
+        &mut self,
+        mut line: Line,
+        expected_id: usize,
+        globals: Vec<Type>,
+        calls: &mut Vec<(usize, Option<ValueId>, FunctionId, usize, usize)>,
+    ) -> Result<Function, ParseError> {
+        // $0: public fn main(a: s32) s32 {
+        let col = line.col();
+        match line.next("a function")? {
+            Tok::Block(id) if id == expected_id => {}
+            Tok::Block(id) => {
+                return Err(line.err_at(
+                    col,
+                    format!("expected function ${}, found ${}", expected_id, id),
+                ))
+            }
+            tok => return Err(line.err_at(col, format!("expected a function, found {}", tok))),
+        }
+        line.expect_punct(':')?;
+        let col = line.col();
+        let linkage = line.linkage()?;
+        if line.ident("`fn`")? != "fn" {
+            return Err(line.err_at(col, "expected `fn`"));
+        }
+        let name = line.ident("a function name")?;
+        line.expect_punct('(')?;
+        let mut args = Vec::new();
+        if !line.eat(&Tok::Punct(')')) {
+            loop {
+                let arg = line.ident("an argument name")?;
+                line.expect_punct(':')?;
+                args.push((arg, line.ty()?));
+                if !line.eat(&Tok::Punct(',')) {
+                    line.expect_punct(')')?;
+                    break;
+                }
+            }
+        }
+        let ret_type = line.ty()?;
+        line.expect_punct('{')?;
+        line.expect_end()?;
+
+        let mut state = FunctionState {
+            func: Function::new(&name, ret_type, args, linkage, vec![], expected_id),
+            types: vec![],
+            defs: vec![],
+            block_refs: vec![],
+            globals,
+        };
+        let header_line = line.number;
+
+        loop {
+            let Some(line) = self.next_line() else {
+                return Err(ParseError {
+                    line: header_line,
+                    col: 1,
+                    message: format!("function `{}` is missing its closing `}}`", name),
+                });
+            };
+            let mut line = line?;
+            match line.peek() {
+                Some(Tok::Punct('}')) => {
+                    line.pos += 1;
+                    line.expect_end()?;
+                    break;
+                }
+                Some(Tok::Block(_)) => self.parse_block_header(&mut state, line)?,
+                Some(Tok::Ident(kw)) if kw == "var" => {
+                    line.pos += 1;
+                    let col = line.col();
+                    let id = line.var()?;
+                    if id.0 != state.func.variables.len() {
+                        return Err(line.err_at(
+                            col,
+                            format!(
+                                "expected variable #{}, found #{}",
+                                state.func.variables.len(),
+                                id.0
+                            ),
+                        ));
+                    }
+                    let name = line.ident("a variable name")?;
+                    line.expect_punct(':')?;
+                    let ty = line.ty()?;
+                    line.expect_end()?;
+                    state.func.variables.push(Variable {
+                        name,
+                        ty,
+                        bbs_assign_to: HashSet::new(),
+                    });
+                }
+                _ if state.func.blocks.is_empty() => {
+                    return Err(line.unexpected("a block or variable declaration"))
+                }
+                _ => self.parse_block_line(&mut state, line, calls)?,
+            }
+        }
+
+        Self::finish_function(state)
+    }
+
+    fn parse_block_header(
+        &mut self,
+        state: &mut FunctionState,
+        mut line: Line,
+    ) -> Result<(), ParseError> {
+        // $1: ; preds = $0, $2
+        let col = line.col();
+        let id = line.block()?;
+        if id.0 != state.func.blocks.len() {
+            return Err(line.err_at(
+                col,
+                format!(
+                    "expected block ${}, found ${}",
+                    state.func.blocks.len(),
+                    id.0
+                ),
+            ));
+        }
+        line.expect_punct(':')?;
+        let mut preds = Vec::new();
+        if line.eat(&Tok::Punct(';')) {
+            if line.ident("`preds`")? != "preds" {
+                return Err(line.err_at(col, "expected `preds`"));
+            }
+            line.expect_punct('=')?;
+            if line.peek().is_some() {
+                loop {
+                    let col = line.col();
+                    let pred = line.block()?;
+                    state.block_refs.push((pred, line.number, col));
+                    preds.push(pred);
+                    if !line.eat(&Tok::Punct(',')) {
+                        break;
+                    }
+                }
+            }
+        }
+        line.expect_end()?;
+        state.func.blocks.push(BasicBlock {
+            instructions: vec![],
+            terminator: Terminator::NoTerm,
+            preds,
+            id: id.0,
+            par_moves: vec![],
+        });
+        Ok(())
+    }
+
+    fn parse_block_line(
+        &mut self,
+        state: &mut FunctionState,
+        mut line: Line,
+        calls: &mut Vec<(usize, Option<ValueId>, FunctionId, usize, usize)>,
+    ) -> Result<(), ParseError> {
+        let block_id = BlockId(state.func.blocks.len() - 1);
+        if state.func.blocks[block_id.0].terminator != Terminator::NoTerm {
+            return Err(line.err(format!("block ${} already has a terminator", block_id.0)));
+        }
+
+        if line.peek() == Some(&Tok::Punct('[')) {
+            let dsts = line.quoted_values()?;
+            if !line.eat(&Tok::Arrow) {
+                return Err(line.unexpected("`<-`"));
+            }
+            let col = line.col();
+            let srcs = line.quoted_values()?;
+            line.expect_end()?;
+            if dsts.len() != srcs.len() {
+                return Err(line.err_at(col, "par-move lists differ in length"));
+            }
+            for (dst, src) in dsts.into_iter().zip(srcs) {
+                state.ensure_value(dst);
+                state.ensure_value(src);
+                if state.defs[dst.0].is_none() {
+                    state.defs[dst.0] = Some((block_id, line.number));
+                }
+                state.func.blocks[block_id.0].par_moves.push((dst, src));
+            }
+            return Ok(());
+        }
+
+        if let Some(Tok::Ident(kw)) = line.peek() {
+            let term = match kw.as_str() {
+                "ret" => {
+                    line.pos += 1;
+                    Some(Terminator::Return(line.value()?))
+                }
+                "jmp" => {
+                    line.pos += 1;
+                    let col = line.col();
+                    let target = line.block()?;
+                    state.block_refs.push((target, line.number, col));
+                    Some(Terminator::Jump(target))
+                }
+                "br" => {
+                    line.pos += 1;
+                    let cond = line.value()?;
+                    line.expect_punct(',')?;
+                    let col = line.col();
+                    let t = line.block()?;
+                    state.block_refs.push((t, line.number, col));
+                    line.expect_punct(',')?;
+                    let col = line.col();
+                    let f = line.block()?;
+                    state.block_refs.push((f, line.number, col));
+                    Some(Terminator::Branch(cond, t, f))
+                }
+                "noterm" => {
+                    line.pos += 1;
+                    Some(Terminator::NoTerm)
+                }
+                _ => None,
+            };
+            if let Some(term) = term {
+                line.expect_end()?;
+                state.func.blocks[block_id.0].terminator = term;
+                return Ok(());
+            }
+        }
+
+        // [%n[: ty] =] operation
+        let mut yielded = None;
+        let mut ty = None;
+        if let Some(Tok::Value(id)) = line.peek() {
+            if matches!(line.peek_at(1), Some(Tok::Punct(':' | '='))) {
+                let val = ValueId(*id);
+                let col = line.col();
+                line.pos += 1;
+                if line.eat(&Tok::Punct(':')) {
+                    ty = Some(line.ty()?);
+                }
+                line.expect_punct('=')?;
+                state.ensure_value(val);
+                // values may be defined by several copies after par-move lowering
+                let is_copy = matches!(line.peek(), Some(Tok::Ident(op)) if op == "copy");
+                match state.defs[val.0] {
+                    Some((_, prev)) if !is_copy => {
+                        return Err(line.err_at(
+                            col,
+                            format!("value {} is already defined on line {}", val, prev),
+                        ))
+                    }
+                    Some(_) => (),
+                    None => state.defs[val.0] = Some((block_id, line.number)),
+                }
+                if ty.is_some() || state.types[val.0].is_none() {
+                    state.types[val.0] = ty.clone();
+                }
+                yielded = Some(val);
+            }
+        }
+
+        let col = line.col();
+        let operation = match line.next("an instruction")? {
+            Tok::Int(val) => Operation::Integer(val),
+            Tok::Phi => Operation::Phi(line.value_list(None)?),
+            Tok::Ident(name) => match name.as_str() {
+                "load" => match line.peek() {
+                    Some(Tok::Var(_)) => Operation::LoadVar(line.var()?),
+                    _ => Operation::Load(line.value()?),
+                },
+                "alloca" => Operation::Alloca(line.ty()?),
+                "addr" => {
+                    let global_col = line.col();
+                    match line.next("a global")? {
+                        Tok::Global(id) if id < state.globals.len() => {
+                            Operation::GlobalAddr(GlobalId(id))
+                        }
+                        Tok::Global(id) => {
+                            return Err(line.err_at(global_col, format!("undefined global @{}", id)))
+                        }
+                        tok => {
+                            return Err(line
+                                .err_at(global_col, format!("expected a global, found {}", tok)))
+                        }
+                    }
+                }
+                "copy" => Operation::Copy(line.value()?),
+                "select" => {
+                    let cond = line.value()?;
+                    let a = line.value()?;
+                    let b = line.value()?;
+                    Operation::Select(cond, a, b)
+                }
+                "gep" => {
+                    let ptr = line.value()?;
+                    let idx = line.value()?;
+                    Operation::Gep(ptr, idx)
+                }
+                "arg" => {
+                    let idx_col = line.col();
+                    match line.next("an argument index")? {
+                        Tok::Int(idx) if idx >= 0 && (idx as usize) < state.func.args.len() => {
+                            Operation::Arg(idx as usize)
+                        }
+                        Tok::Int(idx) => {
+                            return Err(line.err_at(
+                                idx_col,
+                                format!("`{}` has no argument {}", state.func.name, idx),
+                            ))
+                        }
+                        tok => {
+                            return Err(line.err_at(
+                                idx_col,
+                                format!("expected an argument index, found {}", tok),
+                            ))
+                        }
+                    }
+                }
+                "store" if !matches!(line.peek(), Some(Tok::Var(_))) => {
+                    let ptr = line.value()?;
+                    let val = line.value()?;
+                    Operation::Store(ptr, val)
+                }
+                "store" => {
+                    let var = line.var()?;
+                    let val = line.value()?;
+                    if let Some(var) = state.func.variables.get_mut(var.0) {
+                        var.bbs_assign_to.insert(block_id);
+                    }
+                    Operation::StoreVar(var, val)
+                }
+                "call" => {
+                    let callee_col = line.col();
+                    let callee = match line.next("a function")? {
+                        Tok::Block(id) => FunctionId(id),
+                        tok => {
+                            return Err(line
+                                .err_at(callee_col, format!("expected a function, found {}", tok)))
+                        }
+                    };
+                    line.expect_punct('(')?;
+                    let args = line.value_list(Some(')'))?;
+                    line.expect_punct(')')?;
+                    if ty.is_none() {
+                        calls.push((state.func.id, yielded, callee, line.number, callee_col));
+                    }
+                    Operation::Call(callee, args)
+                }
+                op => {
+                    if let Some(op) = parse_binop(op) {
+                        let lhs = line.value()?;
+                        let rhs = line.value()?;
+                        Operation::BinOp(op, lhs, rhs)
+                    } else if let Some(op) = parse_unop(op) {
+                        Operation::UnOp(op, line.value()?)
+                    } else if let Some(op) = parse_cast(op) {
+                        let val = line.value()?;
+                        Operation::Cast(op, val, line.ty()?)
+                    } else {
+                        return Err(line.err_at(col, format!("unknown instruction `{}`", op)));
+                    }
+                }
+            },
+            tok => return Err(line.err_at(col, format!("expected an instruction, found {}", tok))),
+        };
+        line.expect_end()?;
+
+        if let Operation::LoadVar(var) | Operation::StoreVar(var, _) = operation {
+            if var.0 >= state.func.variables.len() {
+                return Err(line.err_at(col, format!("undeclared variable #{}", var.0)));
+            }
+        }
+        for val in operation.operands() {
+            state.ensure_value(val);
+        }
+
+        state.func.blocks[block_id.0]
+            .instructions
+            .push(Instruction { yielded, operation });
+        Ok(())
+    }
+
+    fn finish_function(mut state: FunctionState) -> Result<Function, ParseError> {
+        for (target, line, col) in &state.block_refs {
+            if target.0 >= state.func.blocks.len() {
+                return Err(ParseError {
+                    line: *line,
+                    col: *col,
+                    message: format!("reference to undefined block ${}", target.0),
+                });
+            }
+        }
+        let term_operands: Vec<ValueId> = state
+            .func
+            .blocks
+            .iter()
+            .filter_map(|block| block.terminator.operand())
+            .collect();
+        for val in term_operands {
+            state.ensure_value(val);
+        }
+
+        // infer missing types until nothing changes
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in &state.func.blocks {
+                for instr in &block.instructions {
+                    let Some(val) = instr.yielded else { continue };
+                    if state.types[val.0].is_some() {
+                        continue;
+                    }
+                    let ty = match &instr.operation {
+                        Operation::Integer(_) => Some(Type::Integer(32, true)),
+                        Operation::LoadVar(var) => Some(state.func.variables[var.0].ty.clone()),
+                        Operation::Arg(idx) => Some(state.func.args[*idx].1.clone()),
+                        Operation::Alloca(ty) => Some(Type::Pointer(Box::new(ty.clone()))),
+                        Operation::GlobalAddr(global) => {
+                            Some(Type::Pointer(Box::new(state.globals[global.0].clone())))
+                        }
+                        Operation::Load(ptr) => state.types[ptr.0]
+                            .as_ref()
+                            .and_then(|ty| ty.pointee())
+                            .cloned(),
+                        Operation::Gep(ptr, _) | Operation::Copy(ptr) | Operation::UnOp(_, ptr) => {
+                            state.types[ptr.0].clone()
+                        }
+                        Operation::Cast(_, _, ty) => Some(ty.clone()),
+                        Operation::Select(_, a, b) => state.types[a.0]
+                            .clone()
+                            .or_else(|| state.types[b.0].clone()),
+                        Operation::BinOp(_, lhs, rhs) => state.types[lhs.0]
+                            .clone()
+                            .or_else(|| state.types[rhs.0].clone()),
+                        Operation::Phi(vals) => vals.iter().find_map(|v| state.types[v.0].clone()),
+                        // resolved once every function has been parsed
+                        Operation::Call(..) | Operation::StoreVar(..) | Operation::Store(..) => {
+                            None
+                        }
+                    };
+                    if ty.is_some() {
+                        state.types[val.0] = ty;
+                        changed = true;
+                    }
+                }
+                for (dst, src) in &block.par_moves {
+                    if state.types[dst.0].is_none() && state.types[src.0].is_some() {
+                        state.types[dst.0] = state.types[src.0].clone();
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let func = &mut state.func;
+        func.values = state
+            .types
+            .iter()
+            .zip(&state.defs)
+            .map(|(ty, def)| Value {
+                ty: ty.clone().unwrap_or(Type::Void),
+                children: vec![],
+                owner: def.map(|(block, _)| block).unwrap_or(BlockId(0)),
+            })
+            .collect();
+        for block in func.blocks.iter() {
+            for instr in &block.instructions {
+                if let Some(val) = instr.yielded {
+                    for operand in instr.operation.operands() {
+                        func.values[operand.0].children.push(val);
+                    }
+                }
+            }
+        }
+
+        Ok(state.func)
+    }
+}
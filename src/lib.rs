@@ -1,19 +1,46 @@
 #![allow(dead_code)]
 
 pub mod algos;
+pub mod analysis;
 pub mod arch;
 pub mod builder;
+pub mod interp;
 pub mod ir;
 pub mod regalloc;
 pub mod vcode;
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use crate::{
-        arch::urcl::UrclSelector,
+        algos::{
+            lower_to_ssa,
+            opt::{constant_folding::ConstantFolding, FunctionPass, OptPass},
+            out_of_ssa::{out_of_ssa, OutOfSsa},
+            pass_manager::{Analysis, FunctionAnalyses, PassError, PassManager},
+            phi_lowering::lower_phis,
+            remove_critical_edges::remove_critical_edges,
+            verify::verify,
+        },
+        analysis::{
+            call_graph::CallGraph,
+            dominators::{
+                postorder, reverse_postorder, Dfs, DominanceFrontiers, DominatorTree,
+                PostDominatorTree,
+            },
+            liveness::Liveness,
+            loops::LoopInfo,
+        },
+        arch::urcl::{StackBase, UrclAluOp, UrclInstr, UrclSelector, URCL_REG_FP},
         builder::ModuleBuilder,
-        ir::{BinOp, Terminator, Type},
-        regalloc::linear_scan::LinearScanRegAlloc,
+        interp::{InterpError, Interpreter},
+        ir::{
+            parse::parse_module, Algo, BinOp, BlockId, CastOp, Function, FunctionId, Linkage,
+            Module, Operation, Terminator, Type, UnOp, ValueId,
+        },
+        regalloc::{linear_scan::LinearScanRegAlloc, Regalloc, VReg},
+        vcode::{LabelDest, VCodeInstr, VCodeOptions},
     };
 
     #[test]
@@ -77,6 +104,2247 @@ mod tests {
         println!("{}", vcode);
     }
 
+    #[test]
+    fn parse_round_trip() {
+        let mut builder = ModuleBuilder::new("round trip");
+        let callee = builder.push_function("one", Type::Integer(8, false), vec![], None);
+        let main = builder.push_function(
+            "main",
+            Type::Integer(32, true),
+            vec![("argc".to_string(), Type::Pointer(Box::new(Type::Integer(8, false))))],
+            Some(Linkage::Public),
+        );
+
+        builder.switch_to_fn(callee);
+        let entry = builder.push_block();
+        builder.switch_to_block(entry);
+        let one = builder.build_integer(1, Type::Integer(8, false));
+        builder.set_terminator(Terminator::Return(one));
+
+        builder.switch_to_fn(main);
+        let entry = builder.push_block();
+        let then = builder.push_block();
+        let end = builder.push_block();
+        builder.switch_to_block(entry);
+        let x = builder.push_variable("x", Type::Integer(32, true));
+        let val = builder.build_integer(-7, Type::Integer(32, true));
+        builder.build_store(x, val);
+        let ld_x = builder.build_load(x);
+        builder.set_terminator(Terminator::Branch(ld_x, then, end));
+        builder.switch_to_block(then);
+        let ld_x = builder.build_load(x);
+        let sum = builder.build_binop(BinOp::Add, ld_x, val, Type::Integer(32, true));
+        builder.build_store(x, sum);
+        builder.set_terminator(Terminator::Jump(end));
+        builder.switch_to_block(end);
+        let ld_x = builder.build_load(x);
+        builder.set_terminator(Terminator::Return(ld_x));
+
+        let mut module = builder.build();
+        let text = module.to_string();
+        let parsed = text.parse::<Module>().unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.functions[1].variables, module.functions[1].variables);
+
+        module.apply_mandatory_transforms();
+        let text = module.to_string();
+        assert_eq!(text.parse::<Module>().unwrap().to_string(), text);
+    }
+
+    #[test]
+    fn parse_untyped_fixture() {
+        let src = "
+$0: public fn main() s32 {
+$0: ; preds =
+    %0 = 2
+    %1 = mul %0 %0
+    ret %1
+}
+";
+        let module = parse_module(src).unwrap();
+        assert_eq!(module.functions[0].values[1].ty, Type::Integer(32, true));
+        assert_eq!(module.functions[0].values[1].owner, BlockId(0));
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let src = "$0: public fn main() s32 {\n$0: ; preds =\n    %0 = frob %1\n}\n";
+        let err = parse_module(src).unwrap_err();
+        assert_eq!((err.line, err.col), (3, 10));
+        assert_eq!(err.to_string(), "3:10: unknown instruction `frob`");
+    }
+
+    #[test]
+    fn verify_reports_broken_module() {
+        let src = "
+/* [@edges_splitted, @ssa_constructed] module broken */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = add %1 %1
+    br %0, $1, $2
+$1: ; preds = $0
+    %1: s32 = 1
+    jmp $2
+$2: ; preds = $1
+    %2: s32 = Φ %0
+    noterm
+}
+";
+        let errors = verify(&parse_module(src).unwrap());
+        let errors: Vec<(Option<BlockId>, Option<usize>, &str)> = errors
+            .iter()
+            .map(|e| (e.block, e.instr, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (Some(BlockId(2)), None, "block has no terminator"),
+                (
+                    Some(BlockId(2)),
+                    None,
+                    "preds are [$1] but the terminators branching here are in [$0, $1]"
+                ),
+            ]
+        );
+
+        let src = src.replace("$2: ; preds = $1", "$2: ; preds = $0, $1");
+        let errors = verify(&parse_module(&src).unwrap());
+        let errors: Vec<(Option<BlockId>, Option<usize>, &str)> = errors
+            .iter()
+            .map(|e| (e.block, e.instr, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (Some(BlockId(2)), None, "block has no terminator"),
+                (Some(BlockId(2)), None, "critical edge from $0 after edge splitting"),
+                (
+                    Some(BlockId(0)),
+                    Some(0),
+                    "definition of %1 in $1 does not dominate its use"
+                ),
+                (Some(BlockId(2)), Some(0), "Φ has 1 operands but the block has 2 preds"),
+            ]
+        );
+
+        // with verification on, every step of the mandatory transforms is
+        // checked, starting with their input
+        let mut module = parse_module(&src).unwrap();
+        module.set_verify_transforms(true);
+        let panic = std::panic::catch_unwind(move || module.apply_mandatory_transforms())
+            .unwrap_err()
+            .downcast::<String>()
+            .unwrap();
+        assert!(panic.starts_with("module broken failed verification after input:\n"));
+
+        let mut module = parse_module(
+            "
+$0: public fn main() s32 {
+    var #0 x: s32
+$0: ; preds =
+    %0: s32 = 1
+    br %0, $1, $2
+$1: ; preds = $0
+    %1: s32 = 2
+    store #0 %1
+    jmp $2
+$2: ; preds = $0, $1
+    %2: s32 = load #0
+    ret %2
+}
+",
+        )
+        .unwrap();
+        module.set_verify_transforms(true);
+        module.apply_mandatory_transforms();
+        assert_eq!(verify(&module), vec![]);
+    }
+
+    #[test]
+    fn interp_stages_agree() {
+        let mut builder = ModuleBuilder::new("diamond");
+        let main = builder.push_function("main", Type::Integer(32, true), vec![], None);
+        builder.switch_to_fn(main);
+        let entry = builder.push_block();
+        let edge = builder.push_block();
+        let end = builder.push_block();
+        builder.switch_to_block(entry);
+        let x = builder.push_variable("x", Type::Integer(32, true));
+        let y = builder.push_variable("y", Type::Integer(32, true));
+        let three = builder.build_integer(3, Type::Integer(32, true));
+        builder.build_store(x, three);
+        builder.build_store(y, three);
+        let ld_x = builder.build_load(x);
+        builder.set_terminator(Terminator::Branch(ld_x, edge, end));
+        builder.switch_to_block(edge);
+        let ld_x = builder.build_load(x);
+        let ld_y = builder.build_load(y);
+        let val = builder.build_binop(BinOp::Mul, ld_x, ld_y, Type::Integer(32, true));
+        builder.build_store(y, val);
+        builder.set_terminator(Terminator::Jump(end));
+        builder.switch_to_block(end);
+        let ld_y = builder.build_load(y);
+        builder.set_terminator(Terminator::Return(ld_y));
+
+        let mut module = builder.build();
+        let run = |module: &Module| Interpreter::new(module).run(main, &[]);
+        assert_eq!(run(&module), Ok(9));
+        remove_critical_edges(&mut module);
+        assert_eq!(run(&module), Ok(9));
+        lower_to_ssa::lower(&mut module);
+        assert_eq!(run(&module), Ok(9));
+        lower_phis(&mut module);
+        assert_eq!(run(&module), Ok(9));
+    }
+
+    #[test]
+    fn interp_calls_and_widths() {
+        let src = "
+$0: public fn main() u8 {
+    var #0 i: u8
+$0: ; preds =
+    %0: u8 = 250
+    store #0 %0
+    jmp $1
+$1: ; preds = $0, $1
+    %1: u8 = load #0
+    %2: u8 = call $1()
+    %3: u8 = add %1 %2
+    store #0 %3
+    %4: u8 = lt %3 %1
+    br %4, $2, $1
+$2: ; preds = $1
+    %5: s8 = 200
+    %6: s8 = -1
+    %7: s8 = div %5 %6
+    ret %7
+}
+$1: private fn one() u8 {
+$0: ; preds =
+    %0: u8 = 1
+    ret %0
+}
+";
+        let module = parse_module(src).unwrap();
+        let mut interp = Interpreter::new(&module);
+        // 200 wraps to -56 as an s8, and -56 / -1 = 56
+        assert_eq!(interp.run(FunctionId(0), &[]), Ok(56));
+        assert_eq!(interp.steps(), 55);
+
+        let mut interp = Interpreter::new(&module).with_step_limit(20);
+        assert_eq!(interp.run(FunctionId(0), &[]), Err(InterpError::StepLimitExceeded));
+    }
+
+    /// Runs `main` at every lowering stage, verifying the module in between,
+    /// and checks that every stage returns `expected`.
+    fn check_stages(src: &str, args: &[i64], expected: i64) -> Module {
+        let mut module = parse_module(src).unwrap();
+        let main = FunctionId(0);
+        let run = |module: &Module| {
+            assert_eq!(verify(module), vec![], "{}", module);
+            Interpreter::new(module)
+                .with_step_limit(100_000)
+                .run(main, args)
+        };
+        assert_eq!(run(&module), Ok(expected));
+        remove_critical_edges(&mut module);
+        assert_eq!(run(&module), Ok(expected));
+        lower_to_ssa::lower(&mut module);
+        assert_eq!(run(&module), Ok(expected), "{}", module);
+        lower_phis(&mut module);
+        assert_eq!(run(&module), Ok(expected), "{}", module);
+        module
+    }
+
+    #[test]
+    fn ssa_loop_nest() {
+        // s = 0; for i in 0..n { for j in 0..i { s += j } }
+        let src = "
+$0: public fn main(n: s32) s32 {
+    var #0 n: s32
+    var #1 i: s32
+    var #2 j: s32
+    var #3 s: s32
+$0: ; preds =
+    %0: s32 = 5
+    store #0 %0
+    %1: s32 = 0
+    store #1 %1
+    store #3 %1
+    jmp $1
+$1: ; preds = $0, $5
+    %2: s32 = load #1
+    %3: s32 = load #0
+    %4: s32 = lt %2 %3
+    br %4, $2, $6
+$2: ; preds = $1
+    %5: s32 = 0
+    store #2 %5
+    jmp $3
+$3: ; preds = $2, $4
+    %6: s32 = load #2
+    %7: s32 = load #1
+    %8: s32 = lt %6 %7
+    br %8, $4, $5
+$4: ; preds = $3
+    %9: s32 = load #3
+    %10: s32 = load #2
+    %11: s32 = add %9 %10
+    store #3 %11
+    %12: s32 = 1
+    %13: s32 = add %10 %12
+    store #2 %13
+    jmp $3
+$5: ; preds = $3
+    %14: s32 = load #1
+    %15: s32 = 1
+    %16: s32 = add %14 %15
+    store #1 %16
+    jmp $1
+$6: ; preds = $1
+    %17: s32 = load #3
+    ret %17
+}
+";
+        let module = check_stages(src, &[5], 10);
+
+        for block in module.functions[0].blocks.iter() {
+            for instr in block.instructions.iter() {
+                assert!(!matches!(instr.operation, Operation::Phi(_)));
+            }
+        }
+    }
+
+    #[test]
+    fn ssa_entry_is_loop_header() {
+        let src = "
+$0: public fn main() s32 {
+    var #0 x: s32
+$0: ; preds = $1
+    %0: s32 = load #0
+    %1: s32 = 3
+    %2: s32 = add %0 %1
+    store #0 %2
+    %3: s32 = 10
+    %4: s32 = lt %2 %3
+    br %4, $1, $2
+$1: ; preds = $0
+    jmp $0
+$2: ; preds = $0
+    %5: s32 = load #0
+    ret %5
+}
+";
+        check_stages(src, &[], 12);
+    }
+
+    #[test]
+    fn ssa_entry_self_loop() {
+        let src = "
+$0: public fn main() s32 {
+    var #0 x: s32
+$0: ; preds = $0
+    %0: s32 = load #0
+    %1: s32 = 3
+    %2: s32 = add %0 %1
+    store #0 %2
+    %3: s32 = 10
+    %4: s32 = lt %2 %3
+    br %4, $0, $1
+$1: ; preds = $0
+    %5: s32 = load #0
+    ret %5
+}
+";
+        let module = check_stages(src, &[], 12);
+        let func = &module.functions[0];
+        assert_eq!(func.blocks[0].terminator, Terminator::Jump(BlockId(2)));
+        assert_eq!(func.blocks[2].preds, vec![BlockId(3), BlockId(0)]);
+    }
+
+    #[test]
+    fn dominator_queries() {
+        // diamond into a loop, plus an unreachable block spinning forever
+        let src = "
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = 1
+    br %0, $1, $2
+$1: ; preds = $0
+    jmp $3
+$2: ; preds = $0
+    jmp $3
+$3: ; preds = $1, $2, $4
+    br %0, $4, $5
+$4: ; preds = $3
+    jmp $3
+$5: ; preds = $3
+    ret %0
+$6: ; preds = $6
+    jmp $6
+}
+";
+        let module = parse_module(src).unwrap();
+        let func = &module.functions[0];
+        let b = BlockId;
+
+        let doms = DominatorTree::compute(func);
+        let idoms: Vec<Option<BlockId>> = (0..7).map(|i| doms.idom(b(i))).collect();
+        let expected = [None, Some(0), Some(0), Some(0), Some(3), Some(3), None];
+        assert_eq!(idoms, expected.map(|d| d.map(b)));
+        assert!(doms.dominates(b(0), b(5)));
+        assert!(doms.dominates(b(3), b(3)));
+        assert!(!doms.strictly_dominates(b(3), b(3)));
+        assert!(!doms.dominates(b(1), b(3)));
+        assert!(!doms.dominates(b(6), b(6)));
+        assert_eq!(doms.children(b(0)), vec![b(1), b(2), b(3)]);
+        assert_eq!(doms.preorder()[0], b(0));
+
+        let frontiers = DominanceFrontiers::compute(func, &doms);
+        assert_eq!(frontiers.frontier(b(1)), &[b(3)]);
+        assert_eq!(frontiers.frontier(b(4)), &[b(3)]);
+        assert_eq!(frontiers.frontier(b(3)), &[b(3)]);
+        assert!(frontiers.frontier(b(0)).is_empty());
+        assert_eq!(frontiers.iterated([b(1)]), vec![b(3)]);
+
+        let pdoms = PostDominatorTree::compute(func);
+        assert_eq!(pdoms.exits(), vec![b(5)]);
+        assert_eq!(pdoms.ipdom(b(5)), None);
+        assert_eq!(pdoms.ipdom(b(4)), Some(b(3)));
+        assert_eq!(pdoms.ipdom(b(0)), Some(b(3)));
+        assert!(pdoms.post_dominates(b(3), b(1)));
+        assert!(!pdoms.post_dominates(b(1), b(0)));
+        assert!(!pdoms.reaches_exit(b(6)));
+
+        let rpo = reverse_postorder(func);
+        assert_eq!(rpo, vec![b(0), b(2), b(1), b(3), b(5), b(4)]);
+        assert_eq!(rpo, doms.reverse_postorder());
+        let mut post = postorder(func);
+        post.reverse();
+        assert_eq!(post, rpo);
+        let dfs: Vec<BlockId> = Dfs::new(func).collect();
+        assert_eq!(dfs, vec![b(0), b(1), b(3), b(4), b(5), b(2)]);
+    }
+
+    #[test]
+    fn liveness_across_back_edge() {
+        // %0 is last mentioned at the top of the loop but needed every trip
+        let src = "
+/* [@ssa_constructed] module loop */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = 1
+    jmp $1
+$1: ; preds = $0, $1
+    %1: s32 = add %0 %0
+    %2: s32 = 7
+    %3: s32 = lt %1 %2
+    br %3, $1, $2
+$2: ; preds = $1
+    ret %2
+}
+";
+        let module = parse_module(src).unwrap();
+        let liveness = Liveness::compute(&module.functions[0]);
+        let set =
+            |vals: &[usize]| -> HashSet<ValueId> { vals.iter().map(|v| ValueId(*v)).collect() };
+        assert_eq!(liveness.live_in(BlockId(0)), &set(&[]));
+        assert_eq!(liveness.live_in(BlockId(1)), &set(&[0]));
+        assert_eq!(liveness.live_out(BlockId(1)), &set(&[0, 2]));
+        assert_eq!(liveness.live_in(BlockId(2)), &set(&[2]));
+        assert!(!liveness.is_live_out(ValueId(1), BlockId(1)));
+
+        let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+        let add = vcode.functions[0].instrs[1]
+            .instrs
+            .iter()
+            .find_map(|instr| match instr {
+                UrclInstr::AluOp { dst, src1, .. } => Some((*dst, *src1)),
+                _ => None,
+            })
+            .unwrap();
+        assert_ne!(add.0, add.1, "%1 must not clobber %0 inside the loop");
+    }
+
+    #[test]
+    fn const_fold_matches_interp() {
+        let ops = [
+            "add", "sub", "mul", "div", "mod", "and", "or", "xor", "shl", "shr", "eq", "ne", "lt",
+            "le", "gt", "ge",
+        ];
+        let types = ["s8", "u8", "s16", "u32", "s64"];
+        let operands = [(100, 100), (-7, 2), (-128, -1), (255, 3), (5, 0), (1, 9)];
+        for op in ops {
+            for ty in types {
+                for (lhs, rhs) in operands {
+                    let src = format!(
+                        "$0: public fn main() {ty} {{\n$0: ; preds =\n    %0: {ty} = {lhs}\n    \
+                         %1: {ty} = {rhs}\n    %2: {ty} = {op} %0 %1\n    ret %2\n}}\n"
+                    );
+                    let mut module = parse_module(&src).unwrap();
+                    let expected = Interpreter::new(&module).run(FunctionId(0), &[]);
+                    ConstantFolding::default().run(&mut module);
+                    let folded = &module.functions[0].blocks[0].instructions[2].operation;
+                    match expected {
+                        Ok(val) => assert_eq!(folded, &Operation::Integer(val), "{src}"),
+                        Err(_) => assert!(matches!(folded, Operation::BinOp(..)), "{src}"),
+                    }
+                    assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn const_fold_branches() {
+        let src = "
+/* [@ssa_constructed] module branches */
+$0: public fn main() u8 {
+$0: ; preds =
+    %0: u8 = 200
+    %1: u8 = 100
+    %2: u8 = add %0 %1
+    %3: u8 = lt %2 %1
+    br %3, $1, $2
+$1: ; preds = $0
+    %4: u8 = 1
+    jmp $2
+$2: ; preds = $0, $1
+    %5: u8 = Φ %0, %4
+    %6: u8 = mul %5 %2
+    ret %6
+}
+";
+        let mut module = parse_module(src).unwrap();
+        let expected = Interpreter::new(&module).run(FunctionId(0), &[]);
+        // 200 + 100 wraps to 44, so the branch to $1 is always taken
+        assert_eq!(expected, Ok(44));
+        ConstantFolding::default().run(&mut module);
+        assert!(verify(&module).is_empty(), "{}", module);
+        let func = &module.functions[0];
+        assert_eq!(func.blocks[0].terminator, Terminator::Jump(BlockId(1)));
+        assert_eq!(func.blocks[2].preds, vec![BlockId(1)]);
+        assert_eq!(
+            func.blocks[2].instructions[0].operation,
+            Operation::Phi(vec![ValueId(4)])
+        );
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+    }
+
+    #[test]
+    fn pass_manager_pipeline() {
+        struct ReachableBlocks(usize);
+        impl FunctionPass for ReachableBlocks {
+            fn name(&self) -> &'static str {
+                "reachable"
+            }
+            fn run_on_function(
+                &mut self,
+                func: &mut Function,
+                analyses: &mut FunctionAnalyses,
+            ) -> bool {
+                self.0 = analyses.dominators(func).reverse_postorder().len();
+                false
+            }
+        }
+
+        let src = "
+$0: public fn main() s32 {
+    var #0 x: s32
+$0: ; preds =
+    %0: s32 = 6
+    %1: s32 = 7
+    %2: s32 = mul %0 %1
+    store #0 %2
+    br %2, $1, $2
+$1: ; preds = $0
+    %3: s32 = load #0
+    ret %3
+$2: ; preds = $0
+    ret %0
+}
+";
+        assert_eq!(
+            PassManager::from_pipeline("constfold, frob").err(),
+            Some(PassError::UnknownPass("frob".to_string()))
+        );
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let mut pm = PassManager::from_pipeline("lower-phis").unwrap();
+        assert_eq!(
+            pm.run(&mut module),
+            Err(PassError::MissingRequirement {
+                pass: "lower-phis",
+                requires: Algo::CriticalEdgeSplitting
+            })
+        );
+
+        let mut pm = PassManager::from_pipeline("split-critical-edges,ssa,constfold").unwrap();
+        pm.add_function_pass(ReachableBlocks(0));
+        pm.run(&mut module).unwrap();
+        assert!(pm.analyses(0).unwrap().is_cached(Analysis::Dominators));
+        let names: Vec<&str> = pm.timings().iter().map(|t| t.name).collect();
+        assert_eq!(names, pm.pass_names());
+        assert!(pm.timing_report().contains("constfold"));
+        // the branch on 42 is folded, leaving $2 unreachable
+        let term = &module.functions[0].blocks[0].terminator;
+        assert_eq!(term, &Terminator::Jump(BlockId(1)));
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(42));
+
+        let mut pm = PassManager::from_pipeline("lower-phis").unwrap();
+        pm.run(&mut module).unwrap();
+        assert!(!pm.analyses(0).unwrap().is_cached(Analysis::Dominators));
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(42));
+    }
+
+    #[test]
+    fn dce_removes_dead_code() {
+        // %5/%7 are a dead Φ cycle, %10 is unused, and $3 is unreachable
+        let src = "
+/* [@ssa_constructed] module dce */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = 0
+    %1: s32 = 1
+    %2: s32 = 5
+    %3: s32 = call $1()
+    jmp $1
+$1: ; preds = $0, $2
+    %4: s32 = Φ %0, %6
+    %5: s32 = Φ %0, %7
+    %10: s32 = mul %4 %4
+    %8: s32 = lt %4 %2
+    br %8, $2, $4
+$2: ; preds = $1
+    %6: s32 = add %4 %1
+    %7: s32 = add %5 %2
+    jmp $1
+$3: ; preds =
+    %9: s32 = 9
+    jmp $4
+$4: ; preds = $1, $3
+    %11: s32 = Φ %4, %9
+    ret %11
+}
+
+$1: private fn f() s32 {
+$0: ; preds =
+    %0: s32 = 3
+    ret %0
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let expected = Interpreter::new(&module).run(FunctionId(0), &[]);
+        assert_eq!(expected, Ok(5));
+        let mut pm = PassManager::from_pipeline("dce").unwrap();
+        pm.run(&mut module).unwrap();
+
+        let func = &module.functions[0];
+        assert_eq!(func.blocks.len(), 4);
+        assert!(func.blocks.iter().enumerate().all(|(i, b)| b.id == i));
+        assert_eq!(func.blocks[3].preds, vec![BlockId(1)]);
+        let defined: Vec<usize> = func
+            .blocks
+            .iter()
+            .flat_map(|b| b.instructions.iter().filter_map(|i| i.yielded.map(|v| v.0)))
+            .collect();
+        assert_eq!(defined, vec![0, 1, 2, 3, 4, 8, 6, 11]);
+        let mut rebuilt = func.clone();
+        rebuilt.rebuild_children();
+        assert_eq!(&rebuilt, func);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+    }
+
+    #[test]
+    fn sccp_through_phis() {
+        // x stays 5 on every executed path, so the x == 5 test and the
+        // $4 arm are dead
+        let src = "
+$0: public fn main() s32 {
+    var #0 x: s32
+    var #1 i: s32
+    var #2 s: s32
+$0: ; preds =
+    %0: s32 = 5
+    store #0 %0
+    %1: s32 = 0
+    store #1 %1
+    store #2 %1
+    jmp $1
+$1: ; preds = $0, $6
+    %2: s32 = load #1
+    %3: s32 = 10
+    %4: s32 = lt %2 %3
+    br %4, $2, $5
+$2: ; preds = $1
+    %5: s32 = load #0
+    %6: s32 = 5
+    %7: s32 = eq %5 %6
+    br %7, $3, $4
+$3: ; preds = $2
+    %8: s32 = load #2
+    %9: s32 = 1
+    %10: s32 = add %8 %9
+    store #2 %10
+    store #0 %6
+    jmp $6
+$4: ; preds = $2
+    %11: s32 = 7
+    store #0 %11
+    %12: s32 = load #2
+    %13: s32 = 100
+    %14: s32 = add %12 %13
+    store #2 %14
+    jmp $6
+$5: ; preds = $1
+    %18: s32 = load #2
+    ret %18
+$6: ; preds = $3, $4
+    %15: s32 = load #1
+    %16: s32 = 1
+    %17: s32 = add %15 %16
+    store #1 %17
+    jmp $1
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let mut pm = PassManager::from_pipeline("split-critical-edges,ssa").unwrap();
+        pm.run(&mut module).unwrap();
+        let expected = Interpreter::new(&module).run(FunctionId(0), &[]);
+        assert_eq!(expected, Ok(10));
+        let blocks = module.functions[0].blocks.len();
+
+        let mut pm = PassManager::from_pipeline("sccp,dce").unwrap();
+        pm.run(&mut module).unwrap();
+        let func = &module.functions[0];
+        assert_eq!(func.blocks.len(), blocks - 1);
+        let branches = func
+            .blocks
+            .iter()
+            .filter(|b| matches!(b.terminator, Terminator::Branch(..)))
+            .count();
+        assert_eq!(branches, 1);
+        let phis = func
+            .blocks
+            .iter()
+            .flat_map(|b| b.instructions.iter())
+            .filter(|i| matches!(i.operation, Operation::Phi(_)))
+            .count();
+        assert_eq!(phis, 3, "{}", module);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+
+        let mut pm = PassManager::from_pipeline("lower-phis").unwrap();
+        pm.run(&mut module).unwrap();
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+    }
+
+    #[test]
+    fn gvn_merges_redundant_values() {
+        let src = "
+/* [@ssa_constructed] module gvn */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = 3
+    %1: s32 = 3
+    %2: s8 = 3
+    %3: s32 = add %0 %1
+    %4: s32 = add %1 %0
+    %5: s32 = lt %3 %4
+    br %5, $1, $2
+$1: ; preds = $0
+    %6: s32 = mul %3 %3
+    jmp $3
+$2: ; preds = $0
+    %7: s32 = mul %4 %3
+    %8: s32 = 3
+    %9: s32 = sub %7 %8
+    jmp $3
+$3: ; preds = $1, $2
+    %10: s32 = Φ %6, %9
+    %11: s32 = Φ %6, %9
+    %12: s32 = add %10 %11
+    %13: s8 = add %2 %2
+    ret %12
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let expected = Interpreter::new(&module).run(FunctionId(0), &[]);
+        assert_eq!(expected, Ok(66));
+        let mut pm = PassManager::from_pipeline("gvn").unwrap();
+        pm.run(&mut module).unwrap();
+
+        let func = &module.functions[0];
+        let defined: Vec<usize> = func
+            .blocks
+            .iter()
+            .flat_map(|b| b.instructions.iter().filter_map(|i| i.yielded.map(|v| v.0)))
+            .collect();
+        // %7 isn't dominated by %6, and %2 has a different type than %0
+        assert_eq!(defined, vec![0, 2, 3, 5, 6, 7, 9, 10, 12, 13]);
+        let add = &func.blocks[3].instructions[1].operation;
+        assert_eq!(add, &Operation::BinOp(BinOp::Add, ValueId(10), ValueId(10)));
+        assert!(func.values[10].children.contains(&ValueId(12)));
+        assert!(func.values[11].children.is_empty());
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+    }
+
+    #[test]
+    fn loop_info_and_licm() {
+        let src = "
+/* [@ssa_constructed] module licm */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = 0
+    %1: s32 = 5
+    br %1, $1, $4
+$1: ; preds = $0, $3
+    %2: s32 = Φ %0, %12
+    %3: s32 = Φ %0, %7
+    jmp $2
+$2: ; preds = $1, $2
+    %4: s32 = Φ %3, %7
+    %5: s32 = Φ %0, %9
+    %6: s32 = mul %1 %1
+    %7: s32 = add %4 %6
+    %8: s32 = 1
+    %9: s32 = add %5 %8
+    %10: s32 = add %2 %1
+    %11: s32 = lt %9 %1
+    br %11, $2, $3
+$3: ; preds = $2
+    %12: s32 = add %2 %8
+    %13: s32 = lt %12 %1
+    br %13, $1, $4
+$4: ; preds = $0, $3
+    %14: s32 = Φ %0, %7
+    ret %14
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let expected = Interpreter::new(&module).run(FunctionId(0), &[]);
+        assert_eq!(expected, Ok(625));
+
+        let func = &module.functions[0];
+        let info = LoopInfo::compute(func, &DominatorTree::compute(func));
+        let loops = info.loops();
+        assert_eq!(loops.len(), 2);
+        assert_eq!(loops[0].header, BlockId(1));
+        assert_eq!(loops[0].blocks, vec![BlockId(1), BlockId(2), BlockId(3)]);
+        assert_eq!((loops[0].parent, loops[0].depth), (None, 1));
+        assert_eq!(loops[1].blocks, vec![BlockId(2)]);
+        assert_eq!(loops[1].latches, vec![BlockId(2)]);
+        assert_eq!((loops[1].parent, loops[1].depth), (Some(0), 2));
+        let depths: Vec<usize> = (0..5).map(|b| info.loop_depth(BlockId(b))).collect();
+        assert_eq!(depths, vec![0, 1, 2, 1, 0]);
+        // $0 also branches to the exit
+        assert_eq!(loops[0].preheader(func), None);
+        assert_eq!(loops[1].preheader(func), Some(BlockId(1)));
+
+        let mut pm = PassManager::from_pipeline("licm").unwrap();
+        pm.run(&mut module).unwrap();
+        let func = &module.functions[0];
+        let defined = |b: usize| -> Vec<usize> {
+            func.blocks[b]
+                .instructions
+                .iter()
+                .filter_map(|i| i.yielded.map(|v| v.0))
+                .collect()
+        };
+        // the mul and the constant leave both loops, %10 only the inner one
+        assert_eq!(func.blocks.len(), 6);
+        assert_eq!(
+            func.blocks[0].terminator.successors(),
+            [BlockId(5), BlockId(4)]
+        );
+        assert_eq!(defined(5), vec![6, 8]);
+        assert_eq!(defined(1), vec![2, 3, 10]);
+        assert_eq!(defined(2), vec![4, 5, 7, 9, 11]);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+    }
+
+    #[test]
+    fn spill_weight_prefers_loop_values() {
+        // one more register live at once than there are real ones
+        let last = UrclInstr::get_usable_regs().len();
+        let mut regalloc = LinearScanRegAlloc::default();
+        for reg in 0..=last {
+            regalloc.add_def(VReg::Virtual(reg));
+            regalloc.next_instr();
+        }
+        // only the last one is used inside a loop
+        regalloc.set_loop_depth(2);
+        regalloc.add_use(VReg::Virtual(last));
+        regalloc.next_instr();
+        regalloc.set_loop_depth(0);
+        for reg in 0..=last {
+            regalloc.add_use(VReg::Virtual(reg));
+        }
+        regalloc.next_instr();
+
+        let regs = regalloc.alloc_regs::<UrclInstr>();
+        assert!(matches!(regs[&VReg::Virtual(last)], VReg::Real(_)));
+        let spilled = regs
+            .values()
+            .filter(|r| matches!(r, VReg::Spilled(_)))
+            .count();
+        assert_eq!(spilled, 1);
+    }
+
+    #[test]
+    fn lower_external_declaration() {
+        let mut builder = ModuleBuilder::new("ext");
+        let main = builder.push_function("main", Type::Integer(32, true), vec![], None);
+        builder.push_function(
+            "ext",
+            Type::Integer(32, true),
+            vec![],
+            Some(Linkage::External),
+        );
+        builder.switch_to_fn(main);
+        let entry = builder.push_block();
+        builder.switch_to_block(entry);
+        let val = builder.build_integer(3, Type::Integer(32, true));
+        builder.set_terminator(Terminator::Return(val));
+        let mut module = builder.build();
+        module.apply_mandatory_transforms();
+
+        let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+        assert_eq!(vcode.functions.len(), 2);
+        assert!(vcode.functions[1].instrs.is_empty());
+    }
+
+    #[test]
+    fn instcombine_simplifies_to_fixed_point() {
+        let src = "
+/* [] module instcombine */
+$0: public fn main() u32 {
+    var #0 x: u32
+$0: ; preds =
+    %0: u32 = 13
+    store #0 %0
+    %1: u32 = load #0
+    %2: u32 = 0
+    %3: u32 = add %1 %2
+    %4: u32 = 1
+    %5: u32 = mul %4 %3
+    %6: u32 = 8
+    %7: u32 = mul %5 %6
+    %8: u32 = sub %7 %7
+    %9: u32 = xor %5 %5
+    %10: u32 = add %8 %9
+    %11: u32 = eq %5 %1
+    %12: u32 = div %7 %4
+    %13: u32 = 16
+    %14: u32 = mod %12 %13
+    %15: u32 = div %14 %6
+    %16: s32 = -7
+    %17: s32 = 4
+    %18: s32 = mod %16 %17
+    %19: s32 = div %16 %17
+    %20: u32 = add %15 %11
+    %21: u32 = add %20 %10
+    ret %21
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let expected = Interpreter::new(&module).run(FunctionId(0), &[]);
+        assert_eq!(expected, Ok(2));
+        let mut pm = PassManager::from_pipeline("instcombine").unwrap();
+        pm.run(&mut module).unwrap();
+
+        let func = &module.functions[0];
+        let op = |val: usize| -> Operation {
+            func.blocks[0]
+                .instructions
+                .iter()
+                .find(|i| i.yielded == Some(ValueId(val)))
+                .map(|i| i.operation.clone())
+                .unwrap()
+        };
+        let defined = |val: usize| {
+            func.blocks[0]
+                .instructions
+                .iter()
+                .any(|i| i.yielded == Some(ValueId(val)))
+        };
+        // %3, %5 and then %12 are just %1 and %7, so `eq %5 %1` only folds
+        // once %5 is gone
+        assert!(!defined(3) && !defined(5) && !defined(12) && !defined(10));
+        assert_eq!(op(11), Operation::Integer(1));
+        assert_eq!(op(8), Operation::Integer(0));
+        assert_eq!(op(9), Operation::Integer(0));
+        // strength reduced operations get a fresh constant operand
+        let reduced = |val: usize| match op(val) {
+            Operation::BinOp(bin, lhs, rhs) => match op(rhs.0) {
+                Operation::Integer(c) => (bin, lhs, c),
+                other => panic!("{other:?}"),
+            },
+            other => panic!("{other:?}"),
+        };
+        assert_eq!(reduced(7), (BinOp::Shl, ValueId(1), 3));
+        assert_eq!(reduced(14), (BinOp::And, ValueId(7), 15));
+        assert_eq!(reduced(15), (BinOp::Shr, ValueId(14), 3));
+        // signed division and remainder don't round like shifts and masks
+        assert_eq!(
+            op(19),
+            Operation::BinOp(BinOp::Div, ValueId(16), ValueId(17))
+        );
+        assert_eq!(
+            op(18),
+            Operation::BinOp(BinOp::Mod, ValueId(16), ValueId(17))
+        );
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+    }
+
+    #[test]
+    fn simplify_cfg_before_and_after_ssa() {
+        let src = "
+/* [] module chain */
+$0: public fn main() s32 {
+    var #0 x: s32
+$0: ; preds =
+    %0: s32 = 1
+    store #0 %0
+    jmp $1
+$1: ; preds = $0
+    jmp $2
+$2: ; preds = $1
+    %1: s32 = load #0
+    br %1, $3, $3
+$3: ; preds = $2, $2
+    jmp $5
+$4: ; preds =
+    jmp $5
+$5: ; preds = $3, $4
+    %2: s32 = load #0
+    ret %2
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let mut pm = PassManager::from_pipeline("simplify-cfg").unwrap();
+        pm.run(&mut module).unwrap();
+        let func = &module.functions[0];
+        assert_eq!(func.blocks.len(), 1);
+        assert_eq!(func.blocks[0].instructions.len(), 4);
+        assert_eq!(func.blocks[0].terminator, Terminator::Return(ValueId(2)));
+        assert!(func.variables[0].bbs_assign_to.contains(&BlockId(0)));
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(1));
+
+        // splitting the critical edges of a loop nest in SSA form and then
+        // simplifying gives back the original CFG
+        let src = "
+/* [@ssa_constructed] module nest */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = 0
+    %1: s32 = 3
+    br %1, $1, $4
+$1: ; preds = $0, $3
+    %2: s32 = Φ %0, %7
+    %3: s32 = Φ %0, %5
+    jmp $2
+$2: ; preds = $1, $2
+    %4: s32 = Φ %3, %5
+    %5: s32 = add %4 %1
+    %6: s32 = lt %5 %1
+    br %6, $2, $3
+$3: ; preds = $2
+    %7: s32 = add %2 %1
+    %8: s32 = lt %7 %5
+    br %8, $1, $4
+$4: ; preds = $0, $3
+    %9: s32 = Φ %0, %5
+    ret %9
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let expected = Interpreter::new(&module).run(FunctionId(0), &[]);
+        assert_eq!(expected, Ok(3));
+        let original = module.functions[0].clone();
+        let mut pm = PassManager::from_pipeline("split-critical-edges").unwrap();
+        pm.run(&mut module).unwrap();
+        assert_eq!(module.functions[0].blocks.len(), 10);
+        let mut pm = PassManager::from_pipeline("simplify-cfg").unwrap();
+        pm.run(&mut module).unwrap();
+        assert!(!module.algos_run.contains(&Algo::CriticalEdgeSplitting));
+        let func = &module.functions[0];
+        assert_eq!(func.blocks.len(), original.blocks.len());
+        for (block, orig) in func.blocks.iter().zip(original.blocks.iter()) {
+            assert_eq!(block.preds, orig.preds);
+            assert_eq!(block.terminator, orig.terminator);
+            assert_eq!(block.instructions, orig.instructions);
+        }
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+    }
+
+    #[test]
+    fn inliner_respects_linkage_and_recursion() {
+        let src = "
+/* [@ssa_constructed] module inline */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = call $1()
+    %1: s32 = call $2()
+    %2: s32 = add %0 %1
+    %3: s32 = call $3()
+    %4: s32 = add %2 %3
+    ret %4
+}
+$1: private fn pick() s32 {
+$0: ; preds =
+    %0: s32 = 5
+    %1: s32 = 3
+    %2: s32 = lt %1 %0
+    br %2, $1, $2
+$1: ; preds = $0
+    ret %0
+$2: ; preds = $0
+    ret %1
+}
+$2: external fn ext() s32 {
+$0: ; preds =
+    %0: s32 = 4
+    ret %0
+}
+$3: private fn rec() s32 {
+$0: ; preds =
+    %0: s32 = 0
+    br %0, $1, $2
+$1: ; preds = $0
+    %1: s32 = call $3()
+    ret %1
+$2: ; preds = $0
+    %2: s32 = 7
+    ret %2
+}
+";
+        let calls = |func: &Function| -> Vec<FunctionId> {
+            func.blocks
+                .iter()
+                .flat_map(|bb| bb.instructions.iter())
+                .filter_map(|instr| match instr.operation {
+                    Operation::Call(callee, _) => Some(callee),
+                    _ => None,
+                })
+                .collect()
+        };
+        for pipeline in ["inline", "split-critical-edges,lower-phis,inline"] {
+            let mut module = parse_module(src).unwrap();
+            module.set_verify_transforms(true);
+            let expected = Interpreter::new(&module).run(FunctionId(0), &[]);
+            assert_eq!(expected, Ok(16));
+            let mut pm = PassManager::from_pipeline(pipeline).unwrap();
+            pm.run(&mut module).unwrap();
+
+            // `rec` is inlined once, but not into its own copy
+            let main = &module.functions[0];
+            assert_eq!(calls(main), vec![FunctionId(2), FunctionId(3)]);
+            assert_eq!(calls(&module.functions[3]), vec![FunctionId(3)]);
+            let phis = main
+                .blocks
+                .iter()
+                .flat_map(|bb| bb.instructions.iter())
+                .filter(|i| matches!(i.operation, Operation::Phi(_)))
+                .count();
+            let par_moves: usize = main.blocks.iter().map(|bb| bb.par_moves.len()).sum();
+            if pipeline == "inline" {
+                assert_eq!((phis, par_moves), (2, 0));
+            } else {
+                assert_eq!((phis, par_moves), (0, 4));
+            }
+            assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), expected);
+        }
+    }
+
+    #[test]
+    fn call_graph_and_dead_functions() {
+        let src = "
+/* [] module dfe */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = call $3()
+    ret %0
+}
+$1: private fn ping() s32 {
+$0: ; preds =
+    %0: s32 = call $2()
+    ret %0
+}
+$2: private fn pong() s32 {
+$0: ; preds =
+    %0: s32 = call $1()
+    %1: s32 = call $3()
+    ret %0
+}
+$3: private fn live() s32 {
+$0: ; preds =
+    %0: s32 = 9
+    ret %0
+}
+$4: external fn ext() s32 {
+$0: ; preds =
+    %0: s32 = call $3()
+    ret %0
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let graph = CallGraph::compute(&module);
+        let f =
+            |ids: &[usize]| -> Vec<FunctionId> { ids.iter().map(|id| FunctionId(*id)).collect() };
+        assert_eq!(graph.callees(FunctionId(2)), f(&[1, 3]));
+        assert_eq!(graph.callers(FunctionId(3)), f(&[0, 2, 4]));
+        assert_eq!(graph.scc(FunctionId(1)), f(&[1, 2]));
+        assert!(graph.is_recursive(FunctionId(2)));
+        assert!(!graph.is_recursive(FunctionId(0)));
+        // callees come first
+        let order: Vec<usize> = graph.sccs().iter().map(|scc| scc[0].0).collect();
+        let pos = |id: usize| order.iter().position(|f| *f == id).unwrap();
+        assert!(pos(3) < pos(0) && pos(3) < pos(1) && pos(3) < pos(4));
+
+        let mut pm = PassManager::from_pipeline("dfe").unwrap();
+        pm.run(&mut module).unwrap();
+        let names: Vec<&str> = module.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["main", "live", "ext"]);
+        for (id, func) in module.functions.iter().enumerate() {
+            assert_eq!(func.id, id);
+            assert_eq!(
+                func.blocks[0].instructions[0].operation,
+                if func.name == "live" {
+                    Operation::Integer(9)
+                } else {
+                    Operation::Call(FunctionId(1), vec![])
+                }
+            );
+        }
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(9));
+    }
+
+    #[test]
+    fn tail_calls() {
+        let src = "
+/* [@ssa_constructed] module tail */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = call $1()
+    ret %0
+}
+$1: private fn rec() s32 {
+$0: ; preds =
+    %0: s32 = 0
+    br %0, $1, $2
+$1: ; preds = $0
+    %1: s32 = call $1()
+    %2: s32 = 7
+    %3: s32 = add %1 %2
+    br %0, $3, $4
+$2: ; preds = $0
+    %4: s32 = 7
+    ret %4
+$3: ; preds = $1
+    %5: s32 = call $1()
+    ret %5
+$4: ; preds = $1
+    ret %3
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let mut pm = PassManager::from_pipeline("tailrec").unwrap();
+        pm.run(&mut module).unwrap();
+        let rec = &module.functions[1];
+        // the body moved to $5, and only the call in tail position became a jump
+        assert_eq!(rec.blocks[0].terminator, Terminator::Jump(BlockId(5)));
+        assert_eq!(rec.blocks[5].preds, vec![BlockId(0), BlockId(3)]);
+        assert_eq!(rec.blocks[3].terminator, Terminator::Jump(BlockId(5)));
+        assert!(rec.blocks[3].instructions.is_empty());
+        assert_eq!(rec.blocks[1].instructions.len(), 3);
+        assert_eq!(module.functions[0].blocks[0].instructions.len(), 1);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(7));
+
+        // main's call to `rec` is a sibling call in tail position
+        let options = VCodeOptions {
+            sibling_tail_calls: true,
+        };
+        let vcode = module.lower_to_vcode_with::<_, UrclSelector, LinearScanRegAlloc>(&options);
+        let main = &vcode.functions[0].instrs[0].instrs;
+        assert_eq!(main.len(), 1);
+        assert!(matches!(
+            main[0],
+            UrclInstr::Jmp {
+                dst: LabelDest::Function(1)
+            }
+        ));
+        // functions have their own labels, distinct from the blocks'
+        let text = vcode.to_string();
+        assert!(text.starts_with("main:\n.F0:\n  .L0:\n    jmp .F1\n"));
+        assert!(text.contains("\nrec:\n.F1:\n  .L0:\n"));
+        let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+        let main = &vcode.functions[0].instrs[0].instrs;
+        assert!(matches!(
+            main[0],
+            UrclInstr::Cal {
+                dst: LabelDest::Function(1)
+            }
+        ));
+        assert!(matches!(main.last(), Some(UrclInstr::Ret)));
+        assert!(vcode.to_string().contains("\n    cal .F1\n"));
+    }
+
+    #[test]
+    fn tail_calls_keep_frames() {
+        // every call gets a pointer to its caller's stack slot
+        let src = "
+/* [@ssa_constructed] module frames */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = 3
+    %1: s32 = 0
+    %2: s32 = call $2(%0, %1)
+    ret %2
+}
+$1: private fn sum(n: s32, prev: s32*) s32 {
+$0: ; preds =
+    %0: s32 = arg 0
+    %1: s32* = arg 1
+    %2: s32* = alloca s32
+    %3: s32 = load %1
+    %4: s32 = add %3 %0
+    store %2 %4
+    %5: s32 = 0
+    %6: s32 = eq %0 %5
+    br %6, $1, $2
+$1: ; preds = $0
+    ret %4
+$2: ; preds = $0
+    %7: s32 = 1
+    %8: s32 = sub %0 %7
+    %9: s32 = call $1(%8, %2)
+    ret %9
+}
+$2: private fn start(n: s32, init: s32) s32 {
+$0: ; preds =
+    %0: s32 = arg 0
+    %1: s32 = arg 1
+    %2: s32* = alloca s32
+    store %2 %1
+    %3: s32 = call $1(%0, %2)
+    ret %3
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(6));
+
+        // a loop would reuse the slot the next iteration reads through `prev`
+        let before = module.clone();
+        let mut pm = PassManager::from_pipeline("tailrec").unwrap();
+        pm.run(&mut module).unwrap();
+        assert_eq!(module.functions[1], before.functions[1]);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(6));
+
+        // a jump would free the slot before the callee reads it
+        let options = VCodeOptions {
+            sibling_tail_calls: true,
+        };
+        let vcode = module.lower_to_vcode_with::<_, UrclSelector, LinearScanRegAlloc>(&options);
+        for func in &vcode.functions[1..] {
+            let instrs = || func.instrs.iter().flat_map(|block| block.instrs.iter());
+            assert!(instrs().any(|instr| matches!(instr, UrclInstr::Cal { .. })));
+            assert!(!instrs().any(|instr| matches!(
+                instr,
+                UrclInstr::Jmp {
+                    dst: LabelDest::Function(_)
+                }
+            )));
+        }
+    }
+
+    #[test]
+    fn function_args() {
+        let ty = Type::Integer(32, true);
+        let mut builder = ModuleBuilder::new("args");
+        let args = vec![("a".to_string(), ty.clone()), ("b".to_string(), ty.clone())];
+        let sub = builder.push_function("sub", ty.clone(), args, Some(Linkage::Public));
+        builder.switch_to_fn(sub);
+        let entry = builder.push_block();
+        builder.switch_to_block(entry);
+        let b = builder.get_arg(1);
+        let a = builder.get_arg(0);
+        assert_eq!(builder.get_arg(1), b);
+        let diff = builder.build_binop(BinOp::Sub, a, b, ty.clone());
+        builder.set_terminator(Terminator::Return(diff));
+        let module = builder.build();
+        let text = module.to_string();
+        assert!(text.contains("%1: s32 = arg 0"));
+        assert_eq!(parse_module(&text).unwrap().to_string(), text);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[9, 4]), Ok(5));
+
+        let src = "
+/* [@ssa_constructed] module args */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = 10
+    %1: s32 = 0
+    %2: s32 = call $1(%0, %1)
+    %3: s32 = call $2(%2, %0)
+    ret %3
+}
+$1: private fn sum(n: s32, acc: s32) s32 {
+$0: ; preds =
+    %0: s32 = arg 0
+    %1: s32 = arg 1
+    %2: s32 = 0
+    %3: s32 = eq %0 %2
+    br %3, $1, $2
+$1: ; preds = $0
+    ret %1
+$2: ; preds = $0
+    %4: s32 = 1
+    %5: s32 = sub %0 %4
+    %6: s32 = add %1 %0
+    %7: s32 = call $1(%5, %6)
+    ret %7
+}
+$2: private fn minus(a: s32, b: s32) s32 {
+$0: ; preds =
+    %0: s32 = arg 0
+    %1: s32 = arg 1
+    %2: s32 = sub %0 %1
+    ret %2
+}
+";
+        let err =
+            parse_module(&src.replace("arg 1\n    %2: s32 = sub", "arg 2\n    %2: s32 = sub"));
+        assert!(err.unwrap_err().message.contains("no argument 2"));
+
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(45));
+
+        // the URCL calling convention passes args on the stack, above %0
+        // which is saved for the second call
+        let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+        let main = &vcode.functions[0].instrs[0].instrs;
+        assert!(matches!(
+            main[2..9],
+            [
+                UrclInstr::Psh { .. },
+                UrclInstr::Psh { .. },
+                UrclInstr::Psh { .. },
+                UrclInstr::Cal { .. },
+                UrclInstr::FreeStack { words: 2 },
+                UrclInstr::Mov { .. },
+                UrclInstr::Pop { .. },
+            ]
+        ));
+        let minus = &vcode.functions[2].instrs[0].instrs;
+        assert!(matches!(
+            minus[..2],
+            [
+                UrclInstr::Llod { offset: 1, .. },
+                UrclInstr::Llod { offset: 2, .. },
+            ]
+        ));
+
+        // the recursion becomes a loop with the args merged in the header
+        let mut pm = PassManager::from_pipeline("tailrec").unwrap();
+        pm.run(&mut module).unwrap();
+        let sum = &module.functions[1];
+        assert_eq!(sum.blocks[3].preds, vec![BlockId(0), BlockId(2)]);
+        assert!(sum.blocks[3].instructions[..2]
+            .iter()
+            .all(|instr| matches!(instr.operation, Operation::Phi(_))));
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(45));
+
+        // inlined args are replaced by the operands of the call
+        let mut pm = PassManager::from_pipeline("inline").unwrap();
+        pm.run(&mut module).unwrap();
+        let main = &module.functions[0];
+        assert!(!main
+            .blocks
+            .iter()
+            .flat_map(|bb| bb.instructions.iter())
+            .any(|instr| matches!(instr.operation, Operation::Arg(_) | Operation::Call(..))));
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(45));
+    }
+
+    #[test]
+    fn call_saves_live_values() {
+        let src = "
+/* [@ssa_constructed] module call */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32 = 5
+    %1: s32 = call $1()
+    %2: s32 = add %0 %1
+    ret %2
+}
+$1: private fn ten() s32 {
+$0: ; preds =
+    %0: s32 = 10
+    %1: s32 = 3
+    %2: s32 = add %0 %1
+    ret %2
+}
+";
+        let module = parse_module(src).unwrap();
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(18));
+
+        // `ten` overwrites r1, so %0 has to be restored after the call
+        let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+        let main: Vec<String> = vcode.functions[0].instrs[0]
+            .instrs
+            .iter()
+            .map(|instr| instr.to_string())
+            .collect();
+        assert_eq!(
+            main,
+            [
+                "imm r1 5",
+                "psh r1",
+                "cal .F1",
+                "mov r2 r1",
+                "pop r1",
+                "add r3 r1 r2",
+                "mov r1 r3",
+                "ret",
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_operations() {
+        let ty = Type::Integer(32, true);
+        let mut builder = ModuleBuilder::new("memory");
+        let main = builder.push_function("main", ty.clone(), vec![], Some(Linkage::Public));
+        builder.switch_to_fn(main);
+        let entry = builder.push_block();
+        builder.switch_to_block(entry);
+        let slot = builder.build_alloca(ty.clone());
+        let x = builder.push_variable("x", ty.clone());
+        let five = builder.build_integer(5, ty.clone());
+        builder.build_store_ptr(slot, five);
+        builder.build_store(x, five);
+        let zero = builder.build_integer(0, ty.clone());
+        let elem = builder.build_gep(slot, zero);
+        let loaded = builder.build_load_ptr(elem);
+        let var = builder.build_load(x);
+        let sum = builder.build_binop(BinOp::Add, loaded, var, ty.clone());
+        builder.set_terminator(Terminator::Return(sum));
+        let mut module = builder.build();
+
+        let text = module.to_string();
+        assert!(text.contains("%0: s32* = alloca s32"));
+        assert!(text.contains("store %0 %1"));
+        assert!(text.contains("%4: s32 = load %3"));
+        assert_eq!(parse_module(&text).unwrap().to_string(), text);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(10));
+
+        // SSA construction only removes the variable accesses
+        module.set_verify_transforms(true);
+        module.apply_mandatory_transforms();
+        let ops: Vec<&Operation> = module.functions[0].blocks[0]
+            .instructions
+            .iter()
+            .map(|instr| &instr.operation)
+            .collect();
+        assert!(matches!(
+            ops[..],
+            [
+                Operation::Alloca(_),
+                Operation::Integer(5),
+                Operation::Store(..),
+                Operation::Integer(0),
+                Operation::Gep(..),
+                Operation::Load(_),
+                Operation::BinOp(..),
+            ]
+        ));
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(10));
+
+        // the slot is addressed from the frame pointer
+        let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+        let main = &vcode.functions[0].instrs[0].instrs;
+        assert!(matches!(
+            main[..5],
+            [
+                UrclInstr::Psh {
+                    src: VReg::Real(URCL_REG_FP)
+                },
+                UrclInstr::SetFp,
+                UrclInstr::AllocStack { words: 1 },
+                UrclInstr::FrameAddr { offset: 1, .. },
+                UrclInstr::Imm { val: 5, .. },
+            ]
+        ));
+        assert!(matches!(
+            main[5],
+            UrclInstr::Lstr {
+                base: StackBase::Fp,
+                offset: -1,
+                ..
+            }
+        ));
+        assert!(main
+            .iter()
+            .any(|instr| matches!(instr, UrclInstr::Lod { .. })));
+        assert!(matches!(
+            main[main.len() - 3..],
+            [
+                UrclInstr::RestoreSp,
+                UrclInstr::Pop {
+                    dst: VReg::Real(URCL_REG_FP)
+                },
+                UrclInstr::Ret,
+            ]
+        ));
+
+        let src = "
+/* [] module bad */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32* = alloca s32
+    %1: s32 = 5
+    %2: s32* = gep %0 %1
+    %3: s32 = load %2
+    ret %3
+}
+";
+        let module = parse_module(src).unwrap();
+        assert!(matches!(
+            Interpreter::new(&module).run(FunctionId(0), &[]),
+            Err(InterpError::BadAddress { addr: 6, .. })
+        ));
+        let module = parse_module(&src.replace("load %2", "load %1")).unwrap();
+        let errors = verify(&module);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "%1 is not a pointer");
+    }
+
+    #[test]
+    fn inline_pointer_args() {
+        let src = "
+/* [@ssa_constructed] module bump */
+$0: public fn main() s32 {
+$0: ; preds =
+    %0: s32* = alloca s32
+    %1: s32 = 20
+    store %0 %1
+    %2: s32 = call $1(%0)
+    %3: s32 = load %0
+    %4: s32 = add %2 %3
+    ret %4
+}
+$1: private fn bump(p: s32*) s32 {
+$0: ; preds =
+    %0: s32* = arg 0
+    %1: s32 = 0
+    %2: s32* = gep %0 %1
+    %3: s32 = load %2
+    %4: s32 = 1
+    %5: s32 = add %3 %4
+    store %0 %5
+    %6: s32 = load %0
+    ret %6
+}
+";
+        let mut module = parse_module(src).unwrap();
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(42));
+        let mut pm = PassManager::from_pipeline("inline").unwrap();
+        pm.run(&mut module).unwrap();
+
+        // the gep, load and store of `bump` now use main's slot
+        assert!(module.functions[0]
+            .blocks
+            .iter()
+            .flat_map(|bb| bb.instructions.iter())
+            .all(|instr| !matches!(instr.operation, Operation::Arg(_) | Operation::Call(..))));
+        assert_eq!(verify(&module), vec![]);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(42));
+    }
+
+    #[test]
+    fn unary_cast_select() {
+        // x < 0 ? zext x : sext -x
+        let byte = Type::Integer(8, true);
+        let word = Type::Integer(32, true);
+        let mut builder = ModuleBuilder::new("casts");
+        let main = builder.push_function(
+            "main",
+            word.clone(),
+            vec![("x".to_string(), byte.clone())],
+            Some(Linkage::Public),
+        );
+        builder.switch_to_fn(main);
+        let entry = builder.push_block();
+        builder.switch_to_block(entry);
+        let x = builder.get_arg(0);
+        let neg = builder.build_unop(UnOp::Neg, x);
+        let wide = builder.build_cast(CastOp::SExt, neg, word.clone());
+        let zext = builder.build_cast(CastOp::ZExt, x, word.clone());
+        let zero = builder.build_integer(0, byte.clone());
+        let cond = builder.build_binop(BinOp::Lt, x, zero, byte.clone());
+        let res = builder.build_select(cond, zext, wide);
+        builder.set_terminator(Terminator::Return(res));
+        let module = builder.build();
+
+        let text = module.to_string();
+        assert!(text.contains("%1: s8 = neg %0"));
+        assert!(text.contains("%2: s32 = sext %1 s32"));
+        assert!(text.contains("%6: s32 = select %5 %3 %2"));
+        assert_eq!(parse_module(&text).unwrap().to_string(), text);
+        assert_eq!(verify(&module), vec![]);
+        for (x, expected) in [(-1, 255), (5, -5), (-128, 128), (0, 0)] {
+            assert_eq!(
+                Interpreter::new(&module).run(FunctionId(0), &[x]),
+                Ok(expected)
+            );
+        }
+        let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+        assert!(vcode.functions[0].instrs[0]
+            .instrs
+            .iter()
+            .any(|instr| matches!(
+                instr,
+                UrclInstr::UnOp {
+                    op: UrclAluOp::Neg,
+                    ..
+                }
+            )));
+
+        let src = "
+/* [] module fold */
+$0: public fn main() u8 {
+$0: ; preds =
+    %0: s8 = -1
+    %1: u32 = zext %0 u32
+    %2: u32 = not %1
+    %3: u8 = trunc %2 u8
+    %4: u8 = trunc %1 u8
+    %5: u8 = select %3 %3 %4
+    ret %5
+}
+";
+        let mut module = parse_module(src).unwrap();
+        ConstantFolding::default().run(&mut module);
+        assert!(module.functions[0].blocks[0]
+            .instructions
+            .iter()
+            .all(|instr| matches!(instr.operation, Operation::Integer(_))));
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(255));
+
+        let module = parse_module(&src.replace("trunc %1 u8", "trunc %0 s64")).unwrap();
+        let errors = verify(&module);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "cannot trunc s8 to s64");
+    }
+
+    #[test]
+    fn casts_wrap_narrow_overflow() {
+        let src = "
+/* [@ssa_constructed] module overflow */
+$0: public fn main() u32 {
+$0: ; preds =
+    %0: u8 = 200
+    %1: u8 = 100
+    %2: u8 = add %0 %1
+    %3: u32 = zext %2 u32
+    ret %3
+}
+";
+        let module = parse_module(src).unwrap();
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(44));
+
+        // the add leaves 300 in its register, which the zext has to mask
+        let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+        let main: Vec<String> = vcode.functions[0].instrs[0]
+            .instrs
+            .iter()
+            .map(|instr| instr.to_string())
+            .collect();
+        assert_eq!(
+            main,
+            [
+                "imm r1 200",
+                "imm r2 100",
+                "add r2 r1 r2",
+                "imm r1 255",
+                "and r1 r2 r1",
+                "mov r1 r1",
+                "ret",
+            ]
+        );
+    }
+
+    #[test]
+    fn passes_rewrite_unop_cast_select_users() {
+        let src = "
+/* [@ssa_constructed] module users */
+$0: public fn main(x: s8) s32 {
+$0: ; preds =
+    %0: s8 = arg 0
+    %1: s8 = 0
+    %2: s8 = 0
+    %3: s8 = add %0 %2
+    %4: s8 = neg %3
+    %5: s32 = zext %2 s32
+    %6: s32 = sext %3 s32
+    %7: s8 = select %2 %3 %4
+    %8: s32 = sext %7 s32
+    %9: s32 = add %5 %6
+    %10: s32 = sub %9 %8
+    ret %10
+}
+";
+        // gvn replaces %2 with %1, instcombine %3 with %0
+        for pipeline in ["gvn", "instcombine"] {
+            let mut module = parse_module(src).unwrap();
+            let mut pm = PassManager::from_pipeline(pipeline).unwrap();
+            pm.run(&mut module).unwrap();
+            assert_eq!(verify(&module), vec![], "after {}", pipeline);
+            for (x, expected) in [(5, 10), (-3, -6)] {
+                assert_eq!(
+                    Interpreter::new(&module).run(FunctionId(0), &[x]),
+                    Ok(expected)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn signedness_from_operand_type() {
+        let src = "
+/* [] module signs */
+$0: public fn main(a: u32, b: u32) u32 {
+$0: ; preds =
+    %0: u32 = arg 0
+    %1: u32 = arg 1
+    %2: u32 = lt %0 %1
+    %3: u32 = div %0 %1
+    %4: u32 = mod %0 %1
+    %5: u32 = shr %0 %1
+    %6: u32 = eq %0 %1
+    ret %2
+}
+";
+        let alu_ops = |src: &str| {
+            let module = parse_module(src).unwrap();
+            let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+            vcode.functions[0].instrs[0]
+                .instrs
+                .iter()
+                .filter_map(|instr| match instr {
+                    UrclInstr::AluOp { op, .. } => Some(op.to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(alu_ops(src), ["setl", "div", "mod", "rsh", "sete"]);
+        // signed remainder is computed from the quotient
+        assert_eq!(
+            alu_ops(&src.replace("u32", "s32")),
+            ["ssetl", "sdiv", "sdiv", "mul", "sub", "srs", "sete"]
+        );
+
+        // -1 is the largest u32
+        let module = parse_module(src).unwrap();
+        assert_eq!(
+            Interpreter::new(&module).run(FunctionId(0), &[-1, 1]),
+            Ok(0)
+        );
+        let module = parse_module(&src.replace("u32", "s32")).unwrap();
+        assert_eq!(
+            Interpreter::new(&module).run(FunctionId(0), &[-1, 1]),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn globals() {
+        let ty = Type::Integer(32, true);
+        let mut builder = ModuleBuilder::new("globals");
+        let msg = builder.push_string("msg", "hi");
+        let counter = builder.push_global(
+            "counter",
+            ty.clone(),
+            Some(Linkage::Public),
+            true,
+            Some(vec![5]),
+        );
+        let table = builder.push_global("table", ty.clone(), None, false, Some(vec![10, 20, 30]));
+        let main = builder.push_function("main", ty.clone(), vec![], Some(Linkage::Public));
+        builder.switch_to_fn(main);
+        let entry = builder.push_block();
+        builder.switch_to_block(entry);
+        // ++counter + table[2] + msg[0]
+        let ptr = builder.build_global_addr(counter);
+        let old = builder.build_load_ptr(ptr);
+        let one = builder.build_integer(1, ty.clone());
+        let new = builder.build_binop(BinOp::Add, old, one, ty.clone());
+        builder.build_store_ptr(ptr, new);
+        let base = builder.build_global_addr(table);
+        let two = builder.build_integer(2, ty.clone());
+        let elem = builder.build_gep(base, two);
+        let entry_val = builder.build_load_ptr(elem);
+        let text_ptr = builder.build_global_addr(msg);
+        let byte = builder.build_load_ptr(text_ptr);
+        let wide = builder.build_cast(CastOp::ZExt, byte, ty.clone());
+        let sum = builder.build_binop(BinOp::Add, new, entry_val, ty.clone());
+        let sum = builder.build_binop(BinOp::Add, sum, wide, ty.clone());
+        builder.set_terminator(Terminator::Return(sum));
+        let mut module = builder.build();
+
+        let text = module.to_string();
+        assert!(text.contains("@0: private const msg: u8 = [104, 105, 0]"));
+        assert!(text.contains("@1: public global counter: s32 = [5]"));
+        assert!(text.contains("%0: s32* = addr @1"));
+        assert_eq!(parse_module(&text).unwrap().to_string(), text);
+        assert_eq!(verify(&module), vec![]);
+        // globals keep their values between runs
+        let mut interp = Interpreter::new(&module);
+        assert_eq!(interp.run(FunctionId(0), &[]), Ok(140));
+        assert_eq!(interp.run(FunctionId(0), &[]), Ok(141));
+
+        module.set_verify_transforms(true);
+        module.apply_mandatory_transforms();
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[]), Ok(140));
+        let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+        assert_eq!(vcode.data.len(), 3);
+        assert!(vcode.to_string().contains(".G2:\n    dw [10 20 30]\n"));
+        assert!(vcode.functions[0].instrs[0]
+            .instrs
+            .iter()
+            .any(|instr| matches!(
+                instr,
+                UrclInstr::ImmLabel {
+                    label: LabelDest::Global(1),
+                    ..
+                }
+            )));
+
+        // constants can't be stored to
+        let module = parse_module(&text.replace("addr @1", "addr @2")).unwrap();
+        let errors = verify(&module);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "store to constant `table`");
+        assert!(matches!(
+            Interpreter::new(&module).run(FunctionId(0), &[]),
+            Err(InterpError::ConstantWrite { addr: 5, .. })
+        ));
+        let err = parse_module(&text.replace("addr @1", "addr @3")).unwrap_err();
+        assert_eq!(err.message, "undefined global @3");
+    }
+
+    #[test]
+    fn par_move_lowering() {
+        // swaps a and b n times, then returns a - b
+        let src = "
+/* [@ssa_constructed] module swap */
+$0: public fn main(n: s32) s32 {
+$0: ; preds =
+    %0: s32 = arg 0
+    %1: s32 = 1
+    %2: s32 = 2
+    %3: s32 = 0
+    jmp $1
+$1: ; preds = $0, $2
+    %4: s32 = Φ %1, %5
+    %5: s32 = Φ %2, %4
+    %6: s32 = Φ %3, %9
+    %7: s32 = lt %6 %0
+    br %7, $2, $3
+$2: ; preds = $1
+    %8: s32 = 1
+    %9: s32 = add %6 %8
+    jmp $1
+$3: ; preds = $1
+    %10: s32 = sub %4 %5
+    ret %10
+}
+";
+        let mut module = parse_module(src).unwrap();
+        module.set_verify_transforms(true);
+        let mut pm = PassManager::from_pipeline("split-critical-edges,lower-phis").unwrap();
+        pm.run(&mut module).unwrap();
+        let with_par_moves = module.clone();
+        let mut pm = PassManager::from_pipeline("lower-par-moves").unwrap();
+        pm.run(&mut module).unwrap();
+
+        let main = &module.functions[0];
+        assert!(main.blocks.iter().all(|bb| bb.par_moves.is_empty()));
+        // the counter goes first, and the swap needs a temporary
+        let tmp = ValueId(11);
+        let copies: Vec<(ValueId, ValueId)> = main.blocks[2]
+            .instructions
+            .iter()
+            .filter_map(|instr| match instr.operation {
+                Operation::Copy(src) => Some((instr.yielded.unwrap(), src)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            copies,
+            vec![
+                (ValueId(6), ValueId(9)),
+                (tmp, ValueId(4)),
+                (ValueId(4), ValueId(5)),
+                (ValueId(5), tmp),
+            ]
+        );
+        for (n, expected) in [(3, 1), (4, -1)] {
+            assert_eq!(
+                Interpreter::new(&module).run(FunctionId(0), &[n]),
+                Ok(expected)
+            );
+        }
+
+        let text = module.to_string();
+        assert!(text.contains("@par_moves_lowered"));
+        let parsed = parse_module(&text).unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(verify(&parsed), vec![]);
+
+        // lowering to vcode sequentializes leftover par-moves the same way
+        let movs = |module: &Module| {
+            let vcode = module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+            vcode.functions[0].instrs[2]
+                .instrs
+                .iter()
+                .filter(|instr| matches!(instr, UrclInstr::Mov { .. }))
+                .count()
+        };
+        assert_eq!(movs(&with_par_moves), 4);
+        assert_eq!(movs(&module), 4);
+    }
+
+    #[test]
+    fn passes_after_par_move_lowering() {
+        // counts to n through `inc`, whose result reaches the Φ by a copy
+        let src = "
+/* [@ssa_constructed] module copies */
+$0: public fn main(n: s32) s32 {
+$0: ; preds =
+    %0: s32 = arg 0
+    %1: s32 = 0
+    jmp $1
+$1: ; preds = $0, $3
+    %2: s32 = Φ %1, %5
+    %3: s32 = lt %2 %0
+    br %3, $2, $4
+$2: ; preds = $1
+    %4: s32 = 1
+    jmp $3
+$3: ; preds = $2
+    %5: s32 = call $1(%2, %4)
+    jmp $1
+$4: ; preds = $1
+    ret %2
+}
+$1: private fn inc(a: s32, b: s32) s32 {
+$0: ; preds =
+    %0: s32 = arg 0
+    %1: s32 = arg 1
+    %2: s32 = add %0 %1
+    ret %2
+}
+";
+        let lowered = "split-critical-edges,lower-phis,lower-par-moves";
+        for pipeline in ["simplify-cfg", "simplify-cfg,inline", "inline,simplify-cfg"] {
+            let mut module = parse_module(src).unwrap();
+            let mut pm = PassManager::from_pipeline(&format!("{},{}", lowered, pipeline)).unwrap();
+            pm.run(&mut module).unwrap();
+            assert_eq!(verify(&module), vec![], "after {}", pipeline);
+            assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[3]), Ok(3));
+        }
+    }
+
+    #[test]
+    fn out_of_ssa_coalescing() {
+        // i and s start from the same zero; s = s + i; i = i + 1 while i < n
+        let sum = "
+/* [@edges_splitted, @ssa_constructed] module sum */
+$0: public fn main(n: s32) s32 {
+$0: ; preds =
+    %0: s32 = arg 0
+    %1: s32 = 0
+    jmp $1
+$1: ; preds = $0, $2
+    %2: s32 = Φ %1, %6
+    %3: s32 = Φ %1, %7
+    %4: s32 = lt %2 %0
+    br %4, $2, $3
+$2: ; preds = $1
+    %5: s32 = 1
+    %7: s32 = add %3 %2
+    %6: s32 = add %2 %5
+    jmp $1
+$3: ; preds = $1
+    ret %3
+}
+";
+        let mut module = parse_module(sum).unwrap();
+        let mut pass = OutOfSsa::default();
+        pass.run(&mut module);
+        let stats = &pass.stats()[0];
+        assert_eq!((stats.phi_operands, stats.copies), (4, 1));
+        assert_eq!(stats.eliminated(), 3);
+        // i shares the zero, but s can't as both are live in the header
+        let main = &module.functions[0];
+        assert_eq!(main.blocks[0].par_moves, vec![(ValueId(3), ValueId(1))]);
+        assert!(main.blocks[2].par_moves.is_empty());
+        assert!(main.blocks[1]
+            .instructions
+            .iter()
+            .all(|instr| !matches!(instr.operation, Operation::Phi(_))));
+        assert_eq!(verify(&module), vec![]);
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[5]), Ok(10));
+        module.lower_to_vcode::<_, UrclSelector, LinearScanRegAlloc>();
+
+        // the zero is now defined by the `sub`, the loop's `add`s and a
+        // par-move, and DCE has to keep the operands of all of them
+        let mut module = parse_module(&sum.replace(
+            "    %1: s32 = 0\n",
+            "    %8: s32 = 2\n    %1: s32 = sub %8 %8\n",
+        ))
+        .unwrap();
+        module.set_verify_transforms(true);
+        let mut pm = PassManager::from_pipeline("out-of-ssa,dce").unwrap();
+        pm.run(&mut module).unwrap();
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[5]), Ok(10));
+        // passes relying on a single definition per value refuse to run
+        for pass in ["constfold", "gvn", "instcombine", "licm"] {
+            let mut pm = PassManager::from_pipeline(pass).unwrap();
+            assert_eq!(
+                pm.run(&mut module).err(),
+                Some(PassError::ForbiddenAlgo {
+                    pass,
+                    forbids: Algo::PhiRemoval
+                })
+            );
+        }
+
+        // a swap keeps its copies, the counter doesn't need any
+        let mut module = parse_module(
+            "
+/* [@edges_splitted, @ssa_constructed] module swap */
+$0: public fn main(n: s32) s32 {
+$0: ; preds =
+    %0: s32 = arg 0
+    %1: s32 = 1
+    %2: s32 = 2
+    %3: s32 = 0
+    jmp $1
+$1: ; preds = $0, $2
+    %4: s32 = Φ %1, %5
+    %5: s32 = Φ %2, %4
+    %6: s32 = Φ %3, %9
+    %7: s32 = lt %6 %0
+    br %7, $2, $3
+$2: ; preds = $1
+    %8: s32 = 1
+    %9: s32 = add %6 %8
+    jmp $1
+$3: ; preds = $1
+    %10: s32 = sub %4 %5
+    ret %10
+}
+",
+        )
+        .unwrap();
+        let with_phis = module.clone();
+        let stats = out_of_ssa(&mut module);
+        assert_eq!((stats[0].phi_operands, stats[0].eliminated()), (6, 4));
+        assert_eq!(
+            module.functions[0].blocks[2].par_moves,
+            vec![(ValueId(1), ValueId(2)), (ValueId(2), ValueId(1))]
+        );
+        assert_eq!(verify(&module), vec![]);
+        for (n, expected) in [(3, 1), (4, -1)] {
+            assert_eq!(
+                Interpreter::new(&module).run(FunctionId(0), &[n]),
+                Ok(expected)
+            );
+        }
+        let mut module = with_phis;
+        module.set_verify_transforms(true);
+        let mut pm = PassManager::from_pipeline("out-of-ssa,lower-par-moves").unwrap();
+        pm.run(&mut module).unwrap();
+        assert_eq!(Interpreter::new(&module).run(FunctionId(0), &[3]), Ok(1));
+    }
+
+    #[test]
+    fn builder_ssa_mode() {
+        // s = 0; i = 0; while i < n { s = s + i; i = i + 1 }; return s
+        let ty = Type::Integer(32, true);
+        let mut builder = ModuleBuilder::new("braun");
+        let main = builder.push_function("main", ty.clone(), vec![], Some(Linkage::Public));
+        builder.switch_to_fn(main);
+        let entry = builder.push_block();
+        let header = builder.push_block();
+        let body = builder.push_block();
+        let exit = builder.push_block();
+        let n = builder.push_variable("n", ty.clone());
+        let i = builder.push_variable("i", ty.clone());
+        let s = builder.push_variable("s", ty.clone());
+
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        let zero = builder.build_integer(0, ty.clone());
+        let ten = builder.build_integer(10, ty.clone());
+        builder.write_variable(n, ten);
+        builder.write_variable(i, zero);
+        builder.write_variable(s, zero);
+        builder.set_terminator(Terminator::Jump(header));
+
+        // the header is sealed only once the back edge exists
+        builder.switch_to_block(header);
+        let cur_i = builder.read_variable(i);
+        let cur_n = builder.read_variable(n);
+        let cond = builder.build_binop(BinOp::Lt, cur_i, cur_n, ty.clone());
+        builder.set_terminator(Terminator::Branch(cond, body, exit));
+
+        builder.switch_to_block(body);
+        builder.seal_block(body);
+        let cur_s = builder.read_variable(s);
+        let cur_i = builder.read_variable(i);
+        let sum = builder.build_binop(BinOp::Add, cur_s, cur_i, ty.clone());
+        builder.write_variable(s, sum);
+        let one = builder.build_integer(1, ty.clone());
+        let next = builder.build_binop(BinOp::Add, cur_i, one, ty.clone());
+        builder.write_variable(i, next);
+        builder.set_terminator(Terminator::Jump(header));
+        builder.seal_block(header);
+
+        builder.switch_to_block(exit);
+        builder.seal_block(exit);
+        let res = builder.read_variable(s);
+        builder.set_terminator(Terminator::Return(res));
+
+        let mut module = builder.build();
+        assert_eq!(verify(&module), vec![], "{}", module);
+        let phis = module.functions[0]
+            .blocks
+            .iter()
+            .flat_map(|bb| bb.instructions.iter())
+            .filter(|instr| matches!(instr.operation, Operation::Phi(_)))
+            .count();
+        // `n` is never reassigned, so its Φ in the header is trivial
+        assert_eq!(phis, 2, "{}", module);
+        assert_eq!(Interpreter::new(&module).run(main, &[]), Ok(45));
+
+        module.set_verify_transforms(true);
+        module.apply_mandatory_transforms();
+        assert_eq!(Interpreter::new(&module).run(main, &[]), Ok(45));
+    }
+
     #[test]
     fn test_var_renaming() {
         let mut builder = ModuleBuilder::new("test");
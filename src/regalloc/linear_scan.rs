@@ -4,15 +4,21 @@ use crate::vcode::VCodeInstr;
 
 use super::{Regalloc, VReg};
 
+/// How much more a use one loop deeper counts towards the spill weight.
+const LOOP_WEIGHT: usize = 10;
+
 #[derive(Default)]
 pub struct LinearScanRegAlloc {
     registers: Vec<RegAllocReg>,
     live_count: usize,
+    loop_depth: usize,
 }
 
 pub struct RegAllocReg {
     live_range: Range<usize>,
     uses: usize,
+    /// Defs and uses, weighted by `LOOP_WEIGHT` to the power of their loop depth
+    spill_weight: usize,
     reg: VReg,
     try_to_coalesce_to: Option<VReg>,
 }
@@ -22,12 +28,15 @@ impl Regalloc for LinearScanRegAlloc {
         if !matches!(reg, VReg::Virtual(_)) {
             return;
         }
+        let weight = self.weight();
         if let Some(reg) = self.registers.iter_mut().find(|e| e.reg == reg) {
             reg.live_range.end = self.live_count;
+            reg.spill_weight += weight;
         } else {
             self.registers.push(RegAllocReg {
                 live_range: self.live_count..self.live_count,
                 uses: 0,
+                spill_weight: weight,
                 reg,
                 try_to_coalesce_to: None,
             });
@@ -37,13 +46,16 @@ impl Regalloc for LinearScanRegAlloc {
         if !matches!(reg, VReg::Virtual(_)) {
             return;
         }
+        let weight = self.weight();
         if let Some(reg) = self.registers.iter_mut().find(|e| e.reg == reg) {
             reg.uses += 1;
+            reg.spill_weight += weight;
             reg.live_range.end = self.live_count;
         } else {
             self.registers.push(RegAllocReg {
                 live_range: self.live_count..self.live_count,
                 uses: 1,
+                spill_weight: weight,
                 reg,
                 try_to_coalesce_to: None,
             });
@@ -60,24 +72,71 @@ impl Regalloc for LinearScanRegAlloc {
             reg.try_to_coalesce_to = Some(to);
         }
     }
+    fn extend_live_range(&mut self, reg: VReg, from: usize, to: usize) {
+        if !matches!(reg, VReg::Virtual(_)) {
+            return;
+        }
+        if let Some(reg) = self.find_reg(reg) {
+            reg.live_range.start = reg.live_range.start.min(from);
+            reg.live_range.end = reg.live_range.end.max(to);
+        } else {
+            self.registers.push(RegAllocReg {
+                live_range: from..to,
+                uses: 0,
+                spill_weight: 0,
+                reg,
+                try_to_coalesce_to: None,
+            });
+        }
+    }
+    fn set_loop_depth(&mut self, depth: usize) {
+        self.loop_depth = depth;
+    }
     fn alloc_regs<I: VCodeInstr>(&self) -> HashMap<VReg, VReg> {
         let mut ret = HashMap::new();
         let mut reg_stack = I::get_usable_regs().to_vec();
         reg_stack.reverse();
+        // registers currently holding a real register
+        let mut active: Vec<usize> = Vec::new();
         let mut spill_counter = 0;
         for i in 0..self.live_count {
-            for reg in &self.registers {
+            for (idx, reg) in self.registers.iter().enumerate() {
                 if reg.live_range.start == i {
                     if let Some(to) = reg_stack.pop() {
                         ret.insert(reg.reg, to);
+                        active.push(idx);
                     } else {
                         spill_counter += 1;
-                        ret.insert(reg.reg, VReg::Spilled(spill_counter));
+                        // spill whichever is cheaper: this register or the
+                        // lightest one holding a real register
+                        let victim = active
+                            .iter()
+                            .enumerate()
+                            .min_by_key(|(_, r)| self.registers[**r].spill_weight)
+                            .filter(|(_, r)| self.registers[**r].spill_weight < reg.spill_weight)
+                            .map(|(pos, r)| (pos, *r));
+                        match victim {
+                            Some((pos, victim)) => {
+                                active.swap_remove(pos);
+                                let to = ret
+                                    .insert(
+                                        self.registers[victim].reg,
+                                        VReg::Spilled(spill_counter),
+                                    )
+                                    .unwrap();
+                                ret.insert(reg.reg, to);
+                                active.push(idx);
+                            }
+                            None => {
+                                ret.insert(reg.reg, VReg::Spilled(spill_counter));
+                            }
+                        }
                     }
                 }
                 if reg.live_range.end == i {
-                    if let Some(VReg::Real(reg)) = ret.get(&reg.reg) {
-                        reg_stack.push(VReg::Real(*reg));
+                    if let Some(pos) = active.iter().position(|r| *r == idx) {
+                        active.swap_remove(pos);
+                        reg_stack.push(ret[&reg.reg]);
                     }
                 }
             }
@@ -87,6 +146,9 @@ impl Regalloc for LinearScanRegAlloc {
 }
 
 impl LinearScanRegAlloc {
+    fn weight(&self) -> usize {
+        LOOP_WEIGHT.saturating_pow(self.loop_depth as u32)
+    }
     fn find_reg(&mut self, reg: VReg) -> Option<&mut RegAllocReg> {
         self.registers.iter_mut().find(|e| e.reg == reg)
     }
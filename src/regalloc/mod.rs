@@ -26,6 +26,13 @@ pub trait Regalloc {
     fn add_use(&mut self, reg: VReg);
     fn next_instr(&mut self);
     fn coalesce_move(&mut self, from: VReg, to: VReg);
+    /// Marks `reg` as live across every instruction from `from` to `to`
+    /// (inclusive), e.g. because it is live around a loop back-edge. Does
+    /// nothing by default, leaving the range to the defs and uses.
+    fn extend_live_range(&mut self, _reg: VReg, _from: usize, _to: usize) {}
+    /// Sets the loop nesting depth of the instructions that follow, so uses
+    /// inside loops can be weighted when picking what to spill.
+    fn set_loop_depth(&mut self, _depth: usize) {}
     fn alloc_regs<I: VCodeInstr>(&self) -> HashMap<VReg, VReg>;
 }
 
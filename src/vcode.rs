@@ -1,7 +1,7 @@
 use std::{collections::HashMap, fmt::Display};
 
 use crate::{
-    ir::{Instruction, Linkage, Terminator},
+    ir::{Function, Instruction, Linkage, Terminator},
     regalloc::{Regalloc, VReg},
 };
 
@@ -9,6 +9,22 @@ pub trait InstrSelector {
     type Instr: VCodeInstr;
     fn select(&mut self, gen: &mut VCodeGenerator<Self::Instr>, instr: &Instruction);
     fn select_terminator(&mut self, gen: &mut VCodeGenerator<Self::Instr>, term: &Terminator);
+    /// Selects a call whose result is returned straight away, so the callee
+    /// can return to the current function's caller itself. Only asked for
+    /// when `VCodeOptions::sibling_tail_calls` is set; returns whether it
+    /// did, otherwise the call and return are selected as usual.
+    fn select_tail_call(
+        &mut self,
+        _gen: &mut VCodeGenerator<Self::Instr>,
+        _call: &Instruction,
+    ) -> bool {
+        false
+    }
+    /// Called at the start of the entry block of every function, before any
+    /// of its instructions are selected, e.g. to look at the whole function.
+    fn begin_function(&mut self, _gen: &mut VCodeGenerator<Self::Instr>, _func: &Function) {}
+    /// Called at the start of the entry block of every function, after
+    /// `begin_function`.
     fn get_pre_function_instructions(&mut self, gen: &mut VCodeGenerator<Self::Instr>);
     fn get_post_function_instructions(&mut self, gen: &mut VCodeGenerator<Self::Instr>);
 }
@@ -17,6 +33,22 @@ pub trait VCodeInstr {
     fn get_usable_regs() -> &'static [VReg];
     fn collect_registers(&self, regalloc: &mut impl Regalloc);
     fn apply_allocs(&mut self, allocs: &HashMap<VReg, VReg>);
+    /// The blocks of the current function this instruction may jump to.
+    fn jump_targets(&self) -> Vec<usize> {
+        vec![]
+    }
+    /// Whether execution may continue with the next instruction, i.e. this
+    /// is not an unconditional jump or a return.
+    fn falls_through(&self) -> bool {
+        true
+    }
+}
+
+/// Options for `Module::lower_to_vcode_with`.
+#[derive(Debug, Clone, Default)]
+pub struct VCodeOptions {
+    /// Let the selector turn calls in tail position into jumps.
+    pub sibling_tail_calls: bool,
 }
 
 pub struct VCodeFunction<I: VCodeInstr> {
@@ -35,10 +67,22 @@ pub enum LabelDest {
     Function(usize),
     // usize: index of the block in the function
     Block(usize),
+    // usize: index of the global in the module
+    Global(usize),
+}
+
+/// The initial words of a global defined by the module.
+pub struct VCodeData {
+    pub name: String,
+    /// Index of the global in the module
+    pub id: usize,
+    pub linkage: Linkage,
+    pub words: Vec<i64>,
 }
 
 pub struct VCode<I: VCodeInstr> {
     pub functions: Vec<VCodeFunction<I>>,
+    pub data: Vec<VCodeData>,
 }
 
 pub struct VCodeGenerator<I: VCodeInstr> {
@@ -57,7 +101,10 @@ impl<I: VCodeInstr> Default for VCodeGenerator<I> {
 impl<I: VCodeInstr> VCodeGenerator<I> {
     pub fn new() -> VCodeGenerator<I> {
         VCodeGenerator {
-            vcode: VCode { functions: vec![] },
+            vcode: VCode {
+                functions: vec![],
+                data: vec![],
+            },
             current_func: None,
             current_block: None,
             vreg_count: 0,
@@ -97,6 +144,19 @@ impl<I: VCodeInstr> VCodeGenerator<I> {
         });
         self.vcode.functions.len() - 1
     }
+    /// Adds the initial words of the global at index `id` of the module.
+    pub fn push_data(&mut self, name: &str, id: usize, linkage: Linkage, words: Vec<i64>) {
+        self.vcode.data.push(VCodeData {
+            name: name.to_string(),
+            id,
+            linkage,
+            words,
+        });
+    }
+    /// The number of args of the function being generated.
+    pub fn arg_count(&self) -> usize {
+        self.vcode.functions[self.current_func.unwrap()].arg_count
+    }
     pub fn switch_to_func(&mut self, id: usize) {
         self.current_func = Some(id);
     }
@@ -110,8 +170,9 @@ impl<I: VCodeInstr> VCodeGenerator<I> {
 
 impl<I: Display + VCodeInstr> Display for VCode<I> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for func in self.functions.iter() {
+        for (id, func) in self.functions.iter().enumerate() {
             writeln!(f, "{}:", func.name)?;
+            writeln!(f, "{}:", LabelDest::Function(id))?;
             for (i, instrs) in func.instrs.iter().enumerate() {
                 writeln!(f, "  .L{}:", i)?;
                 for instr in instrs.instrs.iter() {
@@ -119,6 +180,18 @@ impl<I: Display + VCodeInstr> Display for VCode<I> {
                 }
             }
         }
+        for data in self.data.iter() {
+            writeln!(f, "{}:", LabelDest::Global(data.id))?;
+            writeln!(
+                f,
+                "    dw [{}]",
+                data.words
+                    .iter()
+                    .map(|word| word.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            )?;
+        }
         Ok(())
     }
 }
@@ -135,8 +208,9 @@ impl<I: Display + VCodeInstr> Display for LabelledInstructions<I> {
 impl Display for LabelDest {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LabelDest::Function(id) => write!(f, ".L{}", id),
+            LabelDest::Function(id) => write!(f, ".F{}", id),
             LabelDest::Block(id) => write!(f, ".L{}", id),
+            LabelDest::Global(id) => write!(f, ".G{}", id),
         }
     }
 }